@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "archives")]
+use std::io::Read;
+use std::path::Path;
+
+// Suffixes of the archives read with --expand-archives
+const ARCHIVE_SUFFIXES: &[&str] = &[".zip", ".tar.gz", ".tgz"];
+
+// Top-level entries listed before the rest are only counted
+const MAX_LISTED_ENTRIES: usize = 50;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub size: u64,
+}
+
+/// What was read from an archive: every file in it, and the text of as
+/// many of them as the extraction cap allowed.
+#[derive(Debug, Default)]
+pub struct ArchiveContents {
+    /// Files in archive order; directories are left out
+    pub entries: Vec<ArchiveEntry>,
+    /// Path and content of the text entries that were extracted
+    pub texts: Vec<(String, String)>,
+}
+
+impl ArchiveContents {
+    /// Content to summarize: the file count, the top-level structure and
+    /// as many text entries as fit in `max_len` bytes.
+    pub fn describe(&self, max_len: usize) -> String {
+        // Top-level name -> files under it (0 for a file at the top level)
+        let mut top_level: BTreeMap<&str, usize> = BTreeMap::new();
+        for entry in &self.entries {
+            match entry.path.split_once('/') {
+                Some((dir, _)) => *top_level.entry(dir).or_default() += 1,
+                None => {
+                    top_level.entry(&entry.path).or_default();
+                }
+            }
+        }
+
+        let mut description = format!("An archive containing {} files:\n", self.entries.len());
+        for (name, files) in top_level.iter().take(MAX_LISTED_ENTRIES) {
+            match files {
+                0 => description.push_str(&format!("- {}\n", name)),
+                files => description.push_str(&format!("- {}/ ({} files)\n", name, files)),
+            }
+        }
+        if top_level.len() > MAX_LISTED_ENTRIES {
+            description.push_str(&format!("- ... and {} more\n", top_level.len() - MAX_LISTED_ENTRIES));
+        }
+
+        for (path, text) in &self.texts {
+            let section = format!("\n--- {} ---\n{}\n", path, text);
+            if description.len() + section.len() > max_len {
+                break;
+            }
+            description.push_str(&section);
+        }
+        description
+    }
+}
+
+/// Whether `path` names a zip or gzipped tar archive.
+pub fn is_archive(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .map(|name| ARCHIVE_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)))
+        .unwrap_or(false)
+}
+
+/// List the archive at `path` and extract its text entries, stopping once
+/// `max_extracted` bytes have been decompressed so that a zip bomb can't
+/// exhaust memory.
+#[cfg(feature = "archives")]
+pub fn read(path: &Path, max_extracted: u64) -> anyhow::Result<ArchiveContents> {
+    use std::fs::File;
+    use anyhow::Context;
+
+    let file = File::open(path)?;
+    let mut extractor = Extractor { contents: ArchiveContents::default(), remaining: max_extracted };
+    let name = path.to_string_lossy().to_lowercase();
+
+    if name.ends_with(".zip") {
+        let mut archive = zip::ZipArchive::new(file)
+            .with_context(|| format!("Failed to read zip archive {}", path.display()))?;
+        for index in 0..archive.len() {
+            let entry = archive.by_index(index)?;
+            if !entry.is_dir() {
+                let (name, size) = (entry.name().to_string(), entry.size());
+                extractor.add(name, size, entry)?;
+            }
+        }
+    } else {
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        for entry in archive.entries()
+            .with_context(|| format!("Failed to read tar archive {}", path.display()))?
+        {
+            let entry = entry?;
+            if entry.header().entry_type().is_file() {
+                let name = entry.path()?.to_string_lossy().trim_start_matches("./").to_string();
+                let size = entry.header().size()?;
+                extractor.add(name, size, entry)?;
+            }
+        }
+    }
+    Ok(extractor.contents)
+}
+
+#[cfg(feature = "archives")]
+struct Extractor {
+    contents: ArchiveContents,
+    /// Bytes that may still be decompressed
+    remaining: u64,
+}
+
+#[cfg(feature = "archives")]
+impl Extractor {
+    fn add(&mut self, path: String, size: u64, reader: impl Read) -> anyhow::Result<()> {
+        // Declared sizes can lie, so the read itself is capped as well
+        if size <= self.remaining {
+            let mut bytes = Vec::new();
+            reader.take(self.remaining).read_to_end(&mut bytes)?;
+            self.remaining -= bytes.len() as u64;
+            if let Ok(text) = String::from_utf8(bytes) {
+                if !text.contains('\0') {
+                    self.contents.texts.push((path.clone(), text));
+                }
+            }
+        }
+        self.contents.entries.push(ArchiveEntry { path, size });
+        Ok(())
+    }
+}
@@ -1,8 +1,38 @@
-use std::path::Path;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use anyhow::Result;
-use walkdir::WalkDir;
-use crate::gpt_client::{GPTClient, BatchResult};
-use crate::utils::{format_size, get_file_info};
+use clap::ValueEnum;
+use futures::future;
+use futures::stream::{self, StreamExt};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{info, warn};
+use walkdir::{DirEntry, WalkDir};
+use crate::gpt_client::{GPTClient, BatchResult, StructuredSummary, Summary, STDIN_NAME};
+use crate::output::{
+    spawn_output, writer_for, CacheStats, ExplorationReport, GroupBy, GroupedWriter, MarkdownReport, OutputFormat, OutputSender,
+    OutputWriter, ReportCollector, Stats, SummaryFiles, TeeWriter, TextWriter, WebhookWriter,
+};
+use crate::metrics;
+use crate::run_state::RunState;
+use crate::tokenizer;
+use crate::utils::{file_kind, format_size, get_file_info, read_text_lossy, FileInfo, LineRange};
+
+/// Order of entries within each directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SortOrder {
+    /// Case-insensitive by name
+    #[default]
+    Name,
+    /// Largest first
+    Size,
+    /// Most recently modified first
+    Mtime,
+    /// Filesystem traversal order
+    None,
+}
 
 pub struct FileExplorer {
     max_depth: u32,
@@ -10,12 +40,130 @@ pub struct FileExplorer {
     custom_query: Option<String>,
     batch_mode: bool,
     batch_query: Option<String>,
-    total_files: u32,
-    total_dirs: u32,
+    pub(crate) total_files: u32,
+    pub(crate) total_dirs: u32,
+    cache_hits: u64,
+    /// Summaries (or batch requests) that failed with an error
+    summary_errors: u64,
+    /// With --max-files, files past the cap are listed but not summarized
+    max_files: Option<u32>,
+    /// Files handed to the summarizer so far
+    files_summarized: u32,
+    follow_symlinks: bool,
+    sort: SortOrder,
+    excluded_dirs: Vec<String>,
+    dir_summaries: bool,
+    quiet: bool,
+    group_by: Option<GroupBy>,
+    /// Feeds the task that writes stdout, shared by the built-in writers
+    output: OutputSender,
+    writer: Box<dyn OutputWriter>,
+    format: OutputFormat,
+    report: Option<PathBuf>,
+    output_dir: Option<PathBuf>,
+    webhook: Option<String>,
+    concurrency: usize,
+    /// Files this run has summarized, for --resume
+    run_state: Option<RunState>,
+    cost_report: bool,
+    /// Wrapped around each file's summary in text output
+    summary_prefix: String,
+    summary_suffix: String,
+    /// Set during `explore_collect`, which prints nothing
+    collecting: bool,
+    diffstat: bool,
+    /// Lowercase interpreter names; when set only their files are summarized
+    interpreter_filter: Option<Vec<String>>,
+    readme_context: bool,
+    /// Set during `explore_lines`, whose file is summarized from these lines
+    line_range: Option<LineRange>,
+}
+
+type SummaryResult = Result<Option<Summary>>;
+
+/// File count and total size for one file type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypeStats {
+    pub files: u32,
+    pub bytes: u64,
+}
+
+/// Files grouped by interpreter, or by extension when none was detected.
+#[derive(Debug, Default)]
+pub struct TypeBreakdown {
+    totals: HashMap<String, TypeStats>,
+}
+
+impl TypeBreakdown {
+    pub fn add(&mut self, path: &Path, info: &FileInfo) {
+        let totals = self.totals.entry(file_kind(path, info)).or_default();
+        totals.files += 1;
+        totals.bytes += info.size;
+    }
+
+    /// Types by total size, largest first.
+    pub fn rows(&self) -> Vec<(&str, TypeStats)> {
+        let mut rows: Vec<_> = self.totals.iter().map(|(kind, stats)| (kind.as_str(), *stats)).collect();
+        rows.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(b.0)));
+        rows
+    }
+}
+
+/// Token counts per text file, as gathered by `FileExplorer::count_tokens`.
+#[derive(Debug, Default)]
+pub struct TokenCounts {
+    files: Vec<(PathBuf, String, usize)>,
+}
+
+impl TokenCounts {
+    pub fn add(&mut self, path: &Path, info: &FileInfo, tokens: usize) {
+        self.files.push((path.to_path_buf(), file_kind(path, info), tokens));
+    }
+
+    /// Path, type and token count of each file, most tokens first.
+    pub fn files(&self) -> Vec<(&Path, &str, usize)> {
+        let mut files: Vec<_> = self.files
+            .iter()
+            .map(|(path, kind, tokens)| (path.as_path(), kind.as_str(), *tokens))
+            .collect();
+        files.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(b.0)));
+        files
+    }
+
+    /// Type, file count and token count per file type, most tokens first.
+    pub fn by_kind(&self) -> Vec<(&str, u32, usize)> {
+        let mut totals: HashMap<&str, (u32, usize)> = HashMap::new();
+        for (_, kind, tokens) in &self.files {
+            let total = totals.entry(kind).or_default();
+            total.0 += 1;
+            total.1 += tokens;
+        }
+        let mut rows: Vec<_> = totals.into_iter().map(|(kind, (files, tokens))| (kind, files, tokens)).collect();
+        rows.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(b.0)));
+        rows
+    }
+
+    pub fn total(&self) -> usize {
+        self.files.iter().map(|(_, _, tokens)| tokens).sum()
+    }
+}
+
+/// Outcome of warming the cache with `FileExplorer::precache`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrecacheStats {
+    pub newly_cached: u32,
+    pub already_cached: u32,
+    pub failed: u32,
 }
 
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Directories that are almost never worth descending into
+pub const DEFAULT_EXCLUDED_DIRS: &[&str] = &[".git", "node_modules", "target"];
+
 impl FileExplorer {
     pub fn new(max_depth: u32) -> Self {
+        let output = spawn_output(std::io::stdout());
         Self {
             max_depth,
             summarizer: None,
@@ -24,7 +172,182 @@ impl FileExplorer {
             batch_query: None,
             total_files: 0,
             total_dirs: 0,
+            cache_hits: 0,
+            summary_errors: 0,
+            max_files: None,
+            files_summarized: 0,
+            follow_symlinks: false,
+            sort: SortOrder::default(),
+            excluded_dirs: DEFAULT_EXCLUDED_DIRS.iter().map(|name| name.to_string()).collect(),
+            dir_summaries: false,
+            quiet: false,
+            group_by: None,
+            writer: writer_for(OutputFormat::Text, false, output.clone()),
+            output,
+            format: OutputFormat::Text,
+            report: None,
+            output_dir: None,
+            webhook: None,
+            concurrency: DEFAULT_CONCURRENCY,
+            run_state: None,
+            cost_report: true,
+            summary_prefix: String::new(),
+            summary_suffix: String::new(),
+            collecting: false,
+            diffstat: false,
+            interpreter_filter: None,
+            readme_context: false,
+            line_range: None,
+        }
+    }
+
+    /// Descend into symlinked directories and include symlinked files.
+    pub fn set_follow_symlinks(&mut self, follow: bool) {
+        self.follow_symlinks = follow;
+    }
+
+    pub fn set_sort(&mut self, sort: SortOrder) {
+        self.sort = sort;
+    }
+
+    /// Directory names (not paths) that are pruned from the walk, replacing
+    /// the defaults. The explored root itself is never excluded.
+    pub fn set_excluded_dirs(&mut self, names: Vec<String>) {
+        self.excluded_dirs = names;
+    }
+
+    /// Summarize each directory's purpose from its files' summaries.
+    pub fn set_dir_summaries(&mut self, enabled: bool) {
+        self.dir_summaries = enabled;
+    }
+
+    /// Stop summarizing once `max` files were summarized; the files after
+    /// them are still listed.
+    pub fn set_max_files(&mut self, max: u32) {
+        self.max_files = Some(max);
+    }
+
+    /// How many more files may be summarized under --max-files.
+    fn remaining_files(&self) -> usize {
+        self.max_files.map_or(usize::MAX, |max| max.saturating_sub(self.files_summarized) as usize)
+    }
+
+    /// Whether the stats include what the requests cost, on by default.
+    pub fn set_cost_report(&mut self, enabled: bool) {
+        self.cost_report = enabled;
+    }
+
+    /// Print `prefix` before and `suffix` after each file's summary in text
+    /// output, with `{path}`, `{size}` and `{interpreter}` filled in.
+    pub fn set_summary_frame(&mut self, prefix: String, suffix: String) {
+        self.summary_prefix = prefix;
+        self.summary_suffix = suffix;
+    }
+
+    /// Report line counts and comment density for each text file.
+    pub fn set_diffstat(&mut self, enabled: bool) {
+        self.diffstat = enabled;
+    }
+
+    /// Only summarize files whose detected interpreter is one of
+    /// `interpreters`. `python` also matches versioned ones such as
+    /// `python3`. Other files are still listed.
+    pub fn set_interpreter_filter(&mut self, interpreters: Vec<String>) {
+        self.interpreter_filter = Some(interpreters.iter().map(|name| name.trim().to_lowercase()).collect());
+    }
+
+    /// Summarize the README at the root of each explored directory first
+    /// and give its summary to every other file's prompt as context.
+    pub fn set_readme_context(&mut self, enabled: bool) {
+        self.readme_context = enabled;
+    }
+
+    fn should_summarize(&self, summarizer: &GPTClient, path: &Path, info: &FileInfo) -> bool {
+        let wanted = match (&self.interpreter_filter, info.interpreter.as_deref()) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(filter), Some(interpreter)) => {
+                let interpreter = interpreter.to_lowercase();
+                filter.iter().any(|name| {
+                    interpreter.strip_prefix(name.as_str())
+                        .is_some_and(|version| version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+                })
+            }
+        };
+        wanted && summarizer.can_summarize(path, info)
+    }
+
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        self.concurrency = concurrency.max(1);
+    }
+
+    pub fn set_output_format(&mut self, format: OutputFormat) {
+        self.format = format;
+        self.rebuild_writer();
+    }
+
+    /// Leave the tree out of text output and print only summaries.
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+        self.rebuild_writer();
+    }
+
+    /// Also write a markdown report of every file and summary to `path`.
+    pub fn set_report(&mut self, path: PathBuf) {
+        self.report = Some(path);
+        self.rebuild_writer();
+    }
+
+    /// Also write each summary to its own markdown file under `dir`,
+    /// mirroring the explored tree.
+    pub fn set_output_dir(&mut self, dir: PathBuf) {
+        self.output_dir = Some(dir);
+        self.rebuild_writer();
+    }
+
+    /// Also POST each summary, and a batch answer, to `url` as JSON. Goes
+    /// through the summarizer's HTTP agent once one is set.
+    pub fn set_webhook(&mut self, url: String) {
+        self.webhook = Some(url);
+        self.rebuild_writer();
+    }
+
+    /// Render output with a custom writer instead of a built-in format.
+    pub fn set_writer(&mut self, writer: Box<dyn OutputWriter>) {
+        self.writer = writer;
+    }
+
+    /// Group text output instead of listing the tree; other formats
+    /// aren't affected.
+    pub fn set_group_by(&mut self, group_by: Option<GroupBy>) {
+        self.group_by = group_by;
+        self.rebuild_writer();
+    }
+
+    fn rebuild_writer(&mut self) {
+        let writer: Box<dyn OutputWriter> = match (self.group_by, self.format) {
+            (Some(GroupBy::Interpreter), OutputFormat::Text) => {
+                let out = self.output.clone();
+                let text = if self.quiet { TextWriter::quiet(out) } else { TextWriter::new(out) };
+                Box::new(GroupedWriter::new(text))
+            }
+            _ => writer_for(self.format, self.quiet, self.output.clone()),
+        };
+        let mut writers = vec![writer];
+        if let Some(path) = &self.report {
+            writers.push(Box::new(MarkdownReport::new(path.clone())));
+        }
+        if let Some(dir) = &self.output_dir {
+            writers.push(Box::new(SummaryFiles::new(dir.clone())));
         }
+        if let Some(url) = &self.webhook {
+            let agent = self.summarizer.as_ref().map(|summarizer| summarizer.agent().clone()).unwrap_or_else(ureq::agent);
+            writers.push(Box::new(WebhookWriter::new(url.clone(), agent)));
+        }
+        self.writer = match writers.len() {
+            1 => writers.pop().unwrap(),
+            _ => Box::new(TeeWriter::new(writers)),
+        };
     }
 
     pub fn set_summarizer(&mut self, client: GPTClient, custom_query: Option<String>, batch_query: Option<String>) {
@@ -34,13 +357,79 @@ impl FileExplorer {
         self.batch_query = batch_query;
     }
 
+    /// A sender for lines that must not interleave with the exploration
+    /// output, such as ones written by tasks running alongside it.
+    pub fn output(&self) -> OutputSender {
+        self.output.clone()
+    }
+
+    pub fn summarizer(&self) -> Option<&GPTClient> {
+        self.summarizer.as_ref()
+    }
+
+    /// The --ai-query question asked of every file.
+    pub fn custom_query(&self) -> Option<&str> {
+        self.custom_query.as_deref()
+    }
+
+    /// Record summarized files in the cache dir as they are done, so an
+    /// interrupted run can be continued. With `resume`, files recorded by
+    /// the previous run are skipped without being read. Batch runs make a
+    /// single request and dry runs summarize nothing, so neither is tracked.
+    pub fn start_run(&mut self, resume: bool) -> Result<()> {
+        if let Some(summarizer) = self.summarizer.as_ref().filter(|summarizer| !self.batch_mode && !summarizer.is_dry_run()) {
+            self.run_state = Some(RunState::start(summarizer.cache_dir(), resume)?);
+        }
+        Ok(())
+    }
+
+    /// End the run started with `start_run`. The record is removed unless
+    /// a summary failed or the run was interrupted, so a resumed run picks
+    /// up what is left.
+    pub fn finish_run(&mut self) -> Result<()> {
+        match self.run_state.take() {
+            Some(run_state) if self.summary_errors == 0 && !self.is_interrupted() => run_state.clear(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether Ctrl-C stopped the summaries. Files are still listed, and
+    /// summaries that finished before it are still printed.
+    pub fn is_interrupted(&self) -> bool {
+        self.summarizer.as_ref().is_some_and(GPTClient::is_interrupted)
+    }
+
+    fn is_resumed(&self, path: &Path) -> bool {
+        self.run_state.as_ref().is_some_and(|run_state| run_state.is_completed(path))
+    }
+
+    /// Number of summaries that failed so far. Failures are reported and
+    /// skipped rather than ending the exploration.
+    pub fn summary_errors(&self) -> u64 {
+        self.summary_errors
+    }
+
     pub async fn explore<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         let path = path.as_ref();
-        println!("\nExploring: {}", path.display());
-        println!("{}", "=".repeat(80));
+        self.explore_under(path, path).await
+    }
 
-        if path.is_file() {
-            self.process_file(path, 0).await?;
+    /// Explore `path`, a file or directory in the explored root `root`,
+    /// with the README context of `root`. --watch explores the files that
+    /// changed this way.
+    pub(crate) async fn explore_under(&mut self, root: &Path, path: &Path) -> Result<()> {
+        self.writer.begin(path)?;
+        self.load_readme_context(root).await;
+
+        // `-` reads content piped through stdin
+        if path == Path::new("-") {
+            let content = std::io::read_to_string(std::io::stdin())?;
+            self.process_content(STDIN_NAME, &content).await?;
+        } else if path.is_file() {
+            // A line range isn't what the run state records as done
+            if self.line_range.is_some() || !self.is_resumed(path) {
+                self.process_file(path, 0, get_file_info(path)?, None).await?;
+            }
         } else {
             self.explore_directory(path).await?;
         }
@@ -49,78 +438,421 @@ impl FileExplorer {
             if let Some(summarizer) = &self.summarizer {
                 // Only show "Generating batch summaries..." for regular summaries
                 if self.batch_query.is_none() {
-                    println!("\nGenerating batch summaries...");
+                    self.writer.batch_started()?;
                 }
 
                 match summarizer.summarize_batch(self.batch_query.as_deref()).await {
-                    Ok(BatchResult::Summaries(summaries)) => {
-                        if !summaries.is_empty() {
-                            println!("\nFile Summaries:");
-                            println!("{}", "=".repeat(80));
-                            for (file_name, summary) in summaries {
-                                println!("\n📄 {}:", file_name);
-                                println!("   📝 {}", summary);
-                            }
+                    Ok(BatchResult::Summaries { summaries, errors }) => {
+                        self.writer.batch_summaries(&summaries)?;
+                        if !errors.is_empty() {
+                            self.summary_errors += errors.len() as u64;
+                            self.writer.batch_failures(&errors)?;
                         }
                     }
-                    Ok(BatchResult::Answer(answer)) => {
-                        // For custom queries, display the direct answer
-                        println!("\n📝 {}", answer);
+                    Ok(BatchResult::Answer(answer)) => self.writer.batch_answer(&answer)?,
+                    Err(e) => {
+                        self.summary_errors += 1;
+                        self.writer.batch_error(&e)?;
                     }
-                    Err(e) => eprintln!("\n⚠️ Error processing files: {}", e),
                 }
             }
         }
 
-        println!("\nSummary:");
-        println!("Total directories: {}", self.total_dirs);
-        println!("Total files: {}", self.total_files);
+        if let Some(summarizer) = &self.summarizer {
+            summarizer.save_index()?;
+        }
+
+        self.writer.finish(&Stats {
+            total_dirs: self.total_dirs,
+            total_files: self.total_files,
+            dry_run: self.summarizer.as_ref().and_then(|s| s.dry_run_estimate()),
+            tokens_used: self.summarizer.as_ref().and_then(|s| s.tokens_used()),
+            cost: self.summarizer.as_ref()
+                .filter(|_| self.cost_report)
+                .and_then(|s| s.cost_report()),
+            cache: self.summarizer.as_ref()
+                .filter(|s| s.is_verbose_cache())
+                .map(|s| CacheStats { hits: self.cache_hits, api_calls: s.api_calls() }),
+            changed: self.summarizer.as_ref().and_then(|s| s.take_changed()),
+        })?;
+        // The process may exit right after, before the output task caught up
+        self.output.flush()?;
 
         Ok(())
     }
 
-    async fn explore_directory(&mut self, path: &Path) -> Result<()> {
-        for entry in WalkDir::new(path)
-            .max_depth(self.max_depth as usize)
+    /// Explore the file at `path` like `explore`, summarizing only lines
+    /// `range` of it.
+    pub async fn explore_lines(&mut self, path: &Path, range: LineRange) -> Result<()> {
+        if !path.is_file() {
+            anyhow::bail!("A line range needs a file, but {} isn't one", path.display());
+        }
+        self.line_range = Some(range);
+        let result = self.explore(path).await;
+        self.line_range = None;
+        result
+    }
+
+    /// Explore `path` like `explore`, returning the files, summaries and
+    /// stats instead of writing them out. The configured writer is left
+    /// untouched for later calls.
+    pub async fn explore_collect<P: AsRef<Path>>(&mut self, path: P) -> Result<ExplorationReport> {
+        let report = Arc::new(Mutex::new(ExplorationReport::default()));
+        let writer = std::mem::replace(&mut self.writer, Box::new(ReportCollector(report.clone())));
+        self.collecting = true;
+        let result = self.explore(path).await;
+        self.collecting = false;
+        self.writer = writer;
+        result?;
+        let report = std::mem::take(&mut *report.lock().unwrap());
+        Ok(report)
+    }
+
+    /// Summarize every eligible file under `path` into the cache without
+    /// printing the summaries. Keys match a later run with the same
+    /// settings, since this goes through the same `summarize_file` calls.
+    pub async fn precache<P: AsRef<Path>>(&self, path: P) -> Result<PrecacheStats> {
+        let path = path.as_ref();
+        let summarizer = self.summarizer.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Precaching needs a summarizer"))?;
+        let files: Vec<(PathBuf, FileInfo)> = self.files_under(path)
             .into_iter()
-            .filter_map(|e| e.ok())
-        {
+            .filter_map(|file| get_file_info(&file).ok().map(|info| (file, info)))
+            .collect();
+
+        let mut stats = PrecacheStats::default();
+        for (path, result) in self.summarize_files(summarizer, &files, usize::MAX).await {
+            match result {
+                Ok(Some(summary)) if summary.from_cache => stats.already_cached += 1,
+                Ok(Some(summary)) if summary.generated => stats.newly_cached += 1,
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Failed to summarize {}: {}", path.display(), e);
+                    stats.failed += 1;
+                }
+            }
+        }
+        summarizer.save_index()?;
+        Ok(stats)
+    }
+
+    /// Add every file under `path` to `breakdown` without listing or
+    /// summarizing anything.
+    pub fn tally_file_types(&self, path: &Path, breakdown: &mut TypeBreakdown) {
+        for file in self.files_under(path) {
+            match get_file_info(&file) {
+                Ok(info) => breakdown.add(&file, &info),
+                Err(e) => warn!("Skipping {}: {}", file.display(), e),
+            }
+        }
+    }
+
+    /// Add the token count of every text file under `path`, as `model`
+    /// would encode it, to `counts`. No requests are made.
+    pub fn count_tokens(&self, path: &Path, model: &str, counts: &mut TokenCounts) {
+        for file in self.files_under(path) {
+            let counted = get_file_info(&file).and_then(|info| {
+                if info.is_text {
+                    let tokens = tokenizer::count_tokens(model, &read_text_lossy(&file)?);
+                    counts.add(&file, &info, tokens);
+                }
+                Ok(())
+            });
+            if let Err(e) = counted {
+                warn!("Skipping {}: {}", file.display(), e);
+            }
+        }
+    }
+
+    /// Summarize the README of the explored root `root` for
+    /// --readme-context, replacing the context of the previous root.
+    /// Without one, as for a file, or when its summary fails, files are
+    /// summarized without context.
+    async fn load_readme_context(&mut self, root: &Path) {
+        let Some(summarizer) = self.summarizer.as_mut() else {
+            return;
+        };
+        summarizer.clear_project_context();
+        if !self.readme_context || self.batch_mode {
+            return;
+        }
+        let Some(readme) = find_readme(root) else {
+            return;
+        };
+        match summarizer.summarize_file(&readme, None).await {
+            Ok(Some(summary)) if summary.from_cache || summary.generated => {
+                summarizer.set_project_context(&readme, summary.text);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to summarize {} for context: {}", readme.display(), e),
+        }
+    }
+
+    async fn explore_directory(&mut self, path: &Path) -> Result<()> {
+        let mut entries = self.walk(path);
+        let listed = entries.len();
+        entries.retain(|entry| entry.file_type().is_dir() || !self.is_resumed(entry.path()));
+        if entries.len() < listed {
+            info!("Resuming: skipping {} files summarized by the previous run", listed - entries.len());
+        }
+        // Read once, for choosing what to summarize and for listing
+        let files = entries
+            .iter()
+            .filter(|entry| !entry.file_type().is_dir())
+            .map(|entry| Ok((entry.path().to_path_buf(), get_file_info(entry.path())?)))
+            .collect::<Result<Vec<(PathBuf, FileInfo)>>>()?;
+        let mut summaries = self.summarize_all(&files).await;
+        let mut infos: HashMap<PathBuf, FileInfo> = files.into_iter().collect();
+        let mut dir_summaries = self.summarize_directories(&entries, &summaries).await;
+
+        for entry in &entries {
             let depth = entry.depth();
-            let indent = "  ".repeat(depth);
 
             if entry.file_type().is_dir() {
                 self.total_dirs += 1;
-                println!("{}📁 {}/", indent, entry.file_name().to_string_lossy());
+                self.writer.directory(entry.path(), depth)?;
+                match dir_summaries.remove(entry.path()) {
+                    Some(Ok(summary)) => self.writer.directory_summary(entry.path(), &summary.text, depth)?,
+                    Some(Err(e)) => warn!("Failed to summarize directory {}: {}", entry.path().display(), e),
+                    None => {}
+                }
             } else {
-                self.process_file(entry.path(), depth).await?;
+                let summary = summaries.remove(entry.path());
+                let info = infos.remove(entry.path()).expect("every listed file was read");
+                self.process_file(entry.path(), depth, info, summary).await?;
             }
         }
         Ok(())
     }
 
-    async fn process_file(&mut self, path: &Path, depth: usize) -> Result<()> {
+    /// `path` itself when it is a file, otherwise the files the walk lists.
+    pub(crate) fn files_under(&self, path: &Path) -> Vec<PathBuf> {
+        if path.is_file() {
+            return vec![path.to_path_buf()];
+        }
+        self.walk(path)
+            .iter()
+            .filter(|entry| !entry.file_type().is_dir())
+            .map(|entry| entry.path().to_path_buf())
+            .collect()
+    }
+
+    /// Every directory and file under `path` that should be listed, in
+    /// display order.
+    fn walk(&self, path: &Path) -> Vec<DirEntry> {
+        // Every exploring command walks its root first
+        if let Some(summarizer) = &self.summarizer {
+            summarizer.load_root_ignore_files(path);
+        }
+        let mut walker = WalkDir::new(path)
+            .max_depth(self.max_depth as usize)
+            .follow_links(self.follow_symlinks);
+        // Sorting siblings keeps every directory next to its contents
+        if self.sort != SortOrder::None {
+            let sort = self.sort;
+            walker = walker.sort_by(move |a, b| compare_entries(sort, a, b));
+        }
+
+        walker
+            .into_iter()
+            // Pruned before descending, so excluded trees are never read
+            .filter_entry(|entry| entry.depth() == 0 || !self.is_excluded_dir(entry))
+            .filter_map(|entry| match entry {
+                Ok(entry) => Some(entry),
+                Err(err) => {
+                    if let Some(ancestor) = err.loop_ancestor() {
+                        warn!("Skipping symlink loop at {} (points back to {})",
+                            err.path().unwrap_or(path).display(), ancestor.display());
+                    }
+                    None
+                }
+            })
+            // Without --follow-symlinks, links are skipped rather than
+            // listed as files
+            .filter(|entry| self.follow_symlinks || entry.depth() == 0 || !entry.path_is_symlink())
+            .collect()
+    }
+
+    /// Whether `path`, relative to an explored root, lies inside an
+    /// excluded directory.
+    pub(crate) fn is_in_excluded_dir(&self, path: &Path) -> bool {
+        path.parent()
+            .into_iter()
+            .flat_map(Path::components)
+            .any(|component| self.excluded_dirs.iter().any(|name| component.as_os_str() == name.as_str()))
+    }
+
+    fn is_excluded_dir(&self, entry: &DirEntry) -> bool {
+        entry.file_type().is_dir()
+            && self.excluded_dirs.iter().any(|name| entry.file_name() == name.as_str())
+    }
+
+    /// With --dir-summaries, a purpose for every directory with summarized
+    /// files, built from the prefetched file summaries so it can be shown
+    /// under the directory's line before its contents.
+    async fn summarize_directories(
+        &self,
+        entries: &[DirEntry],
+        summaries: &HashMap<PathBuf, SummaryResult>,
+    ) -> HashMap<PathBuf, Result<Summary>> {
+        let summarizer = match &self.summarizer {
+            Some(summarizer) if self.dir_summaries && !summarizer.is_interrupted() => summarizer,
+            _ => return HashMap::new(),
+        };
+
+        let mut children: HashMap<&Path, Vec<(String, String)>> = HashMap::new();
+        for entry in entries.iter().filter(|entry| !entry.file_type().is_dir()) {
+            if let (Some(Ok(Some(summary))), Some(dir)) = (summaries.get(entry.path()), entry.path().parent()) {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                children.entry(dir).or_default().push((name, summary.text.clone()));
+            }
+        }
+
+        stream::iter(children)
+            .map(|(dir, files)| async move {
+                (dir.to_path_buf(), summarizer.summarize_directory(dir, &files).await)
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await
+    }
+
+    /// Summarize text files concurrently, keyed by path so the results can
+    /// be printed in traversal order regardless of completion order.
+    async fn summarize_all(&self, files: &[(PathBuf, FileInfo)]) -> HashMap<PathBuf, SummaryResult> {
+        // Streamed summaries are printed as they arrive, so they can't be
+        // computed ahead of the tree output
+        match &self.summarizer {
+            Some(summarizer) if !self.batch_mode && !summarizer.is_streaming() => {
+                self.summarize_files(summarizer, files, self.remaining_files()).await
+            }
+            _ => HashMap::new(),
+        }
+    }
+
+    /// Summarize the first `limit` of `files` that can be summarized.
+    async fn summarize_files(
+        &self,
+        summarizer: &GPTClient,
+        files: &[(PathBuf, FileInfo)],
+        limit: usize,
+    ) -> HashMap<PathBuf, SummaryResult> {
+        let custom_query = self.custom_query.as_deref();
+
+        let candidates: Vec<&PathBuf> = files
+            .iter()
+            .filter(|(path, info)| self.should_summarize(summarizer, path, info))
+            .map(|(path, _)| path)
+            .take(limit)
+            .collect();
+        let progress = self.progress_bar(candidates.len() as u64);
+
+        // Summaries are started as the stream is polled, so after a Ctrl-C
+        // only those already in flight are finished
+        let summaries = stream::iter(candidates)
+            .take_while(|_| future::ready(!summarizer.is_interrupted()))
+            .map(|path| async move {
+                (path.clone(), summarizer.summarize_file(path, custom_query).await)
+            })
+            .buffer_unordered(self.concurrency)
+            .inspect(|(path, _)| {
+                progress.inc(1);
+                progress.set_message(path.file_name().unwrap_or_default().to_string_lossy().into_owned());
+            })
+            .collect()
+            .await;
+
+        progress.finish_and_clear();
+        summaries
+    }
+
+    /// A progress bar on stderr, hidden when it would corrupt
+    /// machine-readable output or nobody is watching.
+    fn progress_bar(&self, total: u64) -> ProgressBar {
+        if self.format != OutputFormat::Text || self.collecting || !std::io::stdout().is_terminal() || total == 0 {
+            return ProgressBar::hidden();
+        }
+        let progress = ProgressBar::new(total);
+        if let Ok(style) = ProgressStyle::with_template(
+            "{spinner} [{elapsed_precise}] {bar:40} {pos}/{len} (ETA {eta}) {msg}",
+        ) {
+            progress.set_style(style);
+        }
+        progress
+    }
+
+    async fn process_file(&mut self, path: &Path, depth: usize, file_info: FileInfo, prefetched: Option<SummaryResult>) -> Result<()> {
         self.total_files += 1;
-        let indent = "  ".repeat(depth);
-        let file_info = get_file_info(path)?;
 
-        println!("{}📄 {} ({})", 
-            indent,
-            path.file_name().unwrap_or_default().to_string_lossy(),
-            format_size(file_info.size)
-        );
+        self.writer.file(path, &file_info, depth)?;
+        if self.diffstat && file_info.is_text {
+            let metrics = metrics::measure(&read_text_lossy(path)?, file_info.interpreter.as_deref());
+            self.writer.metrics(&metrics, depth)?;
+        }
 
         if let Some(summarizer) = &self.summarizer {
-            if file_info.is_text {
+            if self.should_summarize(summarizer, path, &file_info) {
+                if self.remaining_files() == 0 {
+                    return self.writer.summary("(skipped: file cap reached)", depth);
+                }
+                if prefetched.is_none() && summarizer.is_interrupted() {
+                    return Ok(());
+                }
+                self.files_summarized += 1;
                 if self.batch_mode {
                     summarizer.collect_for_batch(path).await?;
                 } else {
-                    match summarizer.summarize_file(path, self.custom_query.as_deref()).await {
+                    let mut streamed = false;
+                    // The first error writing a chunk, after which the rest
+                    // of the summary isn't written
+                    let mut chunk_error = None;
+                    let (prefix, suffix) = self.summary_frame(path, &file_info);
+                    let result = match (prefetched, self.line_range) {
+                        (Some(result), _) => result,
+                        (None, Some(range)) => summarizer.summarize_file_range(path, range, self.custom_query.as_deref()).await,
+                        (None, None) if summarizer.is_streaming() => {
+                            let writer = &mut self.writer;
+                            summarizer.summarize_file_streaming(path, self.custom_query.as_deref(), &mut |chunk| {
+                                if chunk_error.is_some() {
+                                    return;
+                                }
+                                let written = if streamed { Ok(()) } else { writer.summary_chunk(&prefix, true, depth) };
+                                if let Err(e) = written.and_then(|()| writer.summary_chunk(chunk, false, depth)) {
+                                    chunk_error = Some(e);
+                                }
+                                streamed = true;
+                            }).await
+                        }
+                        (None, None) => summarizer.summarize_file(path, self.custom_query.as_deref()).await,
+                    };
+                    if let Some(e) = chunk_error {
+                        return Err(e);
+                    }
+                    if let Ok(Some(summary)) = &result {
+                        self.cache_hits += summary.from_cache as u64;
+                        // Notes such as an exhausted budget are tried again
+                        if (summary.from_cache || summary.generated) && self.line_range.is_none() {
+                            if let Some(run_state) = &mut self.run_state {
+                                run_state.complete(path)?;
+                            }
+                        }
+                    }
+                    match result {
+                        Ok(Some(summary)) if streamed => {
+                            self.writer.summary_chunk(&suffix, false, depth)?;
+                            self.writer.summary_streamed(&format!("{}{}{}", prefix, summary.text, suffix), depth)?;
+                        }
+                        // Structured summaries are rendered from their JSON
+                        Ok(Some(summary)) if StructuredSummary::parse(&summary.text).is_some() => {
+                            self.writer.summary(&summary.text, depth)?;
+                        }
                         Ok(Some(summary)) => {
-                            println!("{}   📝 Summary: {}", indent, summary);
+                            self.writer.summary(&format!("{}{}{}", prefix, summary.text, suffix), depth)?;
                         }
                         Ok(None) => {}
                         Err(e) => {
-                            eprintln!("{}   ⚠️ Failed to generate summary: {}", indent, e);
+                            self.summary_errors += 1;
+                            self.writer.summary_error(&e, depth)?;
                         }
                     }
                 }
@@ -129,4 +861,82 @@ impl FileExplorer {
 
         Ok(())
     }
+
+    /// The --summary-prefix and --summary-suffix for `path`, empty outside
+    /// text output.
+    fn summary_frame(&self, path: &Path, info: &FileInfo) -> (String, String) {
+        if self.format != OutputFormat::Text || self.collecting {
+            return (String::new(), String::new());
+        }
+        let render = |template: &str| {
+            template
+                .replace("{path}", &path.display().to_string())
+                .replace("{size}", &format_size(info.size))
+                .replace("{interpreter}", info.interpreter.as_deref().unwrap_or("unknown"))
+        };
+        (render(&self.summary_prefix), render(&self.summary_suffix))
+    }
+
+    /// List and summarize content that isn't a file on disk, under `name`.
+    pub async fn process_content(&mut self, name: &str, content: &str) -> Result<()> {
+        self.total_files += 1;
+        let file_info = FileInfo { size: content.len() as u64, is_text: true, interpreter: None };
+        self.writer.file(Path::new(name), &file_info, 0)?;
+
+        if let Some(summarizer) = &self.summarizer {
+            if self.batch_mode {
+                summarizer.collect_content(name, content);
+            } else {
+                match summarizer.summarize_content(name, content, self.custom_query.as_deref()).await {
+                    Ok(Some(summary)) => {
+                        self.cache_hits += summary.from_cache as u64;
+                        self.writer.summary(&summary.text, 0)?;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        self.summary_errors += 1;
+                        self.writer.summary_error(&e, 0)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn compare_entries(sort: SortOrder, a: &DirEntry, b: &DirEntry) -> Ordering {
+    let by_name = || {
+        a.file_name().to_string_lossy().to_lowercase()
+            .cmp(&b.file_name().to_string_lossy().to_lowercase())
+    };
+    match sort {
+        SortOrder::Name | SortOrder::None => by_name(),
+        SortOrder::Size => {
+            let size = |entry: &DirEntry| entry.metadata().map(|m| m.len()).unwrap_or(0);
+            size(b).cmp(&size(a)).then_with(by_name)
+        }
+        SortOrder::Mtime => {
+            let mtime = |entry: &DirEntry| entry.metadata().ok().and_then(|m| m.modified().ok());
+            mtime(b).cmp(&mtime(a)).then_with(by_name)
+        }
+    }
+}
+
+/// The README directly in `dir`: `README`, `README.md`, `readme.txt` and
+/// so on, preferring Markdown when there are several.
+fn find_readme(dir: &Path) -> Option<PathBuf> {
+    let mut readmes: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.file_stem().is_some_and(|stem| stem.eq_ignore_ascii_case("readme"))
+        })
+        .collect();
+    readmes.sort_by_key(|path| {
+        let markdown = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("md"));
+        (!markdown, path.clone())
+    });
+    readmes.into_iter().next()
 }
@@ -0,0 +1,77 @@
+use std::path::Path;
+use std::process::Command;
+use anyhow::{Context, Result};
+
+// Separate the fields of a commit and the commits themselves in
+// `git log` output, since subjects and bodies may contain anything else
+const FIELD_SEPARATOR: char = '\x1f';
+const COMMIT_SEPARATOR: char = '\x1e';
+
+/// One commit as `git log` reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub hash: String,
+    pub author: String,
+    /// `YYYY-MM-DD`
+    pub date: String,
+    pub subject: String,
+    /// The message after the subject, possibly empty
+    pub body: String,
+}
+
+/// The last `count` commits reachable from HEAD in the repository at
+/// `repo`, newest first, limited to authors matching `author` (a regex,
+/// as `git log --author` takes it) when given.
+pub fn read_commits(repo: &Path, count: usize, author: Option<&str>) -> Result<Vec<Commit>> {
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(repo)
+        .arg("log")
+        .arg(format!("--max-count={}", count))
+        .arg("--date=short")
+        .arg("--format=%H%x1f%an%x1f%ad%x1f%s%x1f%b%x1e");
+    if let Some(author) = author {
+        command.arg(format!("--author={}", author));
+    }
+    let output = command.output().context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!("git log failed in {}: {}", repo.display(), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(parse_log(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn parse_log(output: &str) -> Vec<Commit> {
+    output
+        .split(COMMIT_SEPARATOR)
+        .filter_map(|record| {
+            let mut fields = record.trim_start_matches('\n').splitn(5, FIELD_SEPARATOR);
+            Some(Commit {
+                hash: fields.next().filter(|hash| !hash.is_empty())?.to_string(),
+                author: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                subject: fields.next()?.to_string(),
+                body: fields.next().unwrap_or_default().trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The prompt asking for a summary of `commits` in `language`, oldest
+/// commit first so the history reads in order.
+pub fn build_prompt(commits: &[Commit], language: &str) -> String {
+    let mut prompt = format!(
+        "The following are the most recent commits of a repository, oldest first. Summarize in {} \
+        what has been worked on recently: the main themes and notable changes, rather than each commit.\n\n",
+        language
+    );
+    for commit in commits.iter().rev() {
+        prompt.push_str(&format!("{} {} ({}): {}\n", &commit.hash[..commit.hash.len().min(8)], commit.date, commit.author, commit.subject));
+        if !commit.body.is_empty() {
+            for line in commit.body.lines() {
+                prompt.push_str(&format!("    {}\n", line));
+            }
+        }
+    }
+    prompt
+}
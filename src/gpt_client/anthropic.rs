@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use super::provider::{send_with_retry, ChatMessage, Summarizer};
+use super::rate_limit::RateLimiter;
+
+const API_URL: &str = "https://api.anthropic.com/v1/messages";
+const API_VERSION: &str = "2023-06-01";
+
+#[derive(Debug, Deserialize)]
+struct MessageResponse {
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+/// Summarizer backed by Anthropic's messages API.
+pub struct ClaudeClient {
+    agent: ureq::Agent,
+    api_key: String,
+    model: String,
+    max_retries: u32,
+    temperature: f32,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl ClaudeClient {
+    pub fn new(agent: ureq::Agent, api_key: String, model: String, max_retries: u32, temperature: f32) -> Self {
+        Self { agent, api_key, model, max_retries, temperature, limiter: None }
+    }
+
+    /// Make every attempt, retries included, wait for `limiter`.
+    pub(crate) fn with_rate_limiter(mut self, limiter: Option<Arc<RateLimiter>>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+}
+
+#[async_trait]
+impl Summarizer for ClaudeClient {
+    async fn chat(&self, messages: &[ChatMessage], max_tokens: u32) -> Result<String> {
+        // The system prompt is a field of its own rather than a message
+        let (system, messages) = match messages.split_first() {
+            Some((first, rest)) if first.role == "system" => (Some(first.content.as_str()), rest),
+            _ => (None, messages),
+        };
+        let mut body = json!({
+            "model": self.model,
+            "max_tokens": max_tokens,
+            "messages": messages.iter().map(message_json).collect::<Vec<_>>(),
+            "temperature": self.temperature
+        });
+        if let Some(system) = system {
+            body["system"] = json!(system);
+        }
+
+        let api_key = self.api_key.clone();
+        let agent = self.agent.clone();
+        let response = send_with_retry("Anthropic", self.max_retries, self.limiter.as_deref(), move || {
+            agent.post(API_URL)
+                .set("x-api-key", &api_key)
+                .set("anthropic-version", API_VERSION)
+                .set("Content-Type", "application/json")
+                .send_json(body.clone())
+                .map_err(Box::new)
+        })
+        .await?;
+
+        let message = tokio::task::spawn_blocking(move || {
+            response.into_json::<MessageResponse>()
+        })
+        .await??;
+
+        Ok(message.content
+            .into_iter()
+            .filter(|block| block.kind == "text")
+            .map(|block| block.text)
+            .collect())
+    }
+}
+
+/// A message in the request body. Images go in a base64 source block
+/// ahead of the text.
+fn message_json(message: &ChatMessage) -> Value {
+    match &message.image {
+        Some(image) => json!({
+            "role": message.role,
+            "content": [
+                {
+                    "type": "image",
+                    "source": { "type": "base64", "media_type": image.media_type, "data": image.data },
+                },
+                { "type": "text", "text": message.content },
+            ],
+        }),
+        None => json!({ "role": message.role, "content": message.content }),
+    }
+}
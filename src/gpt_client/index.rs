@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::UNIX_EPOCH;
+use anyhow::{Context, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+const INDEX_FILE: &str = "index.json";
+
+/// A file's size and modification time, taken before it is read.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FileStamp {
+    key: String,
+    size: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+}
+
+impl FileStamp {
+    /// `None` when the metadata (or a modification time) isn't available.
+    pub(crate) fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            key: std::path::absolute(path).ok()?.to_string_lossy().into_owned(),
+            size: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    size: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+    /// The summary settings the hash was computed with
+    settings: String,
+    content_hash: String,
+}
+
+/// Content hashes of files summarized before, keyed by absolute path, so
+/// that a file with the same size and modification time is matched to its
+/// cache entry without being read and hashed again.
+pub(crate) struct FileIndex {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, IndexEntry>>,
+    dirty: AtomicBool,
+}
+
+impl FileIndex {
+    /// The index in `cache_dir`; a missing or unreadable one starts empty.
+    pub(crate) fn load(cache_dir: &Path) -> Self {
+        let path = cache_dir.join(INDEX_FILE);
+        let entries = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self { path, entries: Mutex::new(entries), dirty: AtomicBool::new(false) }
+    }
+
+    /// The content hash recorded for an unchanged file with these settings.
+    pub(crate) fn lookup(&self, stamp: &FileStamp, settings: &str) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(&stamp.key)?;
+        let unchanged = entry.size == stamp.size
+            && entry.modified_secs == stamp.modified_secs
+            && entry.modified_nanos == stamp.modified_nanos
+            && entry.settings == settings;
+        unchanged.then(|| entry.content_hash.clone())
+    }
+
+    /// The content hash last recorded for the stamp's file with these
+    /// settings, whether or not the file changed since.
+    pub(crate) fn recorded(&self, stamp: &FileStamp, settings: &str) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(&stamp.key)
+            .filter(|entry| entry.settings == settings)
+            .map(|entry| entry.content_hash.clone())
+    }
+
+    pub(crate) fn record(&self, stamp: FileStamp, settings: String, content_hash: String) {
+        let entry = IndexEntry {
+            size: stamp.size,
+            modified_secs: stamp.modified_secs,
+            modified_nanos: stamp.modified_nanos,
+            settings,
+            content_hash,
+        };
+        self.entries.lock().unwrap().insert(stamp.key, entry);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Write the index back if anything was recorded, replacing the file
+    /// atomically.
+    pub(crate) fn save(&self) -> Result<()> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        file.write_all(serde_json::to_string(&*self.entries.lock().unwrap())?.as_bytes())?;
+        file.persist(&self.path)
+            .with_context(|| format!("Failed to write file index {}", self.path.display()))?;
+        debug!("Saved file index {}", self.path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use std::borrow::Cow;
+use anyhow::Result;
+
+/// Summary languages by canonical name, with the ISO 639 codes and other
+/// names that mean them, and how many more summary tokens they need than
+/// English for the same content.
+const LANGUAGES: &[(&str, &[&str], f32)] = &[
+    ("english", &["en", "eng"], 1.0),
+    ("japanese", &["ja", "jp", "jpn", "日本語"], 1.5),
+    ("chinese", &["zh", "zho", "chi", "cmn", "mandarin", "中文"], 1.5),
+    ("korean", &["ko", "kor", "한국어"], 1.3),
+    ("spanish", &["es", "spa", "español", "espanol"], 1.0),
+    ("french", &["fr", "fra", "fre", "français", "francais"], 1.0),
+    ("german", &["de", "deu", "ger", "deutsch"], 1.0),
+    ("italian", &["it", "ita", "italiano"], 1.0),
+    ("portuguese", &["pt", "por", "português", "portugues"], 1.0),
+    ("dutch", &["nl", "nld", "dut", "nederlands"], 1.0),
+    ("russian", &["ru", "rus", "русский"], 1.0),
+    ("ukrainian", &["uk", "ukr"], 1.0),
+    ("polish", &["pl", "pol", "polski"], 1.0),
+    ("swedish", &["sv", "swe", "svenska"], 1.0),
+    ("turkish", &["tr", "tur", "türkçe"], 1.0),
+    ("arabic", &["ar", "ara"], 1.0),
+    ("hindi", &["hi", "hin"], 1.0),
+    ("indonesian", &["id", "ind", "bahasa indonesia"], 1.0),
+    ("vietnamese", &["vi", "vie", "tiếng việt"], 1.0),
+    ("thai", &["th", "tha"], 1.0),
+];
+
+// The start of a file is enough to tell its language from
+const DETECTION_SAMPLE_CHARS: usize = 10_000;
+
+/// `--language auto`: summarize each file in the language it is written in
+pub(crate) const AUTO_LANGUAGE: &str = "auto";
+
+/// The canonical name of `language`, given as a name or ISO code in any
+/// case. Unknown languages are an error unless `allow_unknown`, in which
+/// case they are passed through as written.
+pub(crate) fn normalize_language(language: &str, allow_unknown: bool) -> Result<String> {
+    let wanted = language.trim().to_lowercase();
+    if wanted == AUTO_LANGUAGE {
+        return Ok(wanted);
+    }
+    let known = LANGUAGES.iter()
+        .find(|(name, aliases, _)| *name == wanted || aliases.contains(&wanted.as_str()));
+    match known {
+        Some((name, _, _)) => Ok(name.to_string()),
+        None if allow_unknown => Ok(language.trim().to_string()),
+        None => anyhow::bail!(
+            "Unknown language '{}'. Use an ISO 639-1 code or one of: {}. Use --allow-unknown-language to pass it through anyway",
+            language,
+            LANGUAGES.iter().map(|(name, _, _)| *name).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+/// The canonical names of the languages known to `normalize_language`.
+pub(crate) fn known_languages() -> impl Iterator<Item = &'static str> {
+    LANGUAGES.iter().map(|(name, _, _)| *name)
+}
+
+/// How much longer summaries in `language` (a canonical name) should be.
+pub(crate) fn length_multiplier(language: &str) -> f32 {
+    LANGUAGES.iter()
+        .find(|(name, _, _)| *name == language)
+        .map_or(1.0, |(_, _, multiplier)| *multiplier)
+}
+
+/// The natural language `content` is mostly written in, as a canonical
+/// name, for `--language auto`. English when the detection isn't reliable,
+/// as for files that are nearly all code.
+pub(crate) fn detect_language(content: &str) -> String {
+    let sample = content.char_indices().nth(DETECTION_SAMPLE_CHARS).map_or(content, |(end, _)| &content[..end]);
+    let Some(info) = whatlang::detect(sample).filter(whatlang::Info::is_reliable) else {
+        return "english".to_string();
+    };
+    let code = info.lang().code();
+    LANGUAGES.iter()
+        .find(|(_, aliases, _)| aliases.contains(&code))
+        .map_or_else(|| info.lang().eng_name().to_lowercase(), |(name, _, _)| name.to_string())
+}
+
+/// `language`, or the language detected in `content` when it is auto.
+pub(crate) fn resolve<'a>(language: &'a str, content: &str) -> Cow<'a, str> {
+    match language {
+        AUTO_LANGUAGE => Cow::Owned(detect_language(content)),
+        language => Cow::Borrowed(language),
+    }
+}
@@ -1,31 +1,130 @@
+use std::borrow::Cow;
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::io::{BufReader, Write};
 use anyhow::{Result, Context};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::env;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use directories::ProjectDirs;
 use ignore::gitignore::{GitignoreBuilder, Gitignore};
-use crate::utils;
+use log::{debug, info, warn};
+use once_cell::sync::OnceCell;
+use self::index::{FileIndex, FileStamp};
+use self::rate_limit::RateLimiter;
+use self::template::PromptTemplate;
+use crate::archive;
+use crate::git_log::{self, Commit};
+use crate::html;
+use crate::manifests;
+use crate::markdown;
+use crate::notebook;
+use crate::pricing::{self, CostReport};
+use crate::redact;
+use crate::tokenizer;
+use crate::utils::{self, LineRange};
 
-const MAX_FILE_SIZE: u64 = 1024 * 1024; // 1MB
-const MODEL: &str = "gpt-4o-mini";
+mod anthropic;
+mod index;
+pub(crate) mod language;
+pub(crate) mod openai;
+pub(crate) mod provider;
+mod rate_limit;
+pub(crate) mod structured;
+mod template;
+pub(crate) mod validate;
+
+pub use anthropic::ClaudeClient;
+pub use openai::OpenAIClient;
+pub use provider::{ChatMessage, OnChunk, Provider, Summarizer, DEFAULT_TEMPERATURE};
+pub use structured::StructuredSummary;
+pub use validate::DEFAULT_VALIDATION_THRESHOLD;
+
+const DEFAULT_MAX_FILE_SIZE: u64 = 1024 * 1024; // 1MB
+
+// Input budget per section when chunking oversized files (~4 chars per token)
+const CHUNK_MAX_TOKENS: usize = 4000;
+const CHUNK_MAX_CHARS: usize = CHUNK_MAX_TOKENS * 4;
 const CACHE_DIR: &str = ".cache";
+const APP_NAME: &str = "nexplorer";
+const GITIGNORE_FILE: &str = ".gitignore";
+// Same syntax as .gitignore, but only affects summarization
+const NEXPLORERIGNORE_FILE: &str = ".nexplorerignore";
+// Also gitignore syntax, read at the root of an explored path on request
+const DOCKERIGNORE_FILE: &str = ".dockerignore";
+const NPMIGNORE_FILE: &str = ".npmignore";
+
+// Retries for failed API requests
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+// Reply budget for free-form questions
+const ANSWER_MAX_TOKENS: u32 = 500;
+// Stored as the summary length of cached batch answers, which keeps them
+// apart from single-file summaries
+pub(crate) const BATCH_ANSWER_MARKER: &str = "batch-query";
+// Directory summaries are a single line, cached under their own marker
+const DIRECTORY_SUMMARY_TOKENS: u32 = 60;
+const DIRECTORY_SUMMARY_MARKER: &str = "directory";
+// Change summaries from `compare`, cached under their own marker
+const CHANGE_SUMMARY_TOKENS: u32 = 500;
+const CHANGE_SUMMARY_MARKER: &str = "compare";
+// Summaries of recent commits from `git-summary`, cached under their own marker
+const GIT_LOG_SUMMARY_TOKENS: u32 = 500;
+const GIT_LOG_SUMMARY_MARKER: &str = "git-log";
+// Summaries of a file's lines from `path:start-end`, cached under their own
+// marker so they stay apart from a whole file with the same content
+const LINE_RANGE_MARKER: &str = "lines";
+/// Confidence below which --resummarize-on-low-confidence asks again
+pub const DEFAULT_CONFIDENCE_THRESHOLD: f32 = 0.7;
+// Added to the prompt when a summary's confidence is below the threshold
+const LOW_CONFIDENCE_INSTRUCTIONS: &str = "Base the summary strictly on the content above: name the functions, \
+    types and settings it defines and what they do, and say plainly where its purpose is unclear rather than guessing.";
+
+/// Introduces the README summary in prompts with --readme-context
+const PROJECT_CONTEXT_INTRO: &str = "For context, this is a summary of the project's README:\n";
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Name under which content piped through stdin is summarized
+pub const STDIN_NAME: &str = "<stdin>";
 
-// File size thresholds for smart summary lengths
-const TINY_FILE_SIZE: u64 = 1024; // 1KB
-const SMALL_FILE_SIZE: u64 = 10 * 1024; // 10KB
-const MEDIUM_FILE_SIZE: u64 = 100 * 1024; // 100KB
-const LARGE_FILE_SIZE: u64 = 500 * 1024; // 500KB
+// Content size thresholds (in tokens) for smart summary lengths
+const TINY_CONTENT_TOKENS: usize = 256;
+const SMALL_CONTENT_TOKENS: usize = 2_500;
+const MEDIUM_CONTENT_TOKENS: usize = 25_000;
+const LARGE_CONTENT_TOKENS: usize = 125_000;
 
-// Base summary length targets for different file sizes (English)
-const TINY_SUMMARY_LENGTH: u32 = 75;     // Increased base length
-const SMALL_SUMMARY_LENGTH: u32 = 150;   // Increased base length
-const MEDIUM_SUMMARY_LENGTH: u32 = 250;  // Increased base length
-const LARGE_SUMMARY_LENGTH: u32 = 350;   // Increased base length
-const VERY_LARGE_SUMMARY_LENGTH: u32 = 500; // Increased base length
+// Base summary token budgets for different content sizes (English)
+const TINY_SUMMARY_LENGTH: u32 = 75;
+const SMALL_SUMMARY_LENGTH: u32 = 150;
+const MEDIUM_SUMMARY_LENGTH: u32 = 250;
+const LARGE_SUMMARY_LENGTH: u32 = 350;
+const VERY_LARGE_SUMMARY_LENGTH: u32 = 500;
+const SMART_SUMMARY_LENGTHS: [u32; 5] = [
+    TINY_SUMMARY_LENGTH,
+    SMALL_SUMMARY_LENGTH,
+    MEDIUM_SUMMARY_LENGTH,
+    LARGE_SUMMARY_LENGTH,
+    VERY_LARGE_SUMMARY_LENGTH,
+];
+
+// Preset token budgets, in the order of PRESET_SIZE_MULTIPLIERS' rows
+const SUMMARY_PRESETS: [(&str, u32); 4] = [("short", 50), ("medium", 100), ("long", 200), ("super", 500)];
+
+// With --adaptive-presets, each preset's budget is scaled by content size
+// bucket (tiny to very large, as for smart lengths). Every row grows with
+// size, and every column grows with the preset, so a shorter preset stays
+// shorter at any size.
+const PRESET_SIZE_MULTIPLIERS: [[f32; 5]; 4] = [
+    [0.6, 1.0, 1.3, 1.6, 2.0], // short: 30 to 100 tokens
+    [0.6, 1.0, 1.5, 2.0, 2.5], // medium: 60 to 250
+    [0.7, 1.0, 1.6, 2.2, 2.8], // long: 140 to 560
+    [0.8, 1.0, 1.5, 2.0, 2.5], // super: 400 to 1250
+];
 
 // File type specific length multipliers
 const FILE_TYPE_MULTIPLIERS: &[(&str, f32)] = &[
@@ -96,145 +195,720 @@ const FILE_TYPE_MULTIPLIERS: &[(&str, f32)] = &[
     ("*", 1.0),     // Default multiplier
 ];
 
-// Japanese language multiplier (approximately 50% more words needed)
-const JAPANESE_MULTIPLIER: f32 = 1.5;
+#[derive(Debug, Deserialize)]
+struct UserConfig {
+    api_key: Option<String>,
+    anthropic_api_key: Option<String>,
+}
+
+/// Bumped whenever `CacheEntry` changes shape; entries written with
+/// another version are treated as misses and rewritten
+const CACHE_VERSION: u32 = 1;
+
+/// How full, in percent of --cache-max-size, the cache is left after
+/// pruning it
+const CACHE_PRUNE_TARGET_PERCENT: u64 = 90;
 
 #[derive(Debug, Serialize, Deserialize)]
-struct ChatMessage {
-    role: String,
-    content: String,
+pub struct CacheEntry {
+    /// Missing in entries written before versioning, which read as 0
+    #[serde(default)]
+    pub version: u32,
+    pub content_hash: String,
+    pub summary: String,
+    /// Seconds since the epoch
+    pub timestamp: u64,
+    pub language: String,
+    pub summary_length: String,
+    /// How confident the model was in the summary, with --logprobs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ChatCompletion {
-    choices: Vec<Choice>,
+/// A model's reply, scored when --logprobs asked for token logprobs.
+struct Reply {
+    text: String,
+    confidence: Option<f64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Choice {
-    message: ChatMessage,
+impl Reply {
+    fn text(text: String) -> Self {
+        Self { text, confidence: None }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct CacheEntry {
-    content_hash: String,
+/// What `send_to` asks the backend for.
+#[derive(Clone, Copy)]
+enum ReplyFormat {
+    Text,
+    Json,
+    /// Text with its confidence
+    Scored,
+}
+
+/// A project's README summary, from `GPTClient::set_project_context`.
+struct ProjectContext {
+    /// Absolute path of the README
+    readme: PathBuf,
     summary: String,
-    timestamp: u64,
-    language: String,
-    summary_length: String,
+}
+
+/// What `inspect_cache` found for a file.
+#[derive(Debug)]
+pub struct CacheInspection {
+    pub content_hash: String,
+    /// Where the entry is, or would be
+    pub cache_path: PathBuf,
+    pub entry: Option<CacheEntry>,
+    /// Whether a summary with the current settings would use the entry;
+    /// an expired one, or one from an older version, is ignored
+    pub usable: bool,
+}
+
+/// Settings used to construct a `GPTClient`.
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    pub summary_length: String,
+    pub language: String,
+    /// Per-extension language overrides such as `rs=english,md=japanese`
+    pub language_map: Option<String>,
+    pub force_update: bool,
+    /// Neither read nor write the cache; takes precedence over `force_update`
+    pub bypass_cache: bool,
+    pub custom_ignore: Option<String>,
+    /// Comma-separated globs; when set only matching files are summarized
+    pub custom_include: Option<String>,
+    /// Match `custom_ignore` and `custom_include` regardless of case
+    pub ignore_case: bool,
+    /// Comma-separated extensions such as `map,min.js` to skip
+    pub exclude_extensions: Option<String>,
+    /// Comma-separated extensions; when set only files with one of them
+    /// are summarized
+    pub include_extensions: Option<String>,
+    /// Also skip what `.dockerignore` at the root of an explored path lists
+    pub respect_dockerignore: bool,
+    /// Also skip what `.npmignore` at the root of an explored path lists
+    pub respect_npmignore: bool,
+    pub max_retries: u32,
+    /// Connect and read timeout for API requests, in seconds
+    pub timeout_secs: u64,
+    pub provider: Provider,
+    pub model: String,
+    /// OpenAI-compatible API root such as `http://localhost:11434/v1`;
+    /// falls back to `OPENAI_BASE_URL`
+    pub base_url: Option<String>,
+    pub allow_unknown_model: bool,
+    /// Pass `language` (and language map values) through even when they
+    /// aren't known names or codes
+    pub allow_unknown_language: bool,
+    /// Cache entry lifetime in days; 0 keeps entries forever
+    pub cache_ttl_days: u64,
+    /// Delete the oldest cache entries once they take up more bytes than this
+    pub cache_max_size: Option<u64>,
+    /// Cache location; defaults to the platform cache directory
+    pub cache_dir: Option<PathBuf>,
+    /// File containing the API key, used when the provider's key variable is unset
+    pub api_key_file: Option<PathBuf>,
+    /// Estimate cost instead of calling the API; the cache is not touched
+    pub dry_run: bool,
+    /// Use the streaming API so summaries can be shown as they arrive
+    pub stream: bool,
+    /// Files larger than this many bytes are not summarized
+    pub max_file_size: u64,
+    /// Summarize oversized files section by section instead of refusing
+    pub chunk_large: bool,
+    /// Ask for one rewrite when a summary misses the requested length by
+    /// more than half
+    pub enforce_length: bool,
+    /// Log a HIT or MISS line for every cache lookup
+    pub verbose_cache: bool,
+    /// Summarize lock files and minified assets instead of skipping them
+    pub summarize_generated: bool,
+    /// Stop making requests once this many tokens (prompt plus reply
+    /// budget, estimated) would be exceeded
+    pub max_total_tokens: Option<u64>,
+    /// On a cache miss, translate a cached summary of the same content in
+    /// another language rather than summarizing it again
+    pub translate_from_cache: bool,
+    /// Summarize into the fields of a `StructuredSummary`, as JSON, instead
+    /// of prose. Answers to custom queries stay prose.
+    pub structured: bool,
+    /// Sampling temperature, from 0 up to the provider's maximum
+    pub temperature: f32,
+    /// Summarize zip and gzipped tar archives from their listing and text
+    /// entries; needs the `archives` feature
+    pub expand_archives: bool,
+    /// Summarize the readable text of HTML files rather than their markup
+    pub strip_html: bool,
+    /// Model to switch to when the API doesn't know `model` or denies
+    /// access to it
+    pub model_fallback: Option<String>,
+    /// Scale the `summary_length` preset by content size; not allowed
+    /// with smart or numeric lengths
+    pub adaptive_presets: bool,
+    /// Replace keys, tokens and passwords with `[REDACTED]` before any
+    /// content is sent
+    pub redact_secrets: bool,
+    /// Sent as a system message ahead of every request, e.g. a persona
+    pub system_prompt: Option<String>,
+    /// Replaces the built-in summary prompt; must contain `{content}` and
+    /// may use `{language}`, `{length}`, `{path}` and `{interpreter}`
+    pub prompt_template: Option<String>,
+    /// Requests per minute to stay under, counting retries
+    pub rpm: Option<u32>,
+    /// Note the files whose newly generated summary differs from the one
+    /// cached for them before, for `take_changed`
+    pub report_changes: bool,
+    /// Print every request's messages to stderr before sending them
+    pub print_prompt: bool,
+    /// Describe PNG, JPEG, GIF and WebP images by sending them to the model
+    pub vision: bool,
+    /// Flag file summaries when more than this fraction of the code they
+    /// mention can't be found in the file
+    pub validation_threshold: Option<f32>,
+    /// Summarize files over three times this size from their first,
+    /// middle and last this many bytes instead of reading them whole
+    pub head_tail_bytes: Option<u64>,
+    /// Ask for token logprobs and keep how confident the model was in
+    /// each summary. Only the OpenAI-compatible backend reports them.
+    pub logprobs: bool,
+    /// With `logprobs`, ask again with more explicit instructions when a
+    /// summary's confidence is below this
+    pub confidence_threshold: Option<f32>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            summary_length: "medium".to_string(),
+            language: "english".to_string(),
+            language_map: None,
+            force_update: false,
+            bypass_cache: false,
+            custom_ignore: None,
+            custom_include: None,
+            ignore_case: false,
+            exclude_extensions: None,
+            respect_dockerignore: false,
+            respect_npmignore: false,
+            include_extensions: None,
+            max_retries: DEFAULT_MAX_RETRIES,
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+            provider: Provider::OpenAI,
+            model: Provider::OpenAI.default_model().to_string(),
+            base_url: None,
+            allow_unknown_model: false,
+            allow_unknown_language: false,
+            cache_ttl_days: 0,
+            cache_max_size: None,
+            cache_dir: None,
+            api_key_file: None,
+            dry_run: false,
+            stream: false,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            chunk_large: false,
+            enforce_length: false,
+            verbose_cache: false,
+            summarize_generated: false,
+            max_total_tokens: None,
+            translate_from_cache: false,
+            structured: false,
+            temperature: DEFAULT_TEMPERATURE,
+            expand_archives: false,
+            strip_html: false,
+            model_fallback: None,
+            adaptive_presets: false,
+            redact_secrets: false,
+            system_prompt: None,
+            prompt_template: None,
+            rpm: None,
+            report_changes: false,
+            print_prompt: false,
+            vision: false,
+            validation_threshold: None,
+            head_tail_bytes: None,
+            logprobs: false,
+            confidence_threshold: None,
+        }
+    }
+}
+
+// Longer messages are cut short when printed with --print-prompt
+const PROMPT_PRINT_CHARS: usize = 2_000;
+
+/// Messages as --print-prompt shows them, each under its role and cut
+/// to PROMPT_PRINT_CHARS with a note of how much was left out.
+pub(crate) fn format_prompt(messages: &[ChatMessage]) -> String {
+    messages
+        .iter()
+        .map(|message| {
+            let shown: String = message.content.chars().take(PROMPT_PRINT_CHARS).collect();
+            let omitted = message.content.chars().count() - shown.chars().count();
+            match omitted {
+                0 => format!("--- {} ---\n{}\n", message.role, shown),
+                _ => format!("--- {} ---\n{}[...{} more chars]\n", message.role, shown, omitted),
+            }
+        })
+        .collect()
+}
+
+/// Outcome of `clean_cache`.
+#[derive(Debug, Default)]
+pub struct CacheCleanup {
+    pub removed: usize,
+    pub bytes_freed: u64,
+    /// What the remaining entries take up, counted by `prune_cache`
+    pub bytes_kept: u64,
+}
+
+/// Totals accumulated by a dry run.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct DryRunEstimate {
+    pub files: u64,
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+    pub cost: Option<f64>,
+}
+
+/// A summary, and where it came from: the cache, a request made by this
+/// run, or neither (notes such as "Empty file" and dry-run estimates).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub text: String,
+    pub from_cache: bool,
+    /// Requested from the API by this run
+    pub generated: bool,
+}
+
+impl Summary {
+    fn generated(text: String) -> Self {
+        Self { text, from_cache: false, generated: true }
+    }
+
+    fn cached(text: String) -> Self {
+        Self { text, from_cache: true, generated: false }
+    }
+
+    fn note(text: String) -> Self {
+        Self { text, from_cache: false, generated: false }
+    }
+}
+
+/// The history of follow-up questions about a single file.
+#[derive(Debug)]
+pub struct Conversation {
+    path: PathBuf,
+    messages: Vec<ChatMessage>,
+}
+
+impl Conversation {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), messages: Vec::new() }
+    }
+
+    pub fn messages(&self) -> &[ChatMessage] {
+        &self.messages
+    }
 }
 
 pub enum BatchResult {
-    Summaries(HashMap<String, String>),
+    /// Summaries by path, and the error for each file that failed
+    Summaries {
+        summaries: HashMap<String, String>,
+        errors: HashMap<String, String>,
+    },
     Answer(String),
 }
 
+/// The backend for --model-fallback.
+struct Fallback {
+    model: String,
+    backend: Box<dyn Summarizer>,
+}
+
 pub struct GPTClient {
-    api_key: String,
+    /// Shared with the backends
+    agent: ureq::Agent,
+    backend: Box<dyn Summarizer>,
+    fallback: Option<Fallback>,
+    /// Set once the primary model turned out to be unavailable; requests
+    /// then go straight to the fallback
+    primary_unavailable: AtomicBool,
     collected_contents: Mutex<Vec<(String, String)>>,
     cache_dir: PathBuf,
     max_tokens: u32,
     summary_length: String,
+    /// Token bounds of a `min-max` summary length
+    summary_range: Option<(u32, u32)>,
     language: String,
+    /// Lowercase extension -> summary language
+    language_map: HashMap<String, String>,
     force_update: bool,
-    gitignore: Option<Gitignore>,
+    bypass_cache: bool,
+    /// Parsed ignore files keyed by their path, loaded on first use
+    gitignores: Mutex<HashMap<PathBuf, Option<Gitignore>>>,
     custom_patterns: Option<Vec<String>>,
+    include_patterns: Option<Vec<String>>,
+    /// Whether `custom_patterns` and `include_patterns` ignore case
+    ignore_case: bool,
+    /// Lowercase, without the leading dot
+    exclude_extensions: Option<Vec<String>>,
+    include_extensions: Option<Vec<String>>,
+    /// Names of the ignore files to read at explored roots
+    root_ignore_files: Vec<&'static str>,
+    /// Those found so far, keyed by their path
+    root_ignores: Mutex<HashMap<PathBuf, Gitignore>>,
     smart_length: bool,
+    model: String,
+    base_url: Option<String>,
+    cache_ttl_days: u64,
+    cache_max_size: Option<u64>,
+    /// Bytes of cache entries as of the last scan plus those written
+    /// since, `None` before the first write
+    cache_bytes: Mutex<Option<u64>>,
+    dry_run: bool,
+    dry_run_files: AtomicU64,
+    dry_run_prompt_tokens: AtomicU64,
+    dry_run_output_tokens: AtomicU64,
+    stream: bool,
+    max_file_size: u64,
+    chunk_large: bool,
+    enforce_length: bool,
+    verbose_cache: bool,
+    api_calls: AtomicU64,
+    timeout_secs: u64,
+    summarize_generated: bool,
+    max_total_tokens: Option<u64>,
+    tokens_used: AtomicU64,
+    /// Prompt and reply tokens of the requests that succeeded, priced by
+    /// the model that answered
+    usage: Mutex<CostReport>,
+    translate_from_cache: bool,
+    structured: bool,
+    temperature: f32,
+    expand_archives: bool,
+    strip_html: bool,
+    /// Row of PRESET_SIZE_MULTIPLIERS with --adaptive-presets
+    adaptive_preset: Option<usize>,
+    redact_secrets: bool,
+    system_prompt: Option<String>,
+    prompt_template: Option<PromptTemplate>,
+    /// Files whose summary changed, with --only-changed-cache
+    changed: Option<Mutex<Vec<String>>>,
+    /// Loaded on first use
+    file_index: OnceCell<FileIndex>,
+    print_prompt: bool,
+    vision: bool,
+    validation_threshold: Option<f32>,
+    head_tail_bytes: Option<u64>,
+    logprobs: bool,
+    confidence_threshold: Option<f32>,
+    /// The README summary given to every other file's prompt, with
+    /// --readme-context
+    project_context: Option<ProjectContext>,
+    /// Set on Ctrl-C: summaries in flight finish but no new ones start
+    interrupted: Arc<AtomicBool>,
 }
 
 impl GPTClient {
-    pub fn new(summary_length: &str, language: &str, force_update: bool, custom_ignore: Option<&str>) -> Result<Self> {
-        let api_key = env::var("OPENAI_API_KEY")
-            .context("OPENAI_API_KEY environment variable is not set")?;
-            
-        // Check if we should use smart length or fixed length
-        let (smart_length, max_tokens) = if summary_length == "smart" {
-            (true, MEDIUM_SUMMARY_LENGTH) // Default to medium for initial setup
-        } else if let Ok(custom_length) = summary_length.parse::<u32>() {
-            (false, custom_length)
-        } else {
-            (false, match summary_length {
-                "short" => 50,
-                "long" => 200,
-                "super" => 500,
-                _ => 100, // medium or any other value
-            })
+    pub fn new(config: ClientConfig) -> Result<Self> {
+        let summary_length = config.summary_length.as_str();
+
+        let base_url = match config.provider {
+            Provider::OpenAI => config.base_url
+                .or_else(|| env::var("OPENAI_BASE_URL").ok())
+                .filter(|url| !url.trim().is_empty()),
+            Provider::Anthropic if config.base_url.is_some() => {
+                anyhow::bail!("--base-url is only supported with the openai provider")
+            }
+            Provider::Anthropic => None,
         };
 
-        // Create cache directory if it doesn't exist
-        let cache_dir = PathBuf::from(CACHE_DIR);
-        if !cache_dir.exists() {
-            fs::create_dir_all(&cache_dir)?;
+        // Local servers name their models freely, so only check against
+        // the known list when talking to the provider itself
+        let allow_unknown_model = config.allow_unknown_model || base_url.is_some();
+        validate_model(config.provider, &config.model, allow_unknown_model)?;
+        if let Some(fallback) = &config.model_fallback {
+            validate_model(config.provider, fallback, allow_unknown_model)?;
         }
-
-        // Initialize gitignore
-        let mut builder = GitignoreBuilder::new(".");
-        if Path::new(".gitignore").exists() {
-            builder.add(".gitignore");
+        validate_temperature(config.provider, config.temperature)?;
+        if config.report_changes && config.bypass_cache {
+            anyhow::bail!("--only-changed-cache compares against the cache, which --no-cache disables");
         }
-        let gitignore = builder.build().ok();
+        if config.validation_threshold.is_some_and(|threshold| !(0.0..=1.0).contains(&threshold)) {
+            anyhow::bail!("The validation threshold is a fraction between 0 and 1");
+        }
+        if config.validation_threshold.is_some() && (config.structured || config.stream) {
+            anyhow::bail!("--validate-summary appends a note, which --structured and --stream don't allow");
+        }
+        if config.confidence_threshold.is_some() && !config.logprobs {
+            anyhow::bail!("--resummarize-on-low-confidence needs --logprobs");
+        }
+        if config.confidence_threshold.is_some_and(|threshold| !(0.0..=1.0).contains(&threshold)) {
+            anyhow::bail!("The confidence threshold is a probability between 0 and 1");
+        }
+        if config.logprobs && config.stream {
+            anyhow::bail!("--logprobs scores whole replies, which --stream doesn't wait for");
+        }
+        if config.vision {
+            if config.structured {
+                anyhow::bail!("--vision describes images in prose, which --structured doesn't allow");
+            }
+            if let Some(model) = std::iter::once(&config.model).chain(&config.model_fallback).find(|model| !provider::reads_images(model)) {
+                anyhow::bail!("Model {} can't read images, which --vision needs", model);
+            }
+        }
+        if config.structured && config.prompt_template.is_some() {
+            anyhow::bail!("--prompt-template replaces the prompt --structured relies on");
+        }
+        if config.expand_archives && !cfg!(feature = "archives") {
+            anyhow::bail!("--expand-archives needs nexplorer built with the `archives` feature");
+        }
+
+        let config_file = utils::config_dir().map(|dir| dir.join("config.toml"));
+        let api_key = resolve_api_key(
+            config.provider,
+            env::var(config.provider.api_key_env()).ok(),
+            config.api_key_file.as_deref(),
+            config_file.as_deref(),
+        );
+        // A dry run never talks to the API, and local servers usually
+        // don't check keys, so neither needs one
+        let api_key = match api_key {
+            Err(_) if config.dry_run || base_url.is_some() => String::new(),
+            result => result?,
+        };
+            
+        // Check if we should use smart length or a fixed token budget
+        let parsed_length = parse_summary_length(summary_length)?;
+        let (smart_length, max_tokens) = match parsed_length {
+            SummaryLength::Smart => (true, MEDIUM_SUMMARY_LENGTH), // Default to medium for initial setup
+            SummaryLength::Tokens(tokens) => (false, tokens),
+            // The upper bound is the budget; the prompt asks for the range
+            SummaryLength::Range(_, max) => (false, max),
+            SummaryLength::Preset(preset) => (false, SUMMARY_PRESETS[preset].1),
+        };
+        let summary_range = match parsed_length {
+            SummaryLength::Range(min, max) => Some((min, max)),
+            _ => None,
+        };
+        let adaptive_preset = match (config.adaptive_presets, parsed_length) {
+            (true, SummaryLength::Preset(preset)) => Some(preset),
+            (true, _) => {
+                anyhow::bail!("--adaptive-presets needs a preset --summary-length (short, medium, long or super)")
+            }
+            (false, _) => None,
+        };
+
+        // A dry run writes nothing, so it doesn't create the cache either
+        let cache_dir = if config.dry_run {
+            cache_dir_path(config.cache_dir)
+        } else {
+            resolve_cache_dir(config.cache_dir)?
+        };
+        let language = language::normalize_language(&config.language, config.allow_unknown_language)?;
+        let language_map = config.language_map.as_deref()
+            .map(parse_language_map)
+            .transpose()?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(ext, name)| Ok((ext, language::normalize_language(&name, config.allow_unknown_language)?)))
+            .collect::<Result<HashMap<_, _>>>()?;
 
-        // Parse custom ignore patterns
-        let custom_patterns = custom_ignore.map(|patterns| {
-            patterns.split(',')
-                .map(|s| s.trim().to_string())
-                .collect::<Vec<String>>()
-        });
+        // Parse custom ignore and include patterns
+        let custom_patterns = config.custom_ignore.as_deref().map(parse_pattern_list);
+        let include_patterns = config.custom_include.as_deref().map(parse_pattern_list);
+
+        // One agent for every request, so connections are pooled
+        let timeout = Duration::from_secs(config.timeout_secs);
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(timeout)
+            .timeout_read(timeout)
+            .build();
+        // Shared by the primary and fallback backends, which draw on the
+        // same account's limit
+        let limiter = config.rpm.map(|rpm| Arc::new(RateLimiter::per_minute(rpm)));
+        let make_backend = |model: &str| -> Box<dyn Summarizer> {
+            match config.provider {
+                Provider::OpenAI => Box::new(OpenAIClient::new(
+                    agent.clone(),
+                    api_key.clone(),
+                    model.to_string(),
+                    config.max_retries,
+                    config.temperature,
+                    base_url.as_deref(),
+                ).with_rate_limiter(limiter.clone())),
+                Provider::Anthropic => Box::new(ClaudeClient::new(
+                    agent.clone(),
+                    api_key.clone(),
+                    model.to_string(),
+                    config.max_retries,
+                    config.temperature,
+                ).with_rate_limiter(limiter.clone())),
+            }
+        };
+        let backend = make_backend(&config.model);
+        let fallback = config.model_fallback.map(|model| Fallback { backend: make_backend(&model), model });
 
         Ok(Self {
-            api_key,
+            agent,
+            backend,
+            fallback,
+            primary_unavailable: AtomicBool::new(false),
             collected_contents: Mutex::new(Vec::new()),
             cache_dir,
             max_tokens,
             summary_length: summary_length.to_string(),
-            language: language.to_string(),
-            force_update,
-            gitignore,
+            summary_range,
+            language,
+            language_map,
+            force_update: config.force_update,
+            bypass_cache: config.bypass_cache,
+            gitignores: Mutex::new(HashMap::new()),
             custom_patterns,
+            include_patterns,
+            ignore_case: config.ignore_case,
+            exclude_extensions: config.exclude_extensions.as_deref().map(parse_extension_list),
+            root_ignore_files: [
+                (config.respect_dockerignore, DOCKERIGNORE_FILE),
+                (config.respect_npmignore, NPMIGNORE_FILE),
+            ]
+            .into_iter()
+            .filter_map(|(respected, file_name)| respected.then_some(file_name))
+            .collect(),
+            root_ignores: Mutex::new(HashMap::new()),
+            include_extensions: config.include_extensions.as_deref().map(parse_extension_list),
             smart_length,
+            base_url,
+            model: config.model,
+            cache_ttl_days: config.cache_ttl_days,
+            cache_max_size: config.cache_max_size,
+            cache_bytes: Mutex::new(None),
+            dry_run: config.dry_run,
+            dry_run_files: AtomicU64::new(0),
+            dry_run_prompt_tokens: AtomicU64::new(0),
+            dry_run_output_tokens: AtomicU64::new(0),
+            stream: config.stream,
+            max_file_size: config.max_file_size,
+            chunk_large: config.chunk_large,
+            enforce_length: config.enforce_length,
+            verbose_cache: config.verbose_cache,
+            api_calls: AtomicU64::new(0),
+            timeout_secs: config.timeout_secs,
+            summarize_generated: config.summarize_generated,
+            max_total_tokens: config.max_total_tokens,
+            tokens_used: AtomicU64::new(0),
+            usage: Mutex::new(CostReport::default()),
+            translate_from_cache: config.translate_from_cache,
+            structured: config.structured,
+            temperature: config.temperature,
+            expand_archives: config.expand_archives,
+            strip_html: config.strip_html,
+            adaptive_preset,
+            redact_secrets: config.redact_secrets,
+            system_prompt: config.system_prompt.filter(|prompt| !prompt.trim().is_empty()),
+            prompt_template: config.prompt_template.map(PromptTemplate::parse).transpose()?,
+            changed: config.report_changes.then(|| Mutex::new(Vec::new())),
+            file_index: OnceCell::new(),
+            print_prompt: config.print_prompt,
+            vision: config.vision,
+            validation_threshold: config.validation_threshold,
+            head_tail_bytes: config.head_tail_bytes.filter(|bytes| *bytes > 0),
+            logprobs: config.logprobs,
+            confidence_threshold: config.confidence_threshold,
+            project_context: None,
+            interrupted: Arc::new(AtomicBool::new(false)),
         })
     }
 
-    fn get_file_type_multiplier(&self, path: &Path) -> f32 {
-        // Only check by extension for supported file types
-        path.extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| {
-                FILE_TYPE_MULTIPLIERS
-                    .iter()
-                    .find(|&&(file_type, _)| file_type == ext)
-                    .map(|(_, multiplier)| *multiplier)
-                    .unwrap_or(1.0)
-            })
+    /// Length multiplier for the file's type, by extension. An extension
+    /// given another interpreter with --interpreter-override counts as the
+    /// extensions that interpreter normally has, and keeps its own entry
+    /// when there are none.
+    pub(crate) fn get_file_type_multiplier(&self, path: &Path) -> f32 {
+        let Some(ext) = path.extension().and_then(|ext| ext.to_str()) else {
+            return 1.0;
+        };
+        let lookup = |file_type: &str| {
+            FILE_TYPE_MULTIPLIERS
+                .iter()
+                .find(|&&(known, _)| known == file_type)
+                .map(|(_, multiplier)| *multiplier)
+        };
+        utils::interpreter_override(ext)
+            .and_then(|interpreter| utils::extensions_for_interpreter(&interpreter).iter().find_map(|known| lookup(known)))
+            .or_else(|| lookup(ext))
             .unwrap_or(1.0)
     }
 
-    fn calculate_summary_length(&self, file_size: u64, path: &Path) -> u32 {
-        if !self.smart_length {
+    /// Token budget for the summary of `content`, used as `max_tokens`.
+    pub(crate) fn calculate_summary_length(&self, content: &str, path: &Path) -> u32 {
+        // Piped content gets the default (medium) length even in smart
+        // mode, and the plain preset with --adaptive-presets
+        let adaptive = self.smart_length || self.adaptive_preset.is_some();
+        if !adaptive || path == Path::new(STDIN_NAME) {
             return self.max_tokens;
         }
 
-        let base_length = match file_size {
-            size if size <= TINY_FILE_SIZE => TINY_SUMMARY_LENGTH,
-            size if size <= SMALL_FILE_SIZE => SMALL_SUMMARY_LENGTH,
-            size if size <= MEDIUM_FILE_SIZE => MEDIUM_SUMMARY_LENGTH,
-            size if size <= LARGE_FILE_SIZE => LARGE_SUMMARY_LENGTH,
-            _ => VERY_LARGE_SUMMARY_LENGTH,
+        let tokens = tokenizer::count_tokens(&self.model, content);
+        let base_length = match self.adaptive_preset {
+            Some(preset) => adaptive_preset_length(preset, tokens),
+            None => SMART_SUMMARY_LENGTHS[size_bucket(tokens)],
         };
 
         // Apply file type multiplier
         let file_type_multiplier = self.get_file_type_multiplier(path);
         let length_with_type = (base_length as f32 * file_type_multiplier) as u32;
 
-        // Apply Japanese language multiplier if needed
-        if self.language.to_lowercase() == "japanese" {
-            (length_with_type as f32 * JAPANESE_MULTIPLIER) as u32
-        } else {
-            length_with_type
+        // Some languages need more tokens for the same summary
+        (length_with_type as f32 * language::length_multiplier(&self.content_language(path, content))) as u32
+    }
+
+    /// The summary language for `path`: its extension's entry in the
+    /// language map, or the global language.
+    pub(crate) fn language_for(&self, path: &Path) -> &str {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.language_map.get(&ext.to_lowercase()))
+            .unwrap_or(&self.language)
+    }
+
+    /// The summary language for `content` at `path`, detected from the
+    /// content itself with `--language auto`.
+    pub(crate) fn content_language(&self, path: &Path, content: &str) -> Cow<'_, str> {
+        language::resolve(self.language_for(path), content)
+    }
+
+    /// Give every file's prompt `summary`, the summary of the project's
+    /// README at `readme`, as context. The README itself is summarized
+    /// without it, so its summary stays the one the context came from.
+    pub fn set_project_context(&mut self, readme: &Path, summary: String) {
+        let readme = std::path::absolute(readme).unwrap_or_else(|_| readme.to_path_buf());
+        self.project_context = Some(ProjectContext { readme, summary });
+    }
+
+    /// Summarize files without a project context again.
+    pub fn clear_project_context(&mut self) {
+        self.project_context = None;
+    }
+
+    /// The project context for the prompt about `path`, if any.
+    fn project_context_for(&self, path: &Path) -> Option<&str> {
+        let context = self.project_context.as_ref()?;
+        let is_readme = std::path::absolute(path).is_ok_and(|path| path == context.readme);
+        (!is_readme).then_some(context.summary.as_str())
+    }
+
+    /// What the content hash of `content` at `path` is calculated from:
+    /// the content, preceded by the project context its prompt is given.
+    fn hashed_content<'a>(&self, path: &Path, content: &'a str) -> Cow<'a, str> {
+        match self.project_context_for(path) {
+            Some(context) => Cow::Owned(format!("{}\n{}", context, content)),
+            None => Cow::Borrowed(content),
         }
     }
 
-    fn calculate_content_hash(&self, content: &str, query: Option<&str>) -> String {
+    pub(crate) fn calculate_content_hash(&self, content: &str, query: Option<&str>, language: &str) -> String {
         use std::collections::hash_map::DefaultHasher;
         use std::hash::{Hash, Hasher};
         
@@ -242,20 +916,62 @@ impl GPTClient {
         content.hash(&mut hasher);
         query.unwrap_or("").hash(&mut hasher);
         self.summary_length.hash(&mut hasher);
-        self.language.hash(&mut hasher);
+        language.hash(&mut hasher);
+        self.active_model().hash(&mut hasher);
+        // Only hashed when set, so prose entries keep their hashes
+        if self.structured {
+            "structured".hash(&mut hasher);
+        }
+        // Likewise only hashed when changed from the default
+        if self.temperature != DEFAULT_TEMPERATURE {
+            self.temperature.to_bits().hash(&mut hasher);
+        }
+        if self.adaptive_preset.is_some() {
+            "adaptive".hash(&mut hasher);
+        }
+        if let Some(system_prompt) = &self.system_prompt {
+            system_prompt.hash(&mut hasher);
+        }
+        if let Some(template) = &self.prompt_template {
+            template.as_str().hash(&mut hasher);
+        }
         
         format!("{:x}", hasher.finish())
     }
 
-    fn get_cache_path(&self, content_hash: &str) -> PathBuf {
+    /// The model requests currently go to: the fallback once the primary
+    /// model was found unavailable. Summaries are cached under it.
+    pub(crate) fn active_model(&self) -> &str {
+        match &self.fallback {
+            Some(fallback) if self.primary_unavailable.load(Ordering::Relaxed) => &fallback.model,
+            _ => &self.model,
+        }
+    }
+
+    pub(crate) fn get_cache_path(&self, content_hash: &str) -> PathBuf {
         self.cache_dir.join(format!("{}.json", content_hash))
     }
 
-    fn get_from_cache(&self, content_hash: &str) -> Option<String> {
-        if self.force_update {
-            return None; // Skip cache when update flag is set
+    pub(crate) fn get_from_cache(&self, path: &Path, content_hash: &str, language: &str) -> Option<String> {
+        self.read_cache(&path.display().to_string(), content_hash, language, &self.summary_length)
+    }
+
+    /// Look up an entry written with the given language and summary length
+    /// (or marker, for entries that aren't single-file summaries). `label`
+    /// names the entry in --verbose-cache logs.
+    fn read_cache(&self, label: &str, content_hash: &str, language: &str, summary_length: &str) -> Option<String> {
+        if self.force_update || self.bypass_cache {
+            return None; // Skip cache when update or no-cache flag is set
+        }
+
+        let summary = self.load_cache_entry(content_hash, language, summary_length);
+        if self.verbose_cache {
+            info!("Cache {} {} ({})", if summary.is_some() { "HIT" } else { "MISS" }, label, content_hash);
         }
+        summary
+    }
 
+    fn load_cache_entry(&self, content_hash: &str, language: &str, summary_length: &str) -> Option<String> {
         let cache_path = self.get_cache_path(content_hash);
         if !cache_path.exists() {
             return None;
@@ -265,8 +981,17 @@ impl GPTClient {
             Ok(cache_str) => {
                 match serde_json::from_str::<CacheEntry>(&cache_str) {
                     Ok(entry) => {
+                        if entry.version != CACHE_VERSION {
+                            return None;
+                        }
+
+                        // Expired entries are regenerated and overwritten by add_to_cache
+                        if is_expired(entry.timestamp, self.cache_ttl_days) {
+                            return None;
+                        }
+
                         // Validate cache entry matches current settings
-                        if entry.language == self.language && entry.summary_length == self.summary_length {
+                        if entry.language == language && entry.summary_length == summary_length {
                             Some(entry.summary)
                         } else {
                             None
@@ -279,175 +1004,1733 @@ impl GPTClient {
         }
     }
 
-    fn add_to_cache(&self, content_hash: String, summary: String) -> Result<()> {
+    /// A cached summary of the same content written in another language,
+    /// with that language, for --translate-from-cache. Only the known
+    /// summary languages are looked up, one cache entry each.
+    pub(crate) fn get_from_cache_any_language(
+        &self,
+        content: &str,
+        custom_query: Option<&str>,
+        language: &str,
+    ) -> Option<(String, String)> {
+        if !self.translate_from_cache || self.force_update || self.bypass_cache {
+            return None;
+        }
+
+        language::known_languages()
+            .filter(|other| *other != language)
+            .find_map(|other| {
+                let content_hash = self.calculate_content_hash(content, custom_query, other);
+                self.load_cache_entry(&content_hash, other, &self.summary_length)
+                    .map(|summary| (other.to_string(), summary))
+            })
+    }
+
+    fn file_index(&self) -> &FileIndex {
+        self.file_index.get_or_init(|| FileIndex::load(&self.cache_dir))
+    }
+
+    /// Everything besides the content that decides a file's content hash,
+    /// so index entries from other settings aren't used.
+    fn index_settings(&self, custom_query: Option<&str>, language: &str) -> String {
+        let context = self.project_context.as_ref().map(|context| context.summary.as_str()).unwrap_or("");
+        format!(
+            "{}:{}:{}",
+            self.calculate_content_hash(context, custom_query, language), self.strip_html, self.redact_secrets
+        )
+    }
+
+    /// Write out the file index if this run added to it.
+    pub fn save_index(&self) -> Result<()> {
+        match self.file_index.get() {
+            Some(index) => index.save(),
+            None => Ok(()),
+        }
+    }
+
+    /// Cache a summary, returning whether it replaced a different one.
+    pub(crate) fn add_to_cache(&self, content_hash: String, summary: String, language: &str) -> Result<bool> {
+        self.write_cache(content_hash, summary, language, &self.summary_length)
+    }
+
+    /// Cache a generated reply along with its confidence.
+    fn add_reply_to_cache(&self, content_hash: String, reply: Reply, language: &str) -> Result<bool> {
+        self.write_cache_entry(content_hash, reply.text, reply.confidence, language, &self.summary_length)
+    }
+
+    /// Write a cache entry. `true` when an entry with another summary was
+    /// overwritten: a regenerated, expired or differently configured one.
+    /// Only checked when reporting changes; always `false` otherwise.
+    pub(crate) fn write_cache(
+        &self,
+        content_hash: String,
+        summary: String,
+        language: &str,
+        summary_length: &str,
+    ) -> Result<bool> {
+        self.write_cache_entry(content_hash, summary, None, language, summary_length)
+    }
+
+    fn write_cache_entry(
+        &self,
+        content_hash: String,
+        summary: String,
+        confidence: Option<f64>,
+        language: &str,
+        summary_length: &str,
+    ) -> Result<bool> {
+        if self.bypass_cache {
+            return Ok(false);
+        }
+        // Only --only-changed-cache needs the entry being replaced, so
+        // other writes skip reading it
+        let replaced = self.changed.is_some()
+            && self.stored_summary(&content_hash).is_some_and(|previous| previous != summary);
+
         let cache_entry = CacheEntry {
+            version: CACHE_VERSION,
             content_hash,
             summary,
             timestamp: SystemTime::now()
                 .duration_since(UNIX_EPOCH)?
                 .as_secs(),
-            language: self.language.clone(),
-            summary_length: self.summary_length.clone(),
+            language: language.to_string(),
+            summary_length: summary_length.to_string(),
+            confidence,
         };
 
+        // Written to a temp file and renamed into place, so a concurrent
+        // reader or writer never sees half an entry
         let cache_path = self.get_cache_path(&cache_entry.content_hash);
-        fs::write(
-            cache_path,
-            serde_json::to_string_pretty(&cache_entry)?,
-        )?;
+        let mut file = tempfile::NamedTempFile::new_in(&self.cache_dir)?;
+        let json = serde_json::to_string_pretty(&cache_entry)?;
+        file.write_all(json.as_bytes())?;
+        file.persist(&cache_path)
+            .with_context(|| format!("Failed to write cache entry {}", cache_path.display()))?;
 
-        Ok(())
+        self.limit_cache_size(json.len() as u64)?;
+        Ok(replaced)
     }
 
-    pub async fn summarize_file(&self, path: &Path, custom_query: Option<&str>) -> Result<Option<String>> {
-        if self.should_ignore(path) {
-            return Ok(None);
+    /// Keep the cache under --cache-max-size after writing `written` bytes.
+    /// The directory is scanned on the first write and then only when the
+    /// running total passes the limit, which prunes it down to
+    /// `CACHE_PRUNE_TARGET_PERCENT` of the limit so the writes after that
+    /// have room to spare.
+    fn limit_cache_size(&self, written: u64) -> Result<()> {
+        let Some(max_bytes) = self.cache_max_size else {
+            return Ok(());
+        };
+        let mut cache_bytes = self.cache_bytes.lock().unwrap();
+        let total = match *cache_bytes {
+            Some(total) => total + written,
+            // The entry just written is already on disk
+            None => prune_cache(&self.cache_dir, u64::MAX)?.bytes_kept,
+        };
+        if total <= max_bytes {
+            *cache_bytes = Some(total);
+            return Ok(());
         }
 
-        let file_info = utils::get_file_info(path)?;
-        if !file_info.is_text {
-            eprintln!("File type detected as binary: {}", path.display());
-            return Ok(None);
+        let pruned = prune_cache(&self.cache_dir, max_bytes / 100 * CACHE_PRUNE_TARGET_PERCENT)?;
+        if pruned.removed > 0 {
+            debug!("Pruned {} cache entries, freed {}", pruned.removed, utils::format_size(pruned.bytes_freed));
         }
+        *cache_bytes = Some(pruned.bytes_kept);
+        Ok(())
+    }
 
-        if file_info.size > MAX_FILE_SIZE {
-            return Ok(Some("File too large for summarization".to_string()));
-        }
+    /// The summary stored under `content_hash`, however old or configured.
+    fn stored_summary(&self, content_hash: &str) -> Option<String> {
+        let cache_str = fs::read_to_string(self.get_cache_path(content_hash)).ok()?;
+        serde_json::from_str::<CacheEntry>(&cache_str).ok().map(|entry| entry.summary)
+    }
 
-        let content = fs::read_to_string(path)?;
-        if content.trim().is_empty() {
-            return Ok(Some("Empty file".to_string()));
+    fn note_changed(&self, path: &Path) {
+        if let Some(changed) = &self.changed {
+            changed.lock().unwrap().push(path.display().to_string());
         }
+    }
 
-        let content_hash = self.calculate_content_hash(&content, custom_query);
+    /// The files whose summary changed since they were last summarized,
+    /// sorted, and forgotten once taken. `None` without --only-changed-cache.
+    pub fn take_changed(&self) -> Option<Vec<String>> {
+        let changed = self.changed.as_ref()?;
+        let mut changed = std::mem::take(&mut *changed.lock().unwrap());
+        changed.sort();
+        changed.dedup();
+        Some(changed)
+    }
 
-        // Check cache first
-        if let Some(cached_summary) = self.get_from_cache(&content_hash) {
-            return Ok(Some(cached_summary));
+    /// Whether `summarize_file` can produce a summary for this file type.
+    pub fn can_summarize(&self, path: &Path, file_info: &utils::FileInfo) -> bool {
+        file_info.is_text
+            || (cfg!(feature = "pdf") && is_pdf(path))
+            || (self.vision && utils::image_mime_type(path).is_some())
+            || (self.expand_archives && archive::is_archive(path))
+    }
+
+    /// `content` as it may be sent: with secrets redacted when
+    /// --redact-secrets is set.
+    fn redacted(&self, content: String) -> String {
+        if self.redact_secrets {
+            redact::redact(&content)
+        } else {
+            content
         }
+    }
 
-        // Calculate appropriate summary length based on file size and type
-        let summary_length = self.calculate_summary_length(file_info.size, path);
-        
-        // Generate new summary with dynamic length
-        let summary = self.get_gpt_summary(&content, custom_query, summary_length).await?;
+    /// Where summaries are cached.
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
 
-        // Add to cache
-        self.add_to_cache(content_hash, summary.clone())?;
-        Ok(Some(summary))
+    /// The HTTP agent requests go through, for reusing its connections
+    /// and timeouts.
+    pub fn agent(&self) -> &ureq::Agent {
+        &self.agent
     }
 
-    async fn get_gpt_summary(&self, content: &str, custom_prompt: Option<&str>, summary_length: u32) -> Result<String> {
-        let prompt = if let Some(query) = custom_prompt {
-            format!("{} (respond in {})
+    /// The custom OpenAI-compatible endpoint, if one is configured.
+    pub fn base_url(&self) -> Option<&str> {
+        self.base_url.as_deref()
+    }
 
-{}", query, self.language, content)
-        } else {
-            format!(
-                "Provide a detailed summary of the following file content in approximately {} words in {}.                 Focus on its main purpose, key elements, and important details:
+    pub fn is_streaming(&self) -> bool {
+        self.stream
+    }
 
-{}",
-                summary_length, self.language, content
-            )
-        };
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
 
-        self.make_gpt_request(&prompt, summary_length).await
+    pub fn is_verbose_cache(&self) -> bool {
+        self.verbose_cache
     }
 
-    async fn make_gpt_request(&self, prompt: &str, max_tokens: u32) -> Result<String> {
-        let response = ureq::post("https://api.openai.com/v1/chat/completions")
-            .set("Authorization", &format!("Bearer {}", self.api_key))
-            .set("Content-Type", "application/json")
-            .send_json(json!({
-                "model": MODEL,
-                "messages": [{
-                    "role": "user",
-                    "content": prompt
-                }],
-                "max_tokens": max_tokens,
-                "temperature": 0.7
-            }))?
-            .into_json::<ChatCompletion>()?;
+    /// Number of requests sent to the API so far.
+    pub fn api_calls(&self) -> u64 {
+        self.api_calls.load(Ordering::Relaxed)
+    }
 
-        Ok(response.choices[0].message.content.clone())
+    /// The flag a Ctrl-C handler sets to stop the run. Summaries already
+    /// in flight are finished and cached; nothing new is started.
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.interrupted)
     }
 
-    pub async fn collect_for_batch(&self, path: &Path) -> Result<()> {
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    /// Estimated tokens spent on requests so far, or `None` in a dry run.
+    pub fn tokens_used(&self) -> Option<u64> {
+        (!self.dry_run).then(|| self.tokens_used.load(Ordering::Relaxed))
+    }
+
+    /// What the requests made so far cost, or `None` in a dry run.
+    pub fn cost_report(&self) -> Option<CostReport> {
+        (!self.dry_run).then(|| *self.usage.lock().unwrap())
+    }
+
+    /// Count `tokens` against --max-total-tokens, refusing (and counting
+    /// nothing) when they would exceed it.
+    fn reserve_tokens(&self, tokens: u64) -> bool {
+        self.tokens_used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| match self.max_total_tokens {
+                Some(max) if used + tokens > max => None,
+                _ => Some(used + tokens),
+            })
+            .is_ok()
+    }
+
+    pub async fn summarize_file(&self, path: &Path, custom_query: Option<&str>) -> Result<Option<Summary>> {
+        note_exhausted_budget(self.summarize_file_with(path, custom_query, None).await)
+    }
+
+    /// Like `summarize_file`, but passes each piece of the summary to
+    /// `on_chunk` as it arrives when streaming is enabled. Cached summaries
+    /// are returned without calling `on_chunk`.
+    pub async fn summarize_file_streaming(
+        &self,
+        path: &Path,
+        custom_query: Option<&str>,
+        on_chunk: &mut OnChunk<'_>,
+    ) -> Result<Option<Summary>> {
+        note_exhausted_budget(self.summarize_file_with(path, custom_query, Some(on_chunk)).await)
+    }
+
+    /// Summarize only lines `range` of the text file at `path`. The prompt
+    /// says which lines these are, and the summary is cached on the lines'
+    /// content, so it survives edits elsewhere in the file.
+    pub async fn summarize_file_range(&self, path: &Path, range: LineRange, custom_query: Option<&str>) -> Result<Option<Summary>> {
+        note_exhausted_budget(self.summarize_range_with(path, range, custom_query).await)
+    }
+
+    async fn summarize_range_with(&self, path: &Path, range: LineRange, custom_query: Option<&str>) -> Result<Option<Summary>> {
         if self.should_ignore(path) {
-            return Ok(());
+            return Ok(None);
+        }
+        let file_info = utils::get_file_info(path)?;
+        if !file_info.is_text {
+            anyhow::bail!("{} isn't a text file", path.display());
+        }
+        let (excerpt, end) = range.read(BufReader::new(fs::File::open(path)?))?;
+        let excerpt = self.prepare_text(path, excerpt);
+        if excerpt.trim().is_empty() {
+            return Ok(Some(Summary::note("Empty lines".to_string())));
+        }
+        if excerpt.len() as u64 > self.max_file_size {
+            return Ok(Some(Summary::note(self.too_large_message())));
+        }
+
+        let language = &*self.content_language(path, &excerpt);
+        let summary_length = self.calculate_summary_length(&excerpt, path);
+        let prompt = format!(
+            "The following is an excerpt of {}: lines {}\u{2013}{} of the file. Summarize these lines rather than the whole file.\n\n{}",
+            path.display(), range.start, end,
+            self.build_prompt(path, &excerpt, custom_query, summary_length, language, file_info.interpreter.as_deref())
+        );
+        if self.dry_run {
+            return Ok(Some(Summary::note(self.record_dry_run(&prompt, summary_length))));
         }
 
+        let content_hash = self.calculate_content_hash(&self.hashed_content(path, &excerpt), custom_query, language);
+        let label = format!("{}:{}-{}", path.display(), range.start, end);
+        if let Some(summary) = self.read_cache(&label, &content_hash, language, LINE_RANGE_MARKER) {
+            return Ok(Some(Summary::cached(summary)));
+        }
+
+        let summary = self.make_gpt_request(&[ChatMessage::user(prompt)], summary_length, None).await?;
+        // Hashed again in case the request fell back to another model
+        let content_hash = self.calculate_content_hash(&self.hashed_content(path, &excerpt), custom_query, language);
+        self.write_cache(content_hash, summary.clone(), language, LINE_RANGE_MARKER)?;
+        Ok(Some(Summary::generated(summary)))
+    }
+
+    async fn summarize_file_with(
+        &self,
+        path: &Path,
+        custom_query: Option<&str>,
+        on_chunk: Option<&mut OnChunk<'_>>,
+    ) -> Result<Option<Summary>> {
+        if self.should_ignore(path) {
+            return Ok(None);
+        }
+
+        // A file that hasn't changed since it was summarized is matched to
+        // its cache entry without reading or hashing it
+        let language = self.language_for(path);
+        let settings = self.index_settings(custom_query, language);
+        // A detected language isn't known before the file is read
+        let stamp = if self.dry_run || self.bypass_cache || self.force_update || language == language::AUTO_LANGUAGE {
+            None
+        } else {
+            FileStamp::of(path)
+        };
+        if let Some(content_hash) = stamp.as_ref().and_then(|stamp| self.file_index().lookup(stamp, &settings)) {
+            if let Some(summary) = self.get_from_cache(path, &content_hash, language) {
+                let summary = match self.validation_threshold {
+                    Some(_) => self.validated(&self.load_text(path)?, Summary::cached(summary)),
+                    None => Summary::cached(summary),
+                };
+                return Ok(Some(summary));
+            }
+        }
+
+        // What the file was last summarized as, to tell whether its
+        // summary changed
+        let previous_hash = match &self.changed {
+            Some(_) => FileStamp::of(path).and_then(|stamp| self.file_index().recorded(&stamp, &settings)),
+            None => None,
+        };
+
         let file_info = utils::get_file_info(path)?;
-        if !file_info.is_text || file_info.size > MAX_FILE_SIZE {
-            return Ok(());
+
+        #[cfg(feature = "pdf")]
+        if is_pdf(path) {
+            if file_info.size > self.max_file_size {
+                return Ok(Some(Summary::note(self.too_large_message())));
+            }
+            let text = pdf_extract::extract_text(path)
+                .map_err(|e| anyhow::anyhow!("Failed to extract text from PDF: {}", e))?;
+            if text.trim().is_empty() {
+                return Ok(Some(Summary::note("Could not extract text from PDF".to_string())));
+            }
+            let text = self.redacted(text);
+            return self.summarize_text(path, &text, custom_query, Some("pdf"), on_chunk).await;
         }
 
-        if let Ok(content) = fs::read_to_string(path) {
-            if !content.trim().is_empty() {
-                let mut contents = self.collected_contents.lock().unwrap();
-                contents.push((
-                    path.to_string_lossy().into_owned(),
-                    content[..content.len().min(2000)].to_string(),
-                ));
+        if let Some(media_type) = utils::image_mime_type(path).filter(|_| self.vision) {
+            if file_info.size > self.max_file_size {
+                return Ok(Some(Summary::note(self.too_large_message())));
             }
+            return self.describe_image(path, media_type, custom_query, on_chunk).await.map(Some);
         }
 
-        Ok(())
-    }
+        #[cfg(feature = "archives")]
+        if self.expand_archives && archive::is_archive(path) {
+            let contents = archive::read(path, self.max_file_size)?;
+            if contents.entries.is_empty() {
+                return Ok(Some(Summary::note("Empty archive".to_string())));
+            }
+            let description = self.redacted(contents.describe(self.max_file_size as usize));
+            return self.summarize_text(path, &description, custom_query, Some("compressed archive"), on_chunk).await;
+        }
 
-    pub async fn summarize_batch(&self, custom_query: Option<&str>) -> Result<BatchResult> {
-        let contents = self.collected_contents.lock().unwrap();
-        
-        if contents.is_empty() {
-            return Ok(BatchResult::Summaries(HashMap::new()));
+        if !file_info.is_text {
+            eprintln!("File type detected as binary: {}", path.display());
+            return Ok(None);
         }
 
-        let combined_content = contents
-            .iter()
-            .map(|(path, content)| format!("File: {}
-Content:
-{}
+        if file_info.size > self.max_file_size && !self.chunk_large {
+            return Ok(Some(Summary::note(self.too_large_message())));
+        }
 
-", path, content))
-            .collect::<String>();
+        let content = self.load_text(path)?;
 
-        if let Some(query) = custom_query {
-            // For custom queries, return a direct answer
-            let response = self.get_gpt_summary(&combined_content, Some(query), 500).await?;
-            Ok(BatchResult::Answer(response))
-        } else {
-            // For regular batch summaries, return a map of file summaries
-            let mut summaries = HashMap::new();
-            for (path, _) in contents.iter() {
-                if let Some(summary) = self.summarize_file(Path::new(path), None).await? {
-                    summaries.insert(path.clone(), summary);
+        let result = self.summarize_content_with(path, &content, custom_query, file_info.interpreter.as_deref(), on_chunk).await;
+        if let Ok(Some(summary)) = &result {
+            if summary.from_cache || summary.generated {
+                let content_hash = self.calculate_content_hash(&self.hashed_content(path, &content), custom_query, language);
+                // A changed file gets a new entry, so compare with the old one
+                let changed = summary.generated && previous_hash.as_ref()
+                    .filter(|previous| **previous != content_hash)
+                    .and_then(|previous| self.stored_summary(previous))
+                    .is_some_and(|previous| previous != summary.text);
+                if changed {
+                    self.note_changed(path);
+                }
+                if let Some(stamp) = stamp {
+                    self.file_index().record(stamp, settings, content_hash);
                 }
             }
-            Ok(BatchResult::Summaries(summaries))
         }
+        result.map(|summary| summary.map(|summary| self.validated(&content, summary)))
     }
 
-    fn should_ignore(&self, path: &Path) -> bool {
-        // Check gitignore rules first
-        if let Some(ref gitignore) = self.gitignore {
-            if gitignore.matched(path, false).is_ignore() {
-                return true;
-            }
+    /// `summary` with a note appended when --validate-summary finds too
+    /// much of the code it mentions missing from `content`. The note isn't
+    /// cached, so it follows the current threshold.
+    fn validated(&self, content: &str, mut summary: Summary) -> Summary {
+        let note = self.validation_threshold
+            .filter(|_| summary.from_cache || summary.generated)
+            .and_then(|threshold| validate::inaccuracy_note(content, &summary.text, threshold));
+        if let Some(note) = note {
+            summary.text = format!("{}\n\n{}", summary.text, note);
         }
+        summary
+    }
 
-        // Then check custom ignore patterns
-        if let Some(ref patterns) = self.custom_patterns {
-            let path_str = path.to_string_lossy();
-            for pattern in patterns {
-                if glob::Pattern::new(pattern).ok()
-                    .map(|pat| pat.matches(&path_str))
-                    .unwrap_or(false)
-                {
-                    return true;
-                }
+    /// The text of a file as it is summarized and hashed for the cache.
+    fn load_text(&self, path: &Path) -> Result<String> {
+        // A sample of a large file is summarized as it is, since it
+        // wouldn't parse as a notebook, page or manifest
+        if let Some(window) = self.head_tail_bytes {
+            let size = fs::metadata(path)?.len();
+            if size > window.saturating_mul(3) {
+                let sample = utils::read_head_middle_tail(&mut fs::File::open(path)?, size, window)?;
+                return Ok(self.redacted(sample));
             }
         }
 
-        false
+        Ok(self.prepare_text(path, utils::read_text_lossy(path)?))
     }
-}
\ No newline at end of file
+
+    /// `content` of the file at `path` as it is summarized: the text of
+    /// notebooks, pages and manifests, and redacted. Content that doesn't
+    /// parse, such as an excerpt, is summarized as it is.
+    fn prepare_text(&self, path: &Path, mut content: String) -> String {
+        // Summarize what a notebook says rather than its JSON structure
+        if is_notebook(path) {
+            if let Some(cells) = notebook::cells_text(&content) {
+                content = cells;
+            }
+        } else if self.strip_html && is_html(path) {
+            if let Some(text) = html::to_text(&content) {
+                content = text;
+            }
+        } else if is_markdown(path) {
+            // Front matter is passed as hints rather than as raw YAML
+            if let Some(text) = markdown::with_hints(&content) {
+                content = text;
+            }
+        } else if let Some(manifest) = manifests::parse(path, &content) {
+            // Dependencies, entry points and scripts, for a summary of
+            // the project rather than of its manifest
+            content = manifest.describe();
+        }
+        self.redacted(content)
+    }
+
+    /// Look up the cache entry a summary of the text file at `path` would
+    /// use with the current settings, for cache-show.
+    pub fn inspect_cache(&self, path: &Path, custom_query: Option<&str>) -> Result<CacheInspection> {
+        let content = self.load_text(path)?;
+        let language = &*self.content_language(path, &content);
+        let content_hash = self.calculate_content_hash(&self.hashed_content(path, &content), custom_query, language);
+        let cache_path = self.get_cache_path(&content_hash);
+        let entry = fs::read_to_string(&cache_path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<CacheEntry>(&content).ok());
+        let usable = self.load_cache_entry(&content_hash, language, &self.summary_length).is_some();
+        Ok(CacheInspection { content_hash, cache_path, entry, usable })
+    }
+
+    /// Summarize content that didn't come from a file on disk, such as
+    /// stdin. `name` stands in for the path in the cache and the prompt
+    /// settings.
+    pub async fn summarize_content(&self, name: &str, content: &str, custom_query: Option<&str>) -> Result<Option<Summary>> {
+        let content = self.redacted(content.to_string());
+        note_exhausted_budget(self.summarize_content_with(Path::new(name), &content, custom_query, None, None).await)
+    }
+
+    async fn summarize_content_with(
+        &self,
+        path: &Path,
+        content: &str,
+        custom_query: Option<&str>,
+        interpreter: Option<&str>,
+        on_chunk: Option<&mut OnChunk<'_>>,
+    ) -> Result<Option<Summary>> {
+        if content.trim().is_empty() {
+            return Ok(Some(Summary::note("Empty file".to_string())));
+        }
+
+        // Generated content costs tokens without saying much
+        if !self.summarize_generated && (utils::is_lock_file(path) || utils::is_minified(content)) {
+            return Ok(Some(Summary::note("Lock/minified file skipped".to_string())));
+        }
+
+        if content.len() as u64 > self.max_file_size {
+            if !self.chunk_large {
+                return Ok(Some(Summary::note(self.too_large_message())));
+            }
+            return self.summarize_chunked(path, content, custom_query, interpreter, on_chunk).await;
+        }
+
+        self.summarize_text(path, content, custom_query, interpreter, on_chunk).await
+    }
+
+    /// Summarize each section of an oversized file, then combine the section
+    /// summaries in a second request. Sections are cached like small files
+    /// and the combined summary is cached under the whole file's hash.
+    async fn summarize_chunked(
+        &self,
+        path: &Path,
+        content: &str,
+        custom_query: Option<&str>,
+        interpreter: Option<&str>,
+        on_chunk: Option<&mut OnChunk<'_>>,
+    ) -> Result<Option<Summary>> {
+        let language = &*self.content_language(path, content);
+        let hashed = self.hashed_content(path, content);
+        let content_hash = self.calculate_content_hash(&hashed, custom_query, language);
+        if !self.dry_run {
+            if let Some(cached_summary) = self.get_from_cache(path, &content_hash, language) {
+                return Ok(Some(Summary::cached(cached_summary)));
+            }
+        }
+
+        let chunks = split_into_chunks(content, CHUNK_MAX_CHARS);
+        let mut sections = String::new();
+        for (index, chunk) in chunks.iter().enumerate() {
+            if let Some(summary) = self.summarize_text(path, chunk, None, interpreter, None).await? {
+                sections.push_str(&format!("Section {} of {}:\n{}\n\n", index + 1, chunks.len(), summary.text));
+            }
+        }
+
+        let summary_length = self.calculate_summary_length(content, path);
+        // Structured summaries are built from the sections like from a file
+        let combine_prompt = match custom_query {
+            Some(query) => Some(format!(
+                "The following are summaries of consecutive sections of one large file. Using them, answer: {}",
+                query
+            )),
+            None if self.structured => None,
+            None => Some(format!(
+                "The following are summaries of consecutive sections of one large file. Combine them into a single detailed summary of {} words, focusing on its main purpose, key elements, and important details",
+                self.word_target(summary_length)
+            )),
+        };
+
+        if self.dry_run {
+            let prompt = self.build_prompt(path, &sections, combine_prompt.as_deref(), summary_length, language, interpreter);
+            return Ok(Some(Summary::note(self.record_dry_run(&prompt, summary_length))));
+        }
+
+        let reply = self.get_gpt_summary(path, &sections, combine_prompt.as_deref(), summary_length, interpreter, on_chunk).await?;
+        let summary = reply.text.clone();
+        // Hashed again in case the request fell back to another model
+        let content_hash = self.calculate_content_hash(&hashed, custom_query, language);
+        if self.add_reply_to_cache(content_hash, reply, language)? {
+            self.note_changed(path);
+        }
+        Ok(Some(Summary::generated(summary)))
+    }
+
+    /// Summarize already-loaded content, going through the cache. `path`
+    /// only drives the summary length and language.
+    async fn summarize_text(
+        &self,
+        path: &Path,
+        content: &str,
+        custom_query: Option<&str>,
+        interpreter: Option<&str>,
+        on_chunk: Option<&mut OnChunk<'_>>,
+    ) -> Result<Option<Summary>> {
+        let language = &*self.content_language(path, content);
+        if self.dry_run {
+            let summary_length = self.calculate_summary_length(content, path);
+            let prompt = self.build_prompt(path, content, custom_query, summary_length, language, interpreter);
+            return Ok(Some(Summary::note(self.record_dry_run(&prompt, summary_length))));
+        }
+
+        let hashed = self.hashed_content(path, content);
+        let content_hash = self.calculate_content_hash(&hashed, custom_query, language);
+
+        // Check cache first
+        if let Some(cached_summary) = self.get_from_cache(path, &content_hash, language) {
+            return Ok(Some(Summary::cached(cached_summary)));
+        }
+
+        // Calculate appropriate summary length based on file size and type
+        let summary_length = self.calculate_summary_length(content, path);
+
+        // Translating an existing summary is much cheaper than sending the
+        // whole file again
+        let reply = match self.get_from_cache_any_language(&hashed, custom_query, language) {
+            Some((source_language, cached_summary)) => {
+                debug!("Translating cached {} summary of {}", source_language, path.display());
+                let structured = self.structured && custom_query.is_none();
+                Reply::text(self.translate_summary(&cached_summary, &source_language, language, summary_length, structured, on_chunk).await?)
+            }
+            None => self.get_gpt_summary(path, content, custom_query, summary_length, interpreter, on_chunk).await?,
+        };
+        let summary = reply.text.clone();
+
+        // Add to cache, under the model that actually answered
+        let content_hash = self.calculate_content_hash(&hashed, custom_query, language);
+        if self.add_reply_to_cache(content_hash, reply, language)? {
+            self.note_changed(path);
+        }
+        Ok(Some(Summary::generated(summary)))
+    }
+
+    /// Describe the image at `path` with --vision, sending it along with
+    /// the prompt. Cached under its encoded content like a text file.
+    async fn describe_image(
+        &self,
+        path: &Path,
+        media_type: &str,
+        custom_query: Option<&str>,
+        on_chunk: Option<&mut OnChunk<'_>>,
+    ) -> Result<Summary> {
+        // An image has no text to detect a language from, so auto is English
+        let language = &*self.content_language(path, "");
+        let image = provider::Image { media_type: media_type.to_string(), data: utils::base64_encode(&fs::read(path)?) };
+        let prompt = match custom_query {
+            Some(query) => format!("{} (respond in {})", query, language),
+            None => format!(
+                "Describe the attached image {} in {} words in {}: what it shows and what it is likely used for.",
+                path.display(), self.word_target(self.max_tokens), language
+            ),
+        };
+        if self.dry_run {
+            return Ok(Summary::note(self.record_dry_run(&prompt, self.max_tokens)));
+        }
+
+        let image_data = image.data.clone();
+        let content_hash = self.calculate_content_hash(&image_data, custom_query, language);
+        if let Some(summary) = self.get_from_cache(path, &content_hash, language) {
+            return Ok(Summary::cached(summary));
+        }
+        let summary = self.make_gpt_request(&[ChatMessage::user_with_image(prompt, image)], self.max_tokens, on_chunk).await?;
+        // Hashed again in case the request fell back to another model
+        let content_hash = self.calculate_content_hash(&image_data, custom_query, language);
+        if self.add_to_cache(content_hash, summary.clone(), language)? {
+            self.note_changed(path);
+        }
+        Ok(Summary::generated(summary))
+    }
+
+    fn too_large_message(&self) -> String {
+        format!("File too large for summarization (limit {})", utils::format_size(self.max_file_size))
+    }
+
+    /// `summary_length` is a token budget; the prompt asks for the number
+    /// of words that fits in it. A known `interpreter` is named so the
+    /// model doesn't have to guess the file type. A --prompt-template
+    /// replaces the built-in summary prompt, but not custom queries.
+    pub(crate) fn build_prompt(
+        &self,
+        path: &Path,
+        content: &str,
+        custom_prompt: Option<&str>,
+        summary_length: u32,
+        language: &str,
+        interpreter: Option<&str>,
+    ) -> String {
+        let prompt = self.build_file_prompt(path, content, custom_prompt, summary_length, language, interpreter);
+        match self.project_context_for(path) {
+            Some(context) => format!("{}{}\n\n{}", PROJECT_CONTEXT_INTRO, context, prompt),
+            None => prompt,
+        }
+    }
+
+    fn build_file_prompt(
+        &self,
+        path: &Path,
+        content: &str,
+        custom_prompt: Option<&str>,
+        summary_length: u32,
+        language: &str,
+        interpreter: Option<&str>,
+    ) -> String {
+        let file_type = interpreter
+            .map(|interpreter| format!("This is a {} file. ", interpreter))
+            .unwrap_or_default();
+        if let Some(query) = custom_prompt {
+            format!("{}{} (respond in {})
+
+{}", file_type, query, language, content)
+        } else if let Some(template) = &self.prompt_template {
+            template.render(content, language, tokenizer::tokens_to_words(summary_length), path, interpreter)
+        } else if self.structured {
+            structured::prompt(&file_type, content, &self.word_target(summary_length), language)
+        } else {
+            format!(
+                "{}Provide a detailed summary of the following file content in {} words in {}.                 Focus on its main purpose, key elements, and important details:
+
+{}",
+                file_type, self.word_target(summary_length), language, content
+            )
+        }
+    }
+
+    /// How many words a summary with a `summary_length` budget should
+    /// have: "approximately N", or "between N and M" for a range.
+    fn word_target(&self, summary_length: u32) -> String {
+        match self.summary_range {
+            Some((min, max)) => format!(
+                "between {} and {}", tokenizer::tokens_to_words(min), tokenizer::tokens_to_words(max)
+            ),
+            None => format!("approximately {}", tokenizer::tokens_to_words(summary_length)),
+        }
+    }
+
+    async fn get_gpt_summary(
+        &self,
+        path: &Path,
+        content: &str,
+        custom_prompt: Option<&str>,
+        summary_length: u32,
+        interpreter: Option<&str>,
+        on_chunk: Option<&mut OnChunk<'_>>,
+    ) -> Result<Reply> {
+        let language = &*self.content_language(path, content);
+        let prompt = self.build_prompt(path, content, custom_prompt, summary_length, language, interpreter);
+        if self.structured && custom_prompt.is_none() {
+            return self.make_structured_request(&[ChatMessage::user(prompt)], summary_length).await.map(Reply::text);
+        }
+
+        let mut messages = vec![ChatMessage::user(prompt.clone())];
+        let mut reply = self.make_scored_request(&messages, summary_length, on_chunk).await?;
+        if let (Some(confidence), Some(threshold)) = (reply.confidence, self.confidence_threshold) {
+            if confidence < threshold as f64 {
+                debug!("Summary of {} has confidence {:.2}, asking again", path.display(), confidence);
+                let explicit = format!("{}\n\n{}", prompt, LOW_CONFIDENCE_INSTRUCTIONS);
+                let retry = self.make_scored_request(&[ChatMessage::user(explicit)], summary_length, None).await?;
+                // Keep whichever reply the model was surer of
+                if retry.confidence > reply.confidence {
+                    reply = retry;
+                }
+            }
+        }
+        let summary = reply.text;
+
+        // Answers to custom queries have no target length; a range is
+        // aimed at its middle
+        let target = match self.summary_range {
+            Some((min, max)) => tokenizer::tokens_to_words((min + max) / 2),
+            None => tokenizer::tokens_to_words(summary_length),
+        };
+        if !self.enforce_length || custom_prompt.is_some()
+            || !tokenizer::is_far_from_target(tokenizer::count_words(&summary, language), target)
+        {
+            return Ok(Reply { text: summary, ..reply });
+        }
+
+        debug!("Summary missed the {}-word target, asking for a rewrite", target);
+        messages.push(ChatMessage::assistant(summary));
+        messages.push(ChatMessage::user(format!(
+            "Rewrite that summary in {} words in {}.", self.word_target(summary_length), language
+        )));
+        self.make_scored_request(&messages, summary_length, None).await
+    }
+
+    /// Translate `summary` from one summary language into another.
+    async fn translate_summary(
+        &self,
+        summary: &str,
+        from: &str,
+        to: &str,
+        summary_length: u32,
+        structured: bool,
+        on_chunk: Option<&mut OnChunk<'_>>,
+    ) -> Result<String> {
+        if structured {
+            let prompt = format!(
+                "Translate the values in the following JSON file summary from {} into {}, keeping its keys. Reply with the JSON object only:\n\n{}",
+                from, to, summary
+            );
+            return self.make_structured_request(&[ChatMessage::user(prompt)], summary_length).await;
+        }
+
+        let prompt = format!(
+            "Translate the following file summary from {} into {}. Reply with the translation only:\n\n{}",
+            from, to, summary
+        );
+        self.make_gpt_request(&[ChatMessage::user(prompt)], summary_length, on_chunk).await
+    }
+
+    /// Account for a request that a dry run would have made and describe it.
+    fn record_dry_run(&self, prompt: &str, summary_length: u32) -> String {
+        let system_tokens = self.system_prompt.as_deref().map_or(0, pricing::estimate_tokens);
+        let prompt_tokens = pricing::estimate_tokens(prompt) + system_tokens;
+        self.dry_run_files.fetch_add(1, Ordering::Relaxed);
+        self.dry_run_prompt_tokens.fetch_add(prompt_tokens, Ordering::Relaxed);
+        self.dry_run_output_tokens.fetch_add(summary_length as u64, Ordering::Relaxed);
+        format!("[dry run] summary length {} tokens, ~{} prompt tokens", summary_length, prompt_tokens)
+    }
+
+    /// Totals for a dry run, or `None` when requests are actually made.
+    pub fn dry_run_estimate(&self) -> Option<DryRunEstimate> {
+        if !self.dry_run {
+            return None;
+        }
+        let prompt_tokens = self.dry_run_prompt_tokens.load(Ordering::Relaxed);
+        let output_tokens = self.dry_run_output_tokens.load(Ordering::Relaxed);
+        Some(DryRunEstimate {
+            files: self.dry_run_files.load(Ordering::Relaxed),
+            prompt_tokens,
+            output_tokens,
+            cost: pricing::estimate_cost(&self.model, prompt_tokens, output_tokens),
+        })
+    }
+
+    async fn make_gpt_request(
+        &self,
+        messages: &[ChatMessage],
+        max_tokens: u32,
+        on_chunk: Option<&mut OnChunk<'_>>,
+    ) -> Result<String> {
+        self.send_request(messages, max_tokens, on_chunk, ReplyFormat::Text).await.map(|reply| reply.text)
+    }
+
+    /// Like `make_gpt_request`, scoring the reply with --logprobs.
+    async fn make_scored_request(
+        &self,
+        messages: &[ChatMessage],
+        max_tokens: u32,
+        on_chunk: Option<&mut OnChunk<'_>>,
+    ) -> Result<Reply> {
+        match self.logprobs {
+            true => self.send_request(messages, max_tokens, None, ReplyFormat::Scored).await,
+            false => self.send_request(messages, max_tokens, on_chunk, ReplyFormat::Text).await,
+        }
+    }
+
+    /// Request a `StructuredSummary` and return it validated, as JSON.
+    /// `summary_length` only budgets the purpose, so the keys and lists
+    /// get as much again.
+    async fn make_structured_request(&self, messages: &[ChatMessage], summary_length: u32) -> Result<String> {
+        let reply = self.send_request(messages, summary_length.saturating_mul(2), None, ReplyFormat::Json).await?;
+        structured::normalize_reply(&reply.text)
+    }
+
+    async fn send_request(
+        &self,
+        messages: &[ChatMessage],
+        max_tokens: u32,
+        mut on_chunk: Option<&mut OnChunk<'_>>,
+        format: ReplyFormat,
+    ) -> Result<Reply> {
+        let with_system: Vec<ChatMessage>;
+        let messages = match &self.system_prompt {
+            Some(system_prompt) => {
+                with_system = std::iter::once(ChatMessage::system(system_prompt.as_str()))
+                    .chain(messages.iter().cloned())
+                    .collect();
+                &with_system[..]
+            }
+            None => messages,
+        };
+
+        // The prompt goes to stderr, clear of the output on stdout
+        if self.print_prompt {
+            eprintln!("{}", format_prompt(messages));
+        } else if log::log_enabled!(log::Level::Debug) {
+            debug!("Prompt:\n{}", format_prompt(messages));
+        }
+
+        let prompt_tokens: u64 = messages.iter().map(|message| pricing::estimate_tokens(&message.content)).sum();
+        if !self.reserve_tokens(prompt_tokens + max_tokens as u64) {
+            return Err(BudgetExhausted.into());
+        }
+
+        let result = match &self.fallback {
+            Some(fallback) if self.primary_unavailable.load(Ordering::Relaxed) => {
+                self.send_to(fallback.backend.as_ref(), &fallback.model, messages, max_tokens, on_chunk, format).await
+            }
+            Some(fallback) => match self.send_to(self.backend.as_ref(), &self.model, messages, max_tokens, on_chunk.as_deref_mut(), format).await {
+                Err(err) if is_model_unavailable(&err) => {
+                    warn!("Model {} is unavailable ({}), falling back to {}", self.model, err, fallback.model);
+                    self.primary_unavailable.store(true, Ordering::Relaxed);
+                    self.send_to(fallback.backend.as_ref(), &fallback.model, messages, max_tokens, on_chunk, format).await
+                }
+                result => result,
+            },
+            None => self.send_to(self.backend.as_ref(), &self.model, messages, max_tokens, on_chunk, format).await,
+        };
+        result.map_err(|err| if is_timeout(&err) {
+            err.context(format!("Request timed out after {}s (raise it with --timeout)", self.timeout_secs))
+        } else {
+            err
+        })
+    }
+
+    /// Send `messages` to `backend`, which serves `model`, and count the
+    /// reply towards the cost report.
+    async fn send_to(
+        &self,
+        backend: &dyn Summarizer,
+        model: &str,
+        messages: &[ChatMessage],
+        max_tokens: u32,
+        on_chunk: Option<&mut OnChunk<'_>>,
+        format: ReplyFormat,
+    ) -> Result<Reply> {
+        self.api_calls.fetch_add(1, Ordering::Relaxed);
+        let reply = match (on_chunk.filter(|_| self.stream), format) {
+            (Some(on_chunk), _) => Reply::text(backend.chat_streaming(messages, max_tokens, on_chunk).await?),
+            (None, ReplyFormat::Json) => Reply::text(backend.chat_json(messages, max_tokens).await?),
+            (None, ReplyFormat::Scored) => {
+                let (text, confidence) = backend.chat_scored(messages, max_tokens).await?;
+                Reply { text, confidence }
+            }
+            (None, ReplyFormat::Text) => Reply::text(backend.chat(messages, max_tokens).await?),
+        };
+        let input_tokens = messages.iter().map(|message| pricing::estimate_tokens(&message.content)).sum();
+        self.usage.lock().unwrap().add(model, input_tokens, pricing::estimate_tokens(&reply.text));
+        Ok(reply)
+    }
+
+    /// Answer a follow-up question about the conversation's file. The first
+    /// question carries the file content; later ones rely on the history.
+    pub async fn ask(&self, conversation: &mut Conversation, question: &str) -> Result<String> {
+        let message = if conversation.messages.is_empty() {
+            let file_info = utils::get_file_info(&conversation.path)?;
+            if !file_info.is_text {
+                anyhow::bail!("{} is not a text file", conversation.path.display());
+            }
+            if file_info.size > self.max_file_size {
+                anyhow::bail!("{}", self.too_large_message());
+            }
+            let content = self.redacted(utils::read_text_lossy(&conversation.path)?);
+            let language = &*self.content_language(&conversation.path, &content);
+            self.build_prompt(&conversation.path, &content, Some(question), ANSWER_MAX_TOKENS, language, file_info.interpreter.as_deref())
+        } else {
+            question.to_string()
+        };
+
+        conversation.messages.push(ChatMessage::user(message));
+        match self.make_gpt_request(&conversation.messages, ANSWER_MAX_TOKENS, None).await {
+            Ok(answer) => {
+                conversation.messages.push(ChatMessage::assistant(answer.clone()));
+                Ok(answer)
+            }
+            Err(err) => {
+                // Drop the unanswered question so the history keeps alternating
+                conversation.messages.pop();
+                Err(err)
+            }
+        }
+    }
+
+    pub async fn collect_for_batch(&self, path: &Path) -> Result<()> {
+        if self.should_ignore(path) {
+            return Ok(());
+        }
+
+        let file_info = utils::get_file_info(path)?;
+        if !file_info.is_text || file_info.size > self.max_file_size {
+            return Ok(());
+        }
+
+        if let Ok(content) = utils::read_text_lossy(path) {
+            self.collect_content(&path.to_string_lossy(), &content);
+        }
+
+        Ok(())
+    }
+
+    /// Add content that didn't come from a file on disk to the batch.
+    pub fn collect_content(&self, name: &str, content: &str) {
+        if !content.trim().is_empty() {
+            let content = self.redacted(content.to_string());
+            let excerpt = content.char_indices().nth(2000).map_or(&content[..], |(end, _)| &content[..end]);
+            let mut contents = self.collected_contents.lock().unwrap();
+            contents.push((name.to_string(), excerpt.to_string()));
+        }
+    }
+
+    /// A one-line purpose for `dir`, built from the summaries of the files
+    /// directly inside it as `(file name, summary)` pairs.
+    pub async fn summarize_directory(&self, dir: &Path, children: &[(String, String)]) -> Result<Summary> {
+        let name = dir.file_name().unwrap_or(dir.as_os_str()).to_string_lossy();
+        let listing: String = children
+            .iter()
+            .map(|(file, summary)| format!("File: {}\nSummary: {}\n\n", file, summary))
+            .collect();
+        let language = &*language::resolve(&self.language, &listing);
+        let prompt = format!(
+            "The following are summaries of the files in the directory {}. In one line, describe the purpose of this directory in {}:\n\n{}",
+            name, language, listing
+        );
+        if self.dry_run {
+            return Ok(Summary::note(self.record_dry_run(&prompt, DIRECTORY_SUMMARY_TOKENS)));
+        }
+
+        let content_hash = self.calculate_content_hash(&prompt, None, language);
+        let label = dir.display().to_string();
+        if let Some(summary) = self.read_cache(&label, &content_hash, language, DIRECTORY_SUMMARY_MARKER) {
+            return Ok(Summary::cached(summary));
+        }
+
+        let summary = self.make_gpt_request(&[ChatMessage::user(prompt.as_str())], DIRECTORY_SUMMARY_TOKENS, None).await?;
+        let content_hash = self.calculate_content_hash(&prompt, None, language);
+        self.write_cache(content_hash, summary.clone(), language, DIRECTORY_SUMMARY_MARKER)?;
+        Ok(Summary::generated(summary))
+    }
+
+    /// Summarize what changed from the text file `old` to `new`, for
+    /// reviewing two versions of a file. Files that would be skipped when
+    /// exploring are refused, and the result is cached on both contents.
+    pub async fn compare_files(&self, old: &Path, new: &Path) -> Result<Summary> {
+        let old_content = self.comparable_text(old)?;
+        let new_content = self.comparable_text(new)?;
+        let language = &*self.content_language(new, &new_content);
+        let prompt = format!(
+            "The following are two versions of a file. Summarize the changes from the old version to the new one in {}: \
+            what was added, removed or changed and what that means for its behavior. Don't describe what stayed the same.\n\n\
+            --- Old version: {} ---\n{}\n\n--- New version: {} ---\n{}",
+            language, old.display(), old_content, new.display(), new_content
+        );
+        if self.dry_run {
+            return Ok(Summary::note(self.record_dry_run(&prompt, CHANGE_SUMMARY_TOKENS)));
+        }
+
+        let content_hash = self.comparison_hash(&old_content, &new_content, language);
+        let label = format!("{} -> {}", old.display(), new.display());
+        if let Some(summary) = self.read_cache(&label, &content_hash, language, CHANGE_SUMMARY_MARKER) {
+            return Ok(Summary::cached(summary));
+        }
+
+        let summary = self.make_gpt_request(&[ChatMessage::user(prompt.as_str())], CHANGE_SUMMARY_TOKENS, None).await?;
+        // Hashed again in case the request fell back to another model
+        let content_hash = self.comparison_hash(&old_content, &new_content, language);
+        self.write_cache(content_hash, summary.clone(), language, CHANGE_SUMMARY_MARKER)?;
+        Ok(Summary::generated(summary))
+    }
+
+    /// One side of a comparison, with the checks a summary of it would get.
+    fn comparable_text(&self, path: &Path) -> Result<String> {
+        let info = utils::get_file_info(path)?;
+        if self.should_ignore(path) {
+            anyhow::bail!("{} is ignored", path.display());
+        }
+        if !info.is_text {
+            anyhow::bail!("{} isn't a text file", path.display());
+        }
+        if info.size > self.max_file_size {
+            anyhow::bail!("{}: {}", path.display(), self.too_large_message());
+        }
+        self.load_text(path)
+    }
+
+    /// The cache key of a comparison: both contents' keys, in order.
+    fn comparison_hash(&self, old_content: &str, new_content: &str, language: &str) -> String {
+        let old_hash = self.calculate_content_hash(old_content, None, language);
+        let new_hash = self.calculate_content_hash(new_content, None, language);
+        self.calculate_content_hash(&format!("{}\n{}", old_hash, new_hash), Some(CHANGE_SUMMARY_MARKER), language)
+    }
+
+    /// Summarize the recent development activity that `commits` (newest
+    /// first, as `git_log::read_commits` returns them) show. The result is
+    /// cached on the commits' hashes, so new commits ask again.
+    pub async fn summarize_git_log(&self, commits: &[Commit]) -> Result<Summary> {
+        if commits.is_empty() {
+            anyhow::bail!("No commits to summarize");
+        }
+        let subjects: String = commits.iter().map(|commit| format!("{}\n", commit.subject)).collect();
+        let language = &*language::resolve(&self.language, &subjects);
+        let prompt = git_log::build_prompt(commits, language);
+        if self.dry_run {
+            return Ok(Summary::note(self.record_dry_run(&prompt, GIT_LOG_SUMMARY_TOKENS)));
+        }
+
+        let range: Vec<&str> = commits.iter().map(|commit| commit.hash.as_str()).collect();
+        let content_hash = self.calculate_content_hash(&range.join("\n"), Some(GIT_LOG_SUMMARY_MARKER), language);
+        let label = format!("{}..{}", commits[commits.len() - 1].hash, commits[0].hash);
+        if let Some(summary) = self.read_cache(&label, &content_hash, language, GIT_LOG_SUMMARY_MARKER) {
+            return Ok(Summary::cached(summary));
+        }
+
+        let summary = self.make_gpt_request(&[ChatMessage::user(prompt.as_str())], GIT_LOG_SUMMARY_TOKENS, None).await?;
+        // Hashed again in case the request fell back to another model
+        let content_hash = self.calculate_content_hash(&range.join("\n"), Some(GIT_LOG_SUMMARY_MARKER), language);
+        self.write_cache(content_hash, summary.clone(), language, GIT_LOG_SUMMARY_MARKER)?;
+        Ok(Summary::generated(summary))
+    }
+
+    pub async fn summarize_batch(&self, custom_query: Option<&str>) -> Result<BatchResult> {
+        let contents = self.collected_contents.lock().unwrap().clone();
+        
+        if contents.is_empty() {
+            return Ok(BatchResult::Summaries { summaries: HashMap::new(), errors: HashMap::new() });
+        }
+
+        let combined_content = combine_batch_contents(&contents);
+
+        if let Some(query) = custom_query {
+            let language = &*language::resolve(&self.language, &combined_content);
+            // For custom queries, return a direct answer
+            if self.dry_run {
+                let prompt = self.build_prompt(Path::new("batch"), &combined_content, Some(query), 500, language, None);
+                return Ok(BatchResult::Answer(self.record_dry_run(&prompt, 500)));
+            }
+
+            let content_hash = self.calculate_content_hash(&combined_content, Some(query), language);
+            if let Some(answer) = self.read_cache("batch", &content_hash, language, BATCH_ANSWER_MARKER) {
+                return Ok(BatchResult::Answer(answer));
+            }
+
+            let response = self.get_gpt_summary(Path::new("batch"), &combined_content, Some(query), 500, None, None).await?.text;
+            let content_hash = self.calculate_content_hash(&combined_content, Some(query), language);
+            self.write_cache(content_hash, response.clone(), language, BATCH_ANSWER_MARKER)?;
+            Ok(BatchResult::Answer(response))
+        } else {
+            // For regular batch summaries, return a map of file summaries.
+            // A file that fails is reported without losing the others.
+            let mut summaries = HashMap::new();
+            let mut errors = HashMap::new();
+            for (path, _) in contents.iter() {
+                // Keep what is done so far for an interrupted run
+                if self.is_interrupted() {
+                    break;
+                }
+                match self.summarize_file(Path::new(path), None).await {
+                    Ok(Some(summary)) => {
+                        summaries.insert(path.clone(), summary.text);
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        errors.insert(path.clone(), format!("{:#}", e));
+                    }
+                }
+            }
+            Ok(BatchResult::Summaries { summaries, errors })
+        }
+    }
+
+    pub(crate) fn should_ignore(&self, path: &Path) -> bool {
+        // Extensions are cheap to check, so they go first
+        if let Some(ref extensions) = self.include_extensions {
+            if !has_any_extension(extensions, path) {
+                return true;
+            }
+        }
+        if let Some(ref extensions) = self.exclude_extensions {
+            if has_any_extension(extensions, path) {
+                return true;
+            }
+        }
+
+        // Then .nexplorerignore and .gitignore rules
+        if self.is_ignored_by_files(path) {
+            return true;
+        }
+
+        // Files outside the include patterns are skipped
+        if let Some(ref patterns) = self.include_patterns {
+            if !matches_any_pattern(patterns, path, self.ignore_case) {
+                return true;
+            }
+        }
+
+        // Then check custom ignore patterns
+        if let Some(ref patterns) = self.custom_patterns {
+            if matches_any_pattern(patterns, path, self.ignore_case) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Read the --respect-dockerignore and --respect-npmignore files at
+    /// `root`, an explored directory, if it has them.
+    pub fn load_root_ignore_files(&self, root: &Path) {
+        let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        let mut root_ignores = self.root_ignores.lock().unwrap();
+        for file_name in &self.root_ignore_files {
+            let file = root.join(file_name);
+            if root_ignores.contains_key(&file) {
+                continue;
+            }
+            if let Some(gitignore) = load_gitignore(&root, &file) {
+                debug!("Respecting {}", file.display());
+                root_ignores.insert(file, gitignore);
+            }
+        }
+    }
+
+    /// `.nexplorerignore` rules decide first, so they can exclude (or
+    /// re-include) files regardless of what `.gitignore` says. Root ignore
+    /// files only add exclusions of their own.
+    fn is_ignored_by_files(&self, path: &Path) -> bool {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.ignore_file_decision(&path, NEXPLORERIGNORE_FILE)
+            .or_else(|| self.ignore_file_decision(&path, GITIGNORE_FILE))
+            .unwrap_or(false)
+            || self.is_ignored_by_root_files(&path)
+    }
+
+    fn is_ignored_by_root_files(&self, path: &Path) -> bool {
+        self.root_ignores.lock().unwrap().values().any(|gitignore| {
+            path.starts_with(gitignore.path())
+                && gitignore.matched_path_or_any_parents(path, false).is_ignore()
+        })
+    }
+
+    /// Apply the `file_name` ignore files from the file's directory up to
+    /// the repository root. As in git, a deeper file's decision (ignore or
+    /// whitelist) wins over shallower ones. `None` means no rule matched.
+    fn ignore_file_decision(&self, path: &Path, file_name: &str) -> Option<bool> {
+        let mut gitignores = self.gitignores.lock().unwrap();
+
+        for dir in path.ancestors().skip(1) {
+            let file = dir.join(file_name);
+            let gitignore = gitignores
+                .entry(file.clone())
+                .or_insert_with(|| load_gitignore(dir, &file));
+
+            if let Some(gitignore) = gitignore {
+                let matched = gitignore.matched_path_or_any_parents(path, false);
+                if matched.is_ignore() {
+                    return Some(true);
+                }
+                if matched.is_whitelist() {
+                    return Some(false);
+                }
+            }
+
+            if dir.join(".git").exists() {
+                break;
+            }
+        }
+        None
+    }
+}
+
+/// Returned instead of making a request that would exceed --max-total-tokens.
+#[derive(Debug)]
+pub(crate) struct BudgetExhausted;
+
+impl std::fmt::Display for BudgetExhausted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Token budget exhausted")
+    }
+}
+
+impl std::error::Error for BudgetExhausted {}
+
+/// A file skipped for lack of budget still gets a line in the output
+/// rather than an error.
+fn note_exhausted_budget(result: Result<Option<Summary>>) -> Result<Option<Summary>> {
+    match result {
+        Err(err) if err.is::<BudgetExhausted>() => Ok(Some(Summary::note(err.to_string()))),
+        result => result,
+    }
+}
+
+/// Whether the API answered 404, which both providers use for models that
+/// don't exist or aren't available to the key.
+pub(crate) fn is_model_unavailable(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<ureq::Error>(), Some(ureq::Error::Status(404, _)))
+}
+
+/// Whether a request failed because a connect or read timeout expired.
+pub(crate) fn is_timeout(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(|io| matches!(io.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock))
+}
+
+/// The prompt body for a batch: every collected file with its path.
+pub(crate) fn combine_batch_contents(contents: &[(String, String)]) -> String {
+    contents
+        .iter()
+        .map(|(path, content)| format!("File: {}
+Content:
+{}
+
+", path, content))
+        .collect()
+}
+
+fn is_html(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm"))
+        .unwrap_or(false)
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("mdx"))
+        .unwrap_or(false)
+}
+
+fn is_notebook(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("ipynb"))
+        .unwrap_or(false)
+}
+
+fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("pdf"))
+        .unwrap_or(false)
+}
+
+/// Split `content` into pieces of at most `max_len` bytes, breaking on line
+/// boundaries where possible and on character boundaries for long lines.
+pub(crate) fn split_into_chunks(content: &str, max_len: usize) -> Vec<&str> {
+    let max_len = max_len.max(1);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+
+    for line in content.split_inclusive('\n') {
+        if end > start && end - start + line.len() > max_len {
+            chunks.push(&content[start..end]);
+            start = end;
+        }
+        end += line.len();
+
+        while end - start > max_len {
+            let mut cut = start + max_len;
+            while !content.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            if cut == start {
+                // A single character wider than max_len
+                cut = start + content[start..].chars().next().map(char::len_utf8).unwrap_or(1);
+            }
+            chunks.push(&content[start..cut]);
+            start = cut;
+        }
+    }
+
+    if end > start {
+        chunks.push(&content[start..end]);
+    }
+    chunks
+}
+
+/// Content size bucket of `tokens`, from 0 (tiny) to 4 (very large).
+fn size_bucket(tokens: usize) -> usize {
+    [TINY_CONTENT_TOKENS, SMALL_CONTENT_TOKENS, MEDIUM_CONTENT_TOKENS, LARGE_CONTENT_TOKENS]
+        .iter()
+        .take_while(|&&threshold| tokens > threshold)
+        .count()
+}
+
+/// A --summary-length value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SummaryLength {
+    Smart,
+    /// A token budget
+    Tokens(u32),
+    /// Lower and upper token bounds, given as `min-max`
+    Range(u32, u32),
+    /// Row of `SUMMARY_PRESETS`
+    Preset(usize),
+}
+
+/// Parse a --summary-length. Names other than the presets and `smart`
+/// fall back to medium, but a malformed range is an error.
+pub(crate) fn parse_summary_length(value: &str) -> Result<SummaryLength> {
+    if value == "smart" {
+        return Ok(SummaryLength::Smart);
+    }
+    if let Ok(tokens) = value.parse() {
+        return Ok(SummaryLength::Tokens(tokens));
+    }
+    let Some((min, max)) = value.split_once('-') else {
+        return Ok(SummaryLength::Preset(preset_index(value)));
+    };
+    let (Ok(min), Ok(max)) = (min.trim().parse::<u32>(), max.trim().parse::<u32>()) else {
+        anyhow::bail!("Invalid --summary-length range '{}', expected min-max such as 100-200", value);
+    };
+    if min == 0 || min > max {
+        anyhow::bail!("Invalid --summary-length range '{}': the minimum must be above 0 and at most the maximum", value);
+    }
+    Ok(SummaryLength::Range(min, max))
+}
+
+/// Row of `SUMMARY_PRESETS` named `summary_length`; unknown names get
+/// medium, as they do without --adaptive-presets.
+fn preset_index(summary_length: &str) -> usize {
+    SUMMARY_PRESETS.iter()
+        .position(|&(name, _)| name == summary_length)
+        .unwrap_or(1)
+}
+
+/// Token budget of the preset in row `preset` for content of `tokens`
+/// tokens, before the file type and language multipliers.
+pub(crate) fn adaptive_preset_length(preset: usize, tokens: usize) -> u32 {
+    let multiplier = PRESET_SIZE_MULTIPLIERS[preset][size_bucket(tokens)];
+    (SUMMARY_PRESETS[preset].1 as f32 * multiplier) as u32
+}
+
+/// Parse the `ext=language` pairs given to --language-map.
+pub(crate) fn parse_language_map(mapping: &str) -> Result<HashMap<String, String>> {
+    utils::parse_extension_map(mapping, "--language-map")
+}
+
+/// Comma-separated patterns, with braces expanded. Commas inside braces
+/// separate alternatives, not patterns.
+fn parse_pattern_list(patterns: &str) -> Vec<String> {
+    split_outside_braces(patterns)
+        .into_iter()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .flat_map(expand_braces)
+        .collect()
+}
+
+/// Expand `{a,b}` alternatives the way shells do, so `*.{png,jpg}` becomes
+/// `*.png` and `*.jpg`. Alternatives may be empty or nested; a brace that
+/// is never closed is kept as it is.
+pub(crate) fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some((open, close)) = first_brace_group(pattern) else {
+        return vec![pattern.to_string()];
+    };
+    let (prefix, suffix) = (&pattern[..open], &pattern[close + 1..]);
+    split_outside_braces(&pattern[open + 1..close])
+        .into_iter()
+        .flat_map(|alternative| expand_braces(&format!("{}{}{}", prefix, alternative, suffix)))
+        .collect()
+}
+
+/// Where the first `{` with a matching `}` opens and closes.
+fn first_brace_group(pattern: &str) -> Option<(usize, usize)> {
+    let mut open = 0;
+    let mut depth = 0;
+    for (index, c) in pattern.char_indices() {
+        match c {
+            '{' => {
+                if depth == 0 {
+                    open = index;
+                }
+                depth += 1;
+            }
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((open, index));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn split_outside_braces(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut depth) = (0, 0usize);
+    for (index, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(&text[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Extensions as given to --include-extensions and --exclude-extensions:
+/// lowercase, with any leading dot dropped.
+fn parse_extension_list(extensions: &str) -> Vec<String> {
+    parse_pattern_list(extensions)
+        .into_iter()
+        .map(|extension| extension.trim_start_matches('.').to_lowercase())
+        .filter(|extension| !extension.is_empty())
+        .collect()
+}
+
+/// Whether the file name ends in one of `extensions`, ignoring case. An
+/// extension may span dots, as `min.js` does.
+fn has_any_extension(extensions: &[String], path: &Path) -> bool {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    extensions.iter().any(|extension| {
+        name.strip_suffix(extension.as_str())
+            .is_some_and(|stem| stem.len() > 1 && stem.ends_with('.'))
+    })
+}
+
+/// Match `path` against --include/--ignore globs the way .gitignore does:
+/// a pattern without a slash (`*.log`, `target`) matches any file or
+/// directory name along the path, and one with a slash (`src/*.rs`,
+/// `target/**`) matches from the start of the path, where `*` and `?`
+/// stay within one directory and `**` spans any number of them. Matching
+/// a directory covers everything under it.
+fn matches_any_pattern(patterns: &[String], path: &Path, ignore_case: bool) -> bool {
+    // Walking "." yields "./src/..." paths; match them like "src/..."
+    let path = path.strip_prefix(".").unwrap_or(path);
+    let options = glob::MatchOptions {
+        case_sensitive: !ignore_case,
+        require_literal_separator: true,
+        ..glob::MatchOptions::new()
+    };
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.strip_suffix('/').unwrap_or(pattern);
+        let Ok(glob) = glob::Pattern::new(pattern.strip_prefix('/').unwrap_or(pattern)) else {
+            return false;
+        };
+        if pattern.contains('/') {
+            path.ancestors().any(|ancestor| glob.matches_path_with(ancestor, options))
+        } else {
+            path.components().any(|component| glob.matches_with(&component.as_os_str().to_string_lossy(), options))
+        }
+    })
+}
+
+/// Parse an ignore file whose patterns are relative to `dir`.
+fn load_gitignore(dir: &Path, file: &Path) -> Option<Gitignore> {
+    if !file.is_file() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(dir);
+    if let Some(err) = builder.add(file) {
+        warn!("Failed to parse {}: {}", file.display(), err);
+    }
+    builder.build().ok()
+}
+
+/// Find the API key, preferring the provider's environment variable, then
+/// the `--api-key-file` flag, then the user config file (`api_key` for
+/// OpenAI, `anthropic_api_key` for Anthropic).
+pub(crate) fn resolve_api_key(
+    provider: Provider,
+    env_key: Option<String>,
+    key_file: Option<&Path>,
+    config_file: Option<&Path>,
+) -> Result<String> {
+    if let Some(key) = env_key.filter(|key| !key.trim().is_empty()) {
+        return Ok(key);
+    }
+
+    if let Some(path) = key_file {
+        let key = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read API key file {}", path.display()))?;
+        let key = key.trim();
+        if !key.is_empty() {
+            return Ok(key.to_string());
+        }
+    }
+
+    if let Some(path) = config_file.filter(|path| path.exists()) {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let user_config: UserConfig = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+        let key = match provider {
+            Provider::OpenAI => user_config.api_key,
+            Provider::Anthropic => user_config.anthropic_api_key,
+        };
+        if let Some(key) = key.filter(|key| !key.trim().is_empty()) {
+            return Ok(key.trim().to_string());
+        }
+    }
+
+    let mut searched = vec![format!("the {} environment variable", provider.api_key_env())];
+    if let Some(path) = key_file {
+        searched.push(format!("--api-key-file {}", path.display()));
+    }
+    if let Some(path) = config_file {
+        let field = match provider {
+            Provider::OpenAI => "api_key",
+            Provider::Anthropic => "anthropic_api_key",
+        };
+        searched.push(format!("{} in {}", field, path.display()));
+    }
+    anyhow::bail!("No {} API key found. Looked in: {}", provider.name(), searched.join(", "))
+}
+
+/// The cache directory: an explicit path, the platform cache dir, or
+/// `.cache` as a last resort.
+pub fn cache_dir_path(cache_dir: Option<PathBuf>) -> PathBuf {
+    cache_dir
+        .or_else(|| ProjectDirs::from("", "", APP_NAME).map(|dirs| dirs.cache_dir().to_path_buf()))
+        .unwrap_or_else(|| PathBuf::from(CACHE_DIR))
+}
+
+/// Pick the cache directory with `cache_dir_path` and make sure it exists.
+pub fn resolve_cache_dir(cache_dir: Option<PathBuf>) -> Result<PathBuf> {
+    let cache_dir = cache_dir_path(cache_dir);
+
+    if cache_dir.exists() {
+        if !cache_dir.is_dir() {
+            anyhow::bail!("Cache path {} exists but is not a directory", cache_dir.display());
+        }
+    } else {
+        fs::create_dir_all(&cache_dir)
+            .with_context(|| format!("Failed to create cache directory {}", cache_dir.display()))?;
+    }
+
+    Ok(cache_dir)
+}
+
+/// Whether a cache entry written at `timestamp` (seconds since the epoch)
+/// is older than `ttl_days`. A TTL of 0 never expires.
+pub(crate) fn is_expired(timestamp: u64, ttl_days: u64) -> bool {
+    ttl_days != 0 && is_older_than(timestamp, ttl_days)
+}
+
+fn is_older_than(timestamp: u64, days: u64) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    now.saturating_sub(timestamp) > days.saturating_mul(SECONDS_PER_DAY)
+}
+
+/// Remove cache entries from `cache_dir`: every entry when `older_than_days`
+/// is `None`, otherwise only entries older than that many days. Files that
+/// don't parse as a cache entry are left alone, and a missing directory
+/// has nothing to remove.
+pub fn clean_cache(cache_dir: &Path, older_than_days: Option<u64>) -> Result<CacheCleanup> {
+    let mut cleanup = CacheCleanup::default();
+    if !cache_dir.exists() {
+        return Ok(cleanup);
+    }
+
+    for entry in fs::read_dir(cache_dir)
+        .with_context(|| format!("Failed to read cache directory {}", cache_dir.display()))?
+    {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+
+        let cache_entry = match fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<CacheEntry>(&content).ok())
+        {
+            Some(cache_entry) => cache_entry,
+            None => {
+                debug!("Skipping unreadable cache file {}", path.display());
+                continue;
+            }
+        };
+
+        if let Some(days) = older_than_days {
+            if !is_older_than(cache_entry.timestamp, days) {
+                continue;
+            }
+        }
+
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        fs::remove_file(&path)?;
+        cleanup.removed += 1;
+        cleanup.bytes_freed += size;
+    }
+
+    Ok(cleanup)
+}
+
+/// Delete the oldest entries in `cache_dir` until the rest take up at most
+/// `max_bytes`. Files that don't parse as a cache entry are left alone and
+/// aren't counted.
+pub fn prune_cache(cache_dir: &Path, max_bytes: u64) -> Result<CacheCleanup> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(cache_dir)
+        .with_context(|| format!("Failed to read cache directory {}", cache_dir.display()))?
+    {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(cache_entry) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<CacheEntry>(&content).ok())
+        else {
+            continue;
+        };
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        entries.push((cache_entry.timestamp, size, path));
+    }
+
+    // Oldest first
+    entries.sort();
+    let mut cleanup = CacheCleanup { bytes_kept: entries.iter().map(|(_, size, _)| size).sum(), ..CacheCleanup::default() };
+    for (_, size, path) in entries {
+        if cleanup.bytes_kept <= max_bytes {
+            break;
+        }
+        // Another process may have removed it already
+        if fs::remove_file(&path).is_ok() {
+            cleanup.removed += 1;
+            cleanup.bytes_freed += size;
+        }
+        cleanup.bytes_kept -= size;
+    }
+    Ok(cleanup)
+}
+
+/// Reject temperatures outside the range the provider's API accepts.
+pub(crate) fn validate_temperature(provider: Provider, temperature: f32) -> Result<()> {
+    let max = provider.max_temperature();
+    if (0.0..=max).contains(&temperature) {
+        return Ok(());
+    }
+    anyhow::bail!("Temperature {} is out of range for {} (0.0 to {:.1})", temperature, provider.name(), max)
+}
+
+/// Reject model names the provider isn't known to offer unless explicitly
+/// allowed, which catches typos before any request is made.
+pub(crate) fn validate_model(provider: Provider, model: &str, allow_unknown: bool) -> Result<()> {
+    let known = provider.known_models();
+    if allow_unknown || known.contains(&model) {
+        return Ok(());
+    }
+    anyhow::bail!(
+        "Unknown {} model '{}'. Known models: {}. Use --allow-unknown-model for fine-tuned or newer models",
+        provider.name(),
+        model,
+        known.join(", ")
+    )
+}
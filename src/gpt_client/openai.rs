@@ -0,0 +1,267 @@
+use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use super::provider::{send_with_retry, ChatMessage, OnChunk, Summarizer};
+use super::rate_limit::RateLimiter;
+
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ChatCompletion {
+    choices: Vec<Choice>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApiError {
+    message: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+}
+
+/// A completion response body, which may carry an error object instead of
+/// choices.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum ChatResponse {
+    Error { error: ApiError },
+    Completion(ChatCompletion),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Choice {
+    message: ChatMessage,
+    /// Only sent when requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    logprobs: Option<Logprobs>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Logprobs {
+    #[serde(default)]
+    content: Vec<TokenLogprob>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TokenLogprob {
+    logprob: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChunk {
+    choices: Vec<StreamChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChoice {
+    delta: StreamDelta,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamDelta {
+    content: Option<String>,
+}
+
+/// A parsed server-sent event line from the streaming API.
+#[derive(Debug, PartialEq)]
+pub(crate) enum StreamEvent {
+    Delta(String),
+    Done,
+}
+
+/// Summarizer backed by OpenAI's chat completions API.
+pub struct OpenAIClient {
+    agent: ureq::Agent,
+    api_url: String,
+    api_key: String,
+    model: String,
+    max_retries: u32,
+    temperature: f32,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl OpenAIClient {
+    /// `base_url` points at an OpenAI-compatible server instead of OpenAI.
+    pub fn new(
+        agent: ureq::Agent,
+        api_key: String,
+        model: String,
+        max_retries: u32,
+        temperature: f32,
+        base_url: Option<&str>,
+    ) -> Self {
+        let api_url = chat_completions_url(base_url.unwrap_or(DEFAULT_BASE_URL));
+        Self { agent, api_url, api_key, model, max_retries, temperature, limiter: None }
+    }
+
+    /// Make every attempt, retries included, wait for `limiter`.
+    pub(crate) fn with_rate_limiter(mut self, limiter: Option<Arc<RateLimiter>>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    pub(crate) fn request_body(&self, messages: &[ChatMessage], max_tokens: u32) -> Value {
+        json!({
+            "model": self.model,
+            "messages": messages.iter().map(message_json).collect::<Vec<_>>(),
+            "max_tokens": max_tokens,
+            "temperature": self.temperature
+        })
+    }
+
+    async fn send(&self, body: Value) -> Result<ureq::Response> {
+        let api_key = self.api_key.clone();
+        let api_url = self.api_url.clone();
+        let agent = self.agent.clone();
+        send_with_retry("OpenAI", self.max_retries, self.limiter.as_deref(), move || {
+            let mut request = agent.post(&api_url).set("Content-Type", "application/json");
+            if !api_key.is_empty() {
+                request = request.set("Authorization", &format!("Bearer {}", api_key));
+            }
+            request.send_json(body.clone())
+                .map_err(Box::new)
+        })
+        .await
+    }
+
+    async fn complete(&self, body: Value) -> Result<String> {
+        let (parsed, raw) = self.respond(body).await?;
+        completion_text(parsed, &raw)
+    }
+
+    async fn respond(&self, body: Value) -> Result<(ChatResponse, String)> {
+        let response = self.send(body).await?;
+        let raw = tokio::task::spawn_blocking(move || response.into_string()).await??;
+        let parsed: ChatResponse = serde_json::from_str(&raw)
+            .with_context(|| format!("Unexpected OpenAI response: {}", raw))?;
+        Ok((parsed, raw))
+    }
+}
+
+#[async_trait]
+impl Summarizer for OpenAIClient {
+    async fn chat(&self, messages: &[ChatMessage], max_tokens: u32) -> Result<String> {
+        self.complete(self.request_body(messages, max_tokens)).await
+    }
+
+    async fn chat_scored(&self, messages: &[ChatMessage], max_tokens: u32) -> Result<(String, Option<f64>)> {
+        let mut body = self.request_body(messages, max_tokens);
+        body["logprobs"] = json!(true);
+        let (parsed, raw) = self.respond(body).await?;
+        let confidence = match &parsed {
+            ChatResponse::Completion(completion) => completion_confidence(completion),
+            ChatResponse::Error { .. } => None,
+        };
+        Ok((completion_text(parsed, &raw)?, confidence))
+    }
+
+    async fn chat_json(&self, messages: &[ChatMessage], max_tokens: u32) -> Result<String> {
+        let mut body = self.request_body(messages, max_tokens);
+        body["response_format"] = json!({ "type": "json_object" });
+        self.complete(body).await
+    }
+
+    async fn chat_streaming(
+        &self,
+        messages: &[ChatMessage],
+        max_tokens: u32,
+        on_chunk: &mut OnChunk<'_>,
+    ) -> Result<String> {
+        let mut body = self.request_body(messages, max_tokens);
+        body["stream"] = json!(true);
+        let response = self.send(body).await?;
+        read_stream(response, on_chunk).await
+    }
+}
+
+/// A message in the request body. One with an image has a list of content
+/// parts: the text, then the image as a data URL.
+fn message_json(message: &ChatMessage) -> Value {
+    match &message.image {
+        Some(image) => json!({
+            "role": message.role,
+            "content": [
+                { "type": "text", "text": message.content },
+                { "type": "image_url", "image_url": { "url": image.data_url() } },
+            ],
+        }),
+        None => json!({ "role": message.role, "content": message.content }),
+    }
+}
+
+/// The text of the first choice, or a descriptive error for API errors
+/// and responses without choices (e.g. content filter refusals).
+pub(crate) fn completion_text(response: ChatResponse, raw: &str) -> Result<String> {
+    match response {
+        ChatResponse::Error { error } => match error.kind {
+            Some(kind) => anyhow::bail!("OpenAI API error ({}): {}", kind, error.message),
+            None => anyhow::bail!("OpenAI API error: {}", error.message),
+        },
+        ChatResponse::Completion(completion) => completion.choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content)
+            .ok_or_else(|| anyhow::anyhow!("OpenAI response contained no choices: {}", raw)),
+    }
+}
+
+/// How likely the model found the first choice's tokens: the exponent of
+/// their mean logprob, which is the geometric mean of their probabilities.
+/// `None` when the response has no logprobs.
+pub(crate) fn completion_confidence(completion: &ChatCompletion) -> Option<f64> {
+    let tokens = &completion.choices.first()?.logprobs.as_ref()?.content;
+    if tokens.is_empty() {
+        return None;
+    }
+    let mean = tokens.iter().map(|token| token.logprob).sum::<f64>() / tokens.len() as f64;
+    Some(mean.exp())
+}
+
+/// The chat completions endpoint under `base_url`, with or without a
+/// trailing slash.
+pub(crate) fn chat_completions_url(base_url: &str) -> String {
+    format!("{}/chat/completions", base_url.trim().trim_end_matches('/'))
+}
+
+/// Read a server-sent event stream, forwarding each content delta to
+/// `on_chunk` and returning the assembled text.
+async fn read_stream(response: ureq::Response, on_chunk: &mut OnChunk<'_>) -> Result<String> {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let reader = tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+        for line in BufReader::new(response.into_reader()).lines() {
+            let text = match parse_stream_line(&line?) {
+                Some(StreamEvent::Delta(text)) => text,
+                Some(StreamEvent::Done) => break,
+                None => continue,
+            };
+            if sender.send(text).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    });
+
+    let mut text = String::new();
+    while let Some(chunk) = receiver.recv().await {
+        on_chunk(&chunk);
+        text.push_str(&chunk);
+    }
+    reader.await??;
+    Ok(text)
+}
+
+pub(crate) fn parse_stream_line(line: &str) -> Option<StreamEvent> {
+    let data = line.strip_prefix("data:")?.trim();
+    if data == "[DONE]" {
+        return Some(StreamEvent::Done);
+    }
+    let chunk: StreamChunk = serde_json::from_str(data).ok()?;
+    chunk.choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.delta.content)
+        .filter(|content| !content.is_empty())
+        .map(StreamEvent::Delta)
+}
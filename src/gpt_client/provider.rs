@@ -0,0 +1,240 @@
+use std::time::Duration;
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::ValueEnum;
+use log::warn;
+use super::rate_limit::RateLimiter;
+use serde::{Deserialize, Serialize};
+
+const INITIAL_BACKOFF_MS: u64 = 500;
+
+/// Sampling temperature sent with every request unless configured
+pub const DEFAULT_TEMPERATURE: f32 = 0.7;
+
+/// Chat models accepted for each provider without --allow-unknown-model
+const OPENAI_MODELS: &[&str] = &[
+    "gpt-4o",
+    "gpt-4o-mini",
+    "gpt-4-turbo",
+    "gpt-4",
+    "gpt-3.5-turbo",
+    "o1",
+    "o1-mini",
+];
+const ANTHROPIC_MODELS: &[&str] = &[
+    "claude-3-5-haiku-latest",
+    "claude-3-5-sonnet-latest",
+    "claude-3-7-sonnet-latest",
+    "claude-3-opus-latest",
+    "claude-sonnet-4-0",
+    "claude-opus-4-0",
+];
+/// Known models that can't read images, refused with --vision
+const TEXT_ONLY_MODELS: &[&str] = &["gpt-4", "gpt-3.5-turbo", "o1-mini"];
+
+/// Receives pieces of a completion as they are streamed.
+pub type OnChunk<'a> = dyn FnMut(&str) + Send + 'a;
+
+/// One turn of a conversation, in the role/content shape both APIs use.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+    /// Sent along with `content` by backends that read images
+    #[serde(skip)]
+    pub image: Option<Image>,
+}
+
+/// An image attached to a message, base64-encoded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub media_type: String,
+    pub data: String,
+}
+
+impl Image {
+    /// The image as a `data:` URL.
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self { role: "system".to_string(), content: content.into(), image: None }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: "user".to_string(), content: content.into(), image: None }
+    }
+
+    /// A question about `image`.
+    pub fn user_with_image(content: impl Into<String>, image: Image) -> Self {
+        Self { image: Some(image), ..Self::user(content) }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self { role: "assistant".to_string(), content: content.into(), image: None }
+    }
+}
+
+/// A backend that turns a conversation into a reply.
+#[async_trait]
+pub trait Summarizer: Send + Sync {
+    /// Reply to `messages`, which alternate user and assistant turns and
+    /// end with a user turn, after an optional system message.
+    async fn chat(&self, messages: &[ChatMessage], max_tokens: u32) -> Result<String>;
+
+    /// Like `chat`, passing pieces of the reply to `on_chunk` as they
+    /// arrive. Backends without streaming deliver it in one piece.
+    async fn chat_streaming(
+        &self,
+        messages: &[ChatMessage],
+        max_tokens: u32,
+        on_chunk: &mut OnChunk<'_>,
+    ) -> Result<String> {
+        let text = self.chat(messages, max_tokens).await?;
+        on_chunk(&text);
+        Ok(text)
+    }
+
+    /// Like `chat`, for a prompt asking for a single JSON object. Backends
+    /// with a JSON mode turn it on; others rely on the prompt alone.
+    async fn chat_json(&self, messages: &[ChatMessage], max_tokens: u32) -> Result<String> {
+        self.chat(messages, max_tokens).await
+    }
+
+    /// Like `chat`, also returning how confident the model was in the
+    /// reply, from 0 to 1. Backends without token logprobs return `None`.
+    async fn chat_scored(&self, messages: &[ChatMessage], max_tokens: u32) -> Result<(String, Option<f64>)> {
+        Ok((self.chat(messages, max_tokens).await?, None))
+    }
+
+    async fn summarize(&self, prompt: &str, max_tokens: u32) -> Result<String> {
+        self.chat(&[ChatMessage::user(prompt)], max_tokens).await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Provider {
+    /// OpenAI chat completions API
+    #[default]
+    #[value(name = "openai")]
+    OpenAI,
+    /// Anthropic messages API
+    Anthropic,
+}
+
+impl Provider {
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::OpenAI => "OpenAI",
+            Provider::Anthropic => "Anthropic",
+        }
+    }
+
+    pub fn default_model(self) -> &'static str {
+        match self {
+            Provider::OpenAI => "gpt-4o-mini",
+            Provider::Anthropic => "claude-3-5-haiku-latest",
+        }
+    }
+
+    pub(crate) fn known_models(self) -> &'static [&'static str] {
+        match self {
+            Provider::OpenAI => OPENAI_MODELS,
+            Provider::Anthropic => ANTHROPIC_MODELS,
+        }
+    }
+
+    /// Highest sampling temperature the provider's API accepts.
+    pub(crate) fn max_temperature(self) -> f32 {
+        match self {
+            Provider::OpenAI => 2.0,
+            Provider::Anthropic => 1.0,
+        }
+    }
+
+    pub(crate) fn api_key_env(self) -> &'static str {
+        match self {
+            Provider::OpenAI => "OPENAI_API_KEY",
+            Provider::Anthropic => "ANTHROPIC_API_KEY",
+        }
+    }
+}
+
+/// Whether `model` can be sent images. Models that aren't known to be
+/// text-only are given the benefit of the doubt.
+pub(crate) fn reads_images(model: &str) -> bool {
+    !TEXT_ONLY_MODELS.contains(&model)
+}
+
+/// Send a request built by `send`, retrying rate limits and transient
+/// failures with backoff. Every attempt, retries included, waits for the
+/// `limiter` first. `service` only appears in log and error messages.
+pub(crate) async fn send_with_retry<F>(
+    service: &str,
+    max_retries: u32,
+    limiter: Option<&RateLimiter>,
+    send: F,
+) -> Result<ureq::Response>
+where
+    F: Fn() -> Result<ureq::Response, Box<ureq::Error>> + Clone + Send + 'static,
+{
+    let mut attempt = 0;
+    loop {
+        if let Some(limiter) = limiter {
+            limiter.acquire().await;
+        }
+        // ureq is blocking, so run it off the async workers to let
+        // concurrent summaries overlap
+        let result = tokio::task::spawn_blocking(send.clone()).await?;
+
+        let err = match result {
+            Ok(response) => return Ok(response),
+            Err(err) => *err,
+        };
+
+        if attempt >= max_retries || !is_retryable(&err) {
+            return Err(err.into());
+        }
+        attempt += 1;
+
+        let delay = retry_after(&err).unwrap_or_else(|| backoff_delay(attempt));
+        warn!("{} request failed ({}), retrying in {:?} (attempt {}/{})",
+            service, err, delay, attempt, max_retries);
+
+        // Keep Ctrl-C responsive while waiting for the next attempt
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = tokio::signal::ctrl_c() => {
+                anyhow::bail!("Interrupted while waiting to retry {} request", service);
+            }
+        }
+    }
+}
+
+/// Rate limits, server errors and transport failures are worth retrying;
+/// other client errors (400, 401, ...) will fail the same way again.
+pub(crate) fn is_retryable(err: &ureq::Error) -> bool {
+    match err {
+        ureq::Error::Status(code, _) => *code == 429 || (500..600).contains(code),
+        ureq::Error::Transport(_) => true,
+    }
+}
+
+/// Delay requested by the server through the `Retry-After` header, in seconds.
+pub(crate) fn retry_after(err: &ureq::Error) -> Option<Duration> {
+    match err {
+        ureq::Error::Status(_, response) => response
+            .header("Retry-After")
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs),
+        ureq::Error::Transport(_) => None,
+    }
+}
+
+/// Exponential backoff for the given (1-based) retry attempt.
+pub(crate) fn backoff_delay(attempt: u32) -> Duration {
+    Duration::from_millis(INITIAL_BACKOFF_MS.saturating_mul(1 << attempt.saturating_sub(1).min(16)))
+}
@@ -0,0 +1,33 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Spaces requests evenly to stay under a requests-per-minute cap. The
+/// bucket holds a single token, so there are no bursts at the start that
+/// would trip the cap before the minute is over.
+pub(crate) struct RateLimiter {
+    interval: Duration,
+    /// When the next request may go out
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub(crate) fn per_minute(rpm: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(60) / rpm.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for this request's turn. Turns are handed out in call order,
+    /// so concurrent callers queue up rather than race.
+    pub(crate) async fn acquire(&self) {
+        let turn = {
+            let mut next = self.next.lock().unwrap();
+            let turn = (*next).max(Instant::now());
+            *next = turn + self.interval;
+            turn
+        };
+        tokio::time::sleep_until(turn).await;
+    }
+}
@@ -0,0 +1,64 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// The fields requested with --structured. Serialized to JSON, this is the
+/// summary text that gets cached and reported.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredSummary {
+    pub purpose: String,
+    #[serde(default)]
+    pub key_components: Vec<String>,
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    #[serde(default)]
+    pub risks: Vec<String>,
+}
+
+impl StructuredSummary {
+    /// Read back a summary written with --structured; prose summaries
+    /// give `None`.
+    pub fn parse(summary: &str) -> Option<Self> {
+        serde_json::from_str(summary).ok()
+    }
+
+    /// Labelled fields for display, leaving out empty lists.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("Purpose", self.purpose.clone())];
+        for (label, items) in [
+            ("Key components", &self.key_components),
+            ("Dependencies", &self.dependencies),
+            ("Risks", &self.risks),
+        ] {
+            if !items.is_empty() {
+                fields.push((label, items.join(", ")));
+            }
+        }
+        fields
+    }
+}
+
+/// The prompt asking for a `StructuredSummary` of `content` whose purpose
+/// has `words` words, e.g. "approximately 75".
+pub(crate) fn prompt(file_type: &str, content: &str, words: &str, language: &str) -> String {
+    format!(
+        "{}Summarize the following file content as a JSON object with exactly these keys: \
+        \"purpose\" (a string of {} words), and \"key_components\", \"dependencies\" \
+        and \"risks\" (arrays of short strings). Write the values in {} and reply with the JSON object only:
+
+{}",
+        file_type, words, language, content
+    )
+}
+
+/// Check that `reply` holds a `StructuredSummary` and return it as compact
+/// JSON. Models without a JSON mode sometimes wrap it in a code fence.
+pub(crate) fn normalize_reply(reply: &str) -> Result<String> {
+    let json = reply.trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    let summary: StructuredSummary = serde_json::from_str(json)
+        .with_context(|| format!("Structured summary did not match the schema: {}", reply))?;
+    Ok(serde_json::to_string(&summary)?)
+}
@@ -0,0 +1,48 @@
+use std::path::Path;
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\{(content|language|length|path|interpreter)\}").unwrap()
+});
+
+/// A --prompt-template: the summary prompt with `{content}`, `{language}`,
+/// `{length}` (in words), `{path}` and `{interpreter}` placeholders.
+#[derive(Debug, Clone)]
+pub(crate) struct PromptTemplate(String);
+
+impl PromptTemplate {
+    /// Check that the template has somewhere to put the content.
+    pub(crate) fn parse(template: String) -> Result<Self> {
+        if !template.contains("{content}") {
+            anyhow::bail!("Prompt template has no {{content}} placeholder");
+        }
+        Ok(Self(template))
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Fill in the placeholders in one pass, so placeholder-like text in
+    /// the content is left alone. An unknown interpreter is "unknown".
+    pub(crate) fn render(
+        &self,
+        content: &str,
+        language: &str,
+        words: u32,
+        path: &Path,
+        interpreter: Option<&str>,
+    ) -> String {
+        PLACEHOLDER
+            .replace_all(&self.0, |captures: &Captures| match &captures[1] {
+                "content" => content.to_string(),
+                "language" => language.to_string(),
+                "length" => words.to_string(),
+                "path" => path.display().to_string(),
+                _ => interpreter.unwrap_or("unknown").to_string(),
+            })
+            .into_owned()
+    }
+}
@@ -0,0 +1,58 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Fraction of a summary's code references that may be missing from the
+/// file before it is flagged
+pub const DEFAULT_VALIDATION_THRESHOLD: f32 = 0.3;
+
+// What reads as a reference to code: a backticked span, a call, a
+// snake_case or camelCase name, or a file name with an extension
+static REFERENCE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"`(?P<quoted>[^`\s]+)`",
+        r"|\b(?P<call>[A-Za-z_]\w*)\(\)",
+        r"|\b(?P<name>[a-z][a-z0-9]*(?:_[a-z0-9]+)+|[a-z]+[A-Z]\w*)\b",
+        r"|\b(?P<file>[\w-]{2,}\.[a-z]{2,5})\b",
+    ))
+    .unwrap()
+});
+
+/// The code references in `summary`, each once, in order of appearance.
+pub(crate) fn references(summary: &str) -> Vec<String> {
+    let mut references: Vec<String> = Vec::new();
+    for captures in REFERENCE.captures_iter(summary) {
+        let reference = ["quoted", "call", "name", "file"]
+            .iter()
+            .find_map(|group| captures.name(group))
+            .map(|found| found.as_str().trim_end_matches("()").to_string());
+        if let Some(reference) = reference.filter(|reference| !references.contains(reference)) {
+            references.push(reference);
+        }
+    }
+    references
+}
+
+/// The references in `summary` that don't appear in `content`, which may
+/// have been made up. A qualified reference such as `config::load` or
+/// `self.cache` counts as found when its last part is.
+pub(crate) fn validate_summary(content: &str, summary: &str) -> Vec<String> {
+    references(summary)
+        .into_iter()
+        .filter(|reference| {
+            let last = reference.rsplit(['.', ':', '/']).find(|part| !part.is_empty()).unwrap_or(reference);
+            !content.contains(reference.as_str()) && !content.contains(last)
+        })
+        .collect()
+}
+
+/// The note to append to `summary` when more than `threshold` of its
+/// references can't be found in `content`.
+pub(crate) fn inaccuracy_note(content: &str, summary: &str, threshold: f32) -> Option<String> {
+    let unverified = validate_summary(content, summary);
+    let total = references(summary).len();
+    if unverified.is_empty() || unverified.len() as f32 <= total as f32 * threshold {
+        return None;
+    }
+    let names: Vec<String> = unverified.iter().map(|reference| format!("`{}`", reference)).collect();
+    Some(format!("⚠️ possibly inaccurate: mentions {}, not found in the file", names.join(", ")))
+}
@@ -0,0 +1,24 @@
+// Lines are never wrapped; the model doesn't need a terminal width
+const TEXT_WIDTH: usize = usize::MAX / 2;
+
+/// The readable text of an HTML document: headings, paragraphs and link
+/// text, without tags, scripts, styles or link targets, and with runs of
+/// blank lines collapsed. `None` when `raw` can't be parsed.
+pub fn to_text(raw: &str) -> Option<String> {
+    let text = html2text::config::plain()
+        .link_footnotes(false)
+        .string_from_read(raw.as_bytes(), TEXT_WIDTH)
+        .ok()?;
+
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines().map(str::trim_end) {
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+    Some(lines.join("\n"))
+}
@@ -1,7 +1,21 @@
+#[cfg(feature = "magic")]
 pub mod magic;
+pub mod archive;
+pub mod git_log;
 pub mod gpt_client;
+pub mod html;
+pub mod manifests;
+pub mod markdown;
+pub mod metrics;
 pub mod file_explorer;
+pub mod notebook;
+pub mod output;
+pub mod pricing;
+pub mod redact;
+pub mod run_state;
+pub mod tokenizer;
 pub mod utils;
+pub mod watch;
 
 #[cfg(test)]
 mod tests;
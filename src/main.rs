@@ -1,16 +1,30 @@
-use clap::Parser;
-use std::path::PathBuf;
-use anyhow::Result;
+use clap::{Parser, Subcommand};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use anyhow::{Context, Result};
 use env_logger::Env;
 use log::info;
 
-use nexplorer::file_explorer::FileExplorer;
-use nexplorer::gpt_client::GPTClient;
+use nexplorer::file_explorer::{
+    FileExplorer, PrecacheStats, SortOrder, TokenCounts, TypeBreakdown, DEFAULT_EXCLUDED_DIRS,
+};
+use nexplorer::git_log;
+use nexplorer::gpt_client::{self, CacheInspection, ClientConfig, Conversation, GPTClient, Provider};
+use nexplorer::utils::{self, format_size, parse_size};
+use nexplorer::watch;
+use nexplorer::output::{GroupBy, OutputFormat};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
-    /// File or directory paths to explore
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// File or directory paths to explore (`-` reads from stdin, `file:40-120` summarizes only those lines)
     #[arg(required = true)]
     paths: Vec<PathBuf>,
 
@@ -26,59 +40,764 @@ struct Args {
     #[arg(long)]
     ai_whole: Option<String>,
 
+    /// After the initial pass, keep running and summarize files again when they change
+    #[arg(long, conflicts_with_all = ["ai_whole", "interactive", "report"])]
+    watch: bool,
+
+    /// Continue an interrupted run, skipping the files it already summarized
+    #[arg(long, conflicts_with_all = ["ai_whole", "dir_summaries"])]
+    resume: bool,
+
+    /// After summarizing a single file, keep asking follow-up questions about it
+    #[arg(long, conflicts_with_all = ["ai_whole", "dry_run"])]
+    interactive: bool,
+
+    /// Only report file counts and sizes per file type; nothing is summarized
+    #[arg(long)]
+    stats_only: bool,
+
     /// Maximum directory depth to explore
     #[arg(long, default_value_t = 3)]
     max_depth: u32,
 
-    /// Length of the summary (smart: automatic based on file size, short: ~50 words, medium: ~100 words, long: ~200 words, super: ~500 words, or a custom number)
+    /// Also summarize each directory's purpose from the summaries of its files
+    #[arg(long, conflicts_with_all = ["ai_whole", "stream"])]
+    dir_summaries: bool,
+
+    /// List text output grouped under a heading per interpreter instead of as a tree
+    #[arg(long, value_enum, conflicts_with = "dir_summaries")]
+    group_by: Option<GroupBy>,
+
+    /// Order of entries within each directory
+    #[arg(long, value_enum, default_value_t = SortOrder::Name)]
+    sort: SortOrder,
+
+    /// Directory names to skip entirely (comma-separated), in addition to the defaults
+    #[arg(long)]
+    exclude_dir: Option<String>,
+
+    /// Don't skip .git, node_modules and target directories by default
+    #[arg(long)]
+    no_default_excludes: bool,
+
+    /// Follow symbolic links to files and directories
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Summary length in tokens (smart: automatic based on content size, short: 50, medium: 100, long: 200, super: 500, a custom number, or a min-max range such as 100-200)
     #[arg(long, default_value = "medium")]
     summary_length: String,
 
-    /// Language for the summary (e.g., "english", "japanese", etc.)
+    /// Scale the --summary-length preset with the size of each file, keeping shorter presets shorter
+    #[arg(long)]
+    adaptive_presets: bool,
+
+    /// Language for the summary, as a name or ISO 639-1 code (e.g., "english", "ja"), or "auto" for the language each file is written in
     #[arg(long, default_value = "english")]
     language: String,
 
-    /// Force update cache entries
+    /// Per-extension language overrides (e.g., "rs=english,md=japanese")
+    #[arg(long)]
+    language_map: Option<String>,
+
+    /// Accept --language values that are not known language names or codes
+    #[arg(long)]
+    allow_unknown_language: bool,
+
+    /// Per-extension interpreter overrides for ambiguous extensions (e.g., "m=matlab,h=cpp")
+    #[arg(long)]
+    interpreter_override: Option<String>,
+
+    /// Force update cache entries (regenerates summaries and rewrites the cache)
     #[arg(long)]
     update: bool,
 
-    /// Custom ignore patterns (comma-separated)
+    /// Report which files' summaries changed since they were last cached
+    #[arg(long)]
+    only_changed_cache: bool,
+
+    /// Log cache hits and misses and report their totals
+    #[arg(long)]
+    verbose_cache: bool,
+
+    /// Bypass the cache entirely: never read or write entries (overrides --update)
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Translate a cached summary of the same content in another language
+    /// instead of summarizing the file again
+    #[arg(long)]
+    translate_from_cache: bool,
+
+    /// Summarize lock files and minified assets, which are skipped by default
+    #[arg(long)]
+    summarize_generated: bool,
+
+    /// Custom ignore patterns (comma-separated, matched like .gitignore entries)
     #[arg(long)]
     ignore: Option<String>,
 
+    /// Only summarize files matching these patterns (comma-separated)
+    #[arg(long)]
+    include: Option<String>,
+
+    /// Match --ignore and --include patterns regardless of case
+    #[arg(long)]
+    ignore_case: bool,
+
+    /// Skip files with these extensions (comma-separated, e.g. map,lock,min.js)
+    #[arg(long)]
+    exclude_extensions: Option<String>,
+
+    /// Only summarize files with these extensions (comma-separated)
+    #[arg(long)]
+    include_extensions: Option<String>,
+
+    /// Only summarize files whose detected interpreter is in this list (comma-separated, e.g. python3,bash)
+    #[arg(long)]
+    interpreter_filter: Option<String>,
+
+    /// Also skip files listed in .dockerignore at the root of each explored directory
+    #[arg(long)]
+    respect_dockerignore: bool,
+
+    /// Also skip files listed in .npmignore at the root of each explored directory
+    #[arg(long)]
+    respect_npmignore: bool,
+
+    /// Print only summaries (as `path: summary`) and the final stats, without the tree
+    #[arg(long)]
+    quiet: bool,
+
+    /// Summarize the README at the root first and give its summary to every other file's prompt as context
+    #[arg(long)]
+    readme_context: bool,
+
+    /// Show line counts and comment density for each text file (a `metrics` object in JSON)
+    #[arg(long)]
+    diffstat: bool,
+
+    /// Text printed before each summary in text output, with {path}, {size} and {interpreter} placeholders
+    #[arg(long, default_value = "")]
+    summary_prefix: String,
+
+    /// Text printed after each summary in text output, with the same placeholders as --summary-prefix
+    #[arg(long, default_value = "")]
+    summary_suffix: String,
+
+    /// Exit with status 2 when any file fails to summarize
+    #[arg(long)]
+    fail_on_error: bool,
+
     /// Enable debug logging
     #[arg(long)]
     debug: bool,
+
+    /// Maximum number of retries for failed API requests
+    #[arg(long, default_value_t = 3)]
+    max_retries: u32,
+
+    /// Stop summarizing once this many tokens (estimated prompt plus reply) have been used
+    #[arg(long)]
+    max_total_tokens: Option<u64>,
+
+    /// Seconds to wait for the API to connect or respond before giving up
+    #[arg(long, default_value_t = 60)]
+    timeout: u64,
+
+    /// Summarization backend (reads OPENAI_API_KEY or ANTHROPIC_API_KEY)
+    #[arg(long, value_enum, default_value_t = Provider::OpenAI)]
+    provider: Provider,
+
+    /// Chat model used for summaries [default: gpt-4o-mini, or claude-3-5-haiku-latest for anthropic]
+    #[arg(long)]
+    model: Option<String>,
+
+    /// OpenAI-compatible API root for local servers, e.g. http://localhost:11434/v1
+    /// (falls back to OPENAI_BASE_URL; no API key is required)
+    #[arg(long)]
+    base_url: Option<String>,
+
+    /// Accept model names that are not in the known model list (e.g. fine-tuned models)
+    #[arg(long)]
+    allow_unknown_model: bool,
+
+    /// Maximum number of files summarized concurrently
+    #[arg(long, default_value_t = 4)]
+    concurrency: usize,
+
+    /// Summarize at most this many files; the rest are still listed
+    #[arg(long)]
+    max_files: Option<u32>,
+
+    /// Expire cache entries older than this many days (0 = never expire)
+    #[arg(long, default_value_t = 0)]
+    cache_ttl: u64,
+
+    /// Delete the oldest cache entries once the cache grows past this size (e.g. 100M)
+    #[arg(long, value_parser = parse_size)]
+    cache_max_size: Option<u64>,
+
+    /// Directory for cached summaries (defaults to the platform cache directory)
+    #[arg(long, global = true)]
+    cache_dir: Option<PathBuf>,
+
+    /// Read the OpenAI API key from this file when OPENAI_API_KEY is not set
+    #[arg(long)]
+    api_key_file: Option<PathBuf>,
+
+    /// Show which files would be summarized and the estimated cost without calling the API (implies --ai)
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Stream summaries as they are generated
+    #[arg(long)]
+    stream: bool,
+
+    /// Model to retry with when the API reports the requested model as unavailable (404)
+    #[arg(long)]
+    model_fallback: Option<String>,
+
+    /// Summarize the text of .html files without tags, scripts and styles
+    #[arg(long)]
+    strip_html: bool,
+
+    /// Summarize .zip, .tar.gz and .tgz archives from their file listing and text entries
+    #[arg(long)]
+    expand_archives: bool,
+
+    /// Sampling temperature (0.0 to 2.0, up to 1.0 for anthropic); 0 gives the most reproducible summaries
+    #[arg(long, default_value_t = gpt_client::DEFAULT_TEMPERATURE)]
+    temperature: f32,
+
+    /// System prompt sent with every request, e.g. "You are a security auditor; focus on vulnerabilities."
+    #[arg(long, conflicts_with = "system_prompt_file")]
+    system_prompt: Option<String>,
+
+    /// Read the system prompt from a file
+    #[arg(long)]
+    system_prompt_file: Option<PathBuf>,
+
+    /// Summary prompt template with {content}, {language}, {length} (words), {path} and {interpreter} placeholders
+    #[arg(long, conflicts_with = "structured")]
+    prompt_template: Option<PathBuf>,
+
+    /// Print each prompt to stderr before it is sent, long content shortened
+    #[arg(long)]
+    print_prompt: bool,
+
+    /// Leave the estimated cost of the run out of the final stats
+    #[arg(long)]
+    no_cost_report: bool,
+
+    /// Limit API requests (retries included) to this many per minute
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    rpm: Option<u32>,
+
+    /// Replace API keys, tokens, passwords and private keys with [REDACTED] before sending content
+    #[arg(long)]
+    redact_secrets: bool,
+
+    /// Append a warning to summaries that mention code not found in the file
+    #[arg(long, conflicts_with_all = ["structured", "stream"])]
+    validate_summary: bool,
+
+    /// Fraction of a summary's code references that may be missing before --validate-summary warns
+    #[arg(long, requires = "validate_summary", default_value_t = gpt_client::DEFAULT_VALIDATION_THRESHOLD)]
+    validation_threshold: f32,
+
+    /// Ask for token logprobs and record how confident the model was in each summary (OpenAI-compatible APIs only)
+    #[arg(long, conflicts_with = "stream")]
+    logprobs: bool,
+
+    /// Ask again with more explicit instructions when a summary's confidence is below --confidence-threshold
+    #[arg(long, requires = "logprobs")]
+    resummarize_on_low_confidence: bool,
+
+    /// Confidence, from 0 to 1, below which --resummarize-on-low-confidence asks again
+    #[arg(long, requires = "resummarize_on_low_confidence", default_value_t = gpt_client::DEFAULT_CONFIDENCE_THRESHOLD)]
+    confidence_threshold: f32,
+
+    /// Describe PNG, JPEG, GIF and WebP images by sending them to a vision-capable model
+    #[arg(long, conflicts_with = "structured")]
+    vision: bool,
+
+    /// Summarize into JSON fields (purpose, key_components, dependencies, risks) instead of prose
+    #[arg(long, conflicts_with_all = ["stream", "enforce_length"])]
+    structured: bool,
+
+    /// Re-prompt once when a summary is more than 50% off the requested length
+    #[arg(long, conflicts_with = "stream")]
+    enforce_length: bool,
+
+    /// Largest file that will be summarized (e.g. 500K, 2M, or a byte count)
+    #[arg(long, default_value = "1M", value_parser = parse_size)]
+    max_file_size: u64,
+
+    /// Summarize files over three times this size from their first, middle and last this many bytes
+    #[arg(long, value_parser = parse_size)]
+    head_tail_bytes: Option<u64>,
+
+    /// Summarize files over --max-file-size in sections instead of skipping them
+    #[arg(long)]
+    chunk_large: bool,
+
+    /// Also write every file and summary to this markdown report
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Also write each summary to <dir>/<path relative to the explored root>.md
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// POST each summary as JSON ({path, summary, text}) to this URL, e.g. a Slack or Teams webhook
+    #[arg(long)]
+    webhook: Option<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Remove cached summaries
+    CleanCache {
+        /// Remove every cache entry
+        #[arg(long, conflicts_with = "older_than", required_unless_present = "older_than")]
+        all: bool,
+
+        /// Remove entries older than this many days
+        #[arg(long)]
+        older_than: Option<u64>,
+    },
+    /// Summarize files into the cache without printing the summaries
+    Precache {
+        /// File or directory paths to warm
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Count the tokens text files would use in prompts, without calling the API
+    CountTokens {
+        /// File or directory paths to count
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Show the cache entry a file's summary would use with the current settings
+    CacheShow {
+        /// Text file to look up
+        file: PathBuf,
+    },
+    /// Summarize the changes between two versions of a file
+    Compare {
+        /// The old version
+        old: PathBuf,
+        /// The new version
+        new: PathBuf,
+    },
+    /// Summarize the recent commits of a git repository
+    GitSummary {
+        /// Repository to read the history of
+        #[arg(default_value = ".")]
+        repo: PathBuf,
+
+        /// Number of most recent commits to summarize
+        #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..))]
+        commits: u32,
+
+        /// Only include commits by authors matching this pattern (as `git log --author`)
+        #[arg(long)]
+        author: Option<String>,
+    },
+}
+
+/// The process exit status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Exit {
+    /// Everything succeeded
+    Ok = 0,
+    /// Invalid arguments, or a setup error such as a missing API key
+    Error = 1,
+    /// Some summaries failed, with --fail-on-error
+    PartialFailure = 2,
+    /// Stopped by Ctrl-C, after printing what was done
+    Interrupted = 130,
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> Self {
+        ExitCode::from(exit as u8)
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
-    
+async fn main() -> ExitCode {
+    // clap exits with 2 on usage errors, which is reserved for partial failures
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        Err(err) => {
+            let _ = err.print();
+            return if err.use_stderr() { Exit::Error } else { Exit::Ok }.into();
+        }
+    };
+
+    match run(args).await {
+        Ok(exit) => exit.into(),
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            Exit::Error.into()
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<Exit> {
+
     // Initialize logger with appropriate level based on debug flag and release mode
     let env = Env::default()
         .filter_or("RUST_LOG", if args.debug {
             "debug"
-        } else if cfg!(debug_assertions) {
+        } else if cfg!(debug_assertions) || args.verbose_cache {
             "info"
         } else {
             "warn"
         });
     env_logger::init_from_env(env);
 
+    if let Some(Command::CleanCache { older_than, .. }) = args.command {
+        let cache_dir = gpt_client::cache_dir_path(args.cache_dir);
+        let cleanup = gpt_client::clean_cache(&cache_dir, older_than)?;
+        println!("Removed {} cache entries, freed {}", cleanup.removed, format_size(cleanup.bytes_freed));
+        return Ok(Exit::Ok);
+    }
+
+    let precache = matches!(args.command, Some(Command::Precache { .. }));
+    let cache_show = matches!(args.command, Some(Command::CacheShow { .. }));
+    let compare = matches!(args.command, Some(Command::Compare { .. }));
+    let git_summary = matches!(args.command, Some(Command::GitSummary { .. }));
+    let batch = args.ai_whole.is_some();
+    if precache && args.no_cache {
+        anyhow::bail!("precache has nothing to do with --no-cache");
+    }
+
     info!("Starting nexplorer with {} path(s)", args.paths.len());
+
+    let targets = args.paths.iter().map(|path| utils::parse_path_range(path)).collect::<Result<Vec<_>>>()?;
+    let paths: Vec<PathBuf> = targets.iter().map(|(path, _)| path.clone()).collect();
+    if targets.iter().any(|(_, range)| range.is_some()) && (args.ai_whole.is_some() || args.watch || args.interactive) {
+        anyhow::bail!("Line ranges can't be combined with --ai-whole, --watch or --interactive");
+    }
+    if args.watch && paths.iter().any(|path| path == Path::new("-")) {
+        anyhow::bail!("--watch can't watch stdin");
+    }
+    // Each explored path is a JSON document of its own, which one after
+    // another wouldn't parse as JSON
+    if args.format == OutputFormat::Json && paths.len() > 1 && !precache {
+        anyhow::bail!("--format json explores a single path; use --format jsonl for several");
+    }
+
+    if let Some(overrides) = &args.interpreter_override {
+        utils::set_interpreter_overrides(overrides)?;
+    }
+
+    let interactive_file = match paths.as_slice() {
+        [path] if args.interactive && path.is_file() => Some(path.clone()),
+        _ if args.interactive => anyhow::bail!("--interactive requires a single file path"),
+        _ => None,
+    };
     
     let mut explorer = FileExplorer::new(args.max_depth);
+    explorer.set_output_format(args.format);
+    explorer.set_quiet(args.quiet);
+    explorer.set_group_by(args.group_by);
+    explorer.set_concurrency(args.concurrency);
+    explorer.set_cost_report(!args.no_cost_report);
+    explorer.set_summary_frame(args.summary_prefix, args.summary_suffix);
+    explorer.set_diffstat(args.diffstat);
+    explorer.set_readme_context(args.readme_context);
+    if let Some(filter) = &args.interpreter_filter {
+        explorer.set_interpreter_filter(
+            filter.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect()
+        );
+    }
+    if let Some(max) = args.max_files {
+        explorer.set_max_files(max);
+    }
+    explorer.set_follow_symlinks(args.follow_symlinks);
+    explorer.set_sort(args.sort);
+    explorer.set_dir_summaries(args.dir_summaries);
+    explorer.set_excluded_dirs(excluded_dirs(args.exclude_dir.as_deref(), args.no_default_excludes));
+    if let Some(report) = args.report {
+        explorer.set_report(report);
+    }
+    if let Some(dir) = args.output_dir {
+        explorer.set_output_dir(dir);
+    }
+
+    if args.stats_only {
+        let mut breakdown = TypeBreakdown::default();
+        for path in &paths {
+            explorer.tally_file_types(path, &mut breakdown);
+        }
+        print_type_breakdown(&breakdown);
+        return Ok(Exit::Ok);
+    }
 
-    if args.ai || args.ai_query.is_some() || args.ai_whole.is_some() {
-        let client = GPTClient::new(&args.summary_length, &args.language, args.update, args.ignore.as_deref())?;
+    if let Some(Command::CountTokens { paths }) = &args.command {
+        let model = args.model.as_deref().unwrap_or(args.provider.default_model());
+        let mut counts = TokenCounts::default();
+        for path in paths {
+            explorer.count_tokens(path, model, &mut counts);
+        }
+        print_token_counts(&counts);
+        return Ok(Exit::Ok);
+    }
+
+    let system_prompt = match &args.system_prompt_file {
+        Some(path) => Some(fs::read_to_string(path)
+            .with_context(|| format!("Failed to read system prompt from {}", path.display()))?),
+        None => args.system_prompt,
+    };
+    let prompt_template = match &args.prompt_template {
+        Some(path) => Some(fs::read_to_string(path)
+            .with_context(|| format!("Failed to read prompt template from {}", path.display()))?),
+        None => None,
+    };
+
+    if args.ai || args.dry_run || args.ai_query.is_some() || args.ai_whole.is_some() || args.interactive || precache || cache_show || compare || git_summary {
+        let client = GPTClient::new(ClientConfig {
+            summary_length: args.summary_length,
+            language: args.language,
+            language_map: args.language_map,
+            force_update: args.update,
+            bypass_cache: args.no_cache,
+            custom_ignore: args.ignore,
+            custom_include: args.include,
+            ignore_case: args.ignore_case,
+            exclude_extensions: args.exclude_extensions,
+            include_extensions: args.include_extensions,
+            respect_dockerignore: args.respect_dockerignore,
+            respect_npmignore: args.respect_npmignore,
+            max_retries: args.max_retries,
+            timeout_secs: args.timeout,
+            provider: args.provider,
+            model: args.model.unwrap_or_else(|| args.provider.default_model().to_string()),
+            base_url: args.base_url,
+            allow_unknown_model: args.allow_unknown_model,
+            allow_unknown_language: args.allow_unknown_language,
+            cache_ttl_days: args.cache_ttl,
+            cache_max_size: args.cache_max_size,
+            cache_dir: args.cache_dir,
+            api_key_file: args.api_key_file,
+            // Looking up the cache needs no API key
+            dry_run: args.dry_run || cache_show,
+            stream: args.stream,
+            max_file_size: args.max_file_size,
+            head_tail_bytes: args.head_tail_bytes,
+            chunk_large: args.chunk_large,
+            enforce_length: args.enforce_length,
+            verbose_cache: args.verbose_cache,
+            summarize_generated: args.summarize_generated,
+            max_total_tokens: args.max_total_tokens,
+            translate_from_cache: args.translate_from_cache,
+            structured: args.structured,
+            temperature: args.temperature,
+            expand_archives: args.expand_archives,
+            strip_html: args.strip_html,
+            model_fallback: args.model_fallback,
+            adaptive_presets: args.adaptive_presets,
+            redact_secrets: args.redact_secrets,
+            system_prompt,
+            prompt_template,
+            rpm: args.rpm,
+            report_changes: args.only_changed_cache,
+            print_prompt: args.print_prompt,
+            vision: args.vision,
+            validation_threshold: args.validate_summary.then_some(args.validation_threshold),
+            logprobs: args.logprobs,
+            confidence_threshold: args.resummarize_on_low_confidence.then_some(args.confidence_threshold),
+        })?;
         explorer.set_summarizer(client, args.ai_query, args.ai_whole);
     }
+    // After the summarizer, whose HTTP agent the posts share
+    if let Some(url) = args.webhook {
+        explorer.set_webhook(url);
+    }
+
+    if let (Some(Command::CacheShow { file }), Some(client)) = (&args.command, explorer.summarizer()) {
+        print_cache_entry(&client.inspect_cache(file, explorer.custom_query())?, file);
+        return Ok(Exit::Ok);
+    }
+
+    if let (Some(Command::Compare { old, new }), Some(client)) = (&args.command, explorer.summarizer()) {
+        println!("{}", client.compare_files(old, new).await?.text);
+        return Ok(Exit::Ok);
+    }
+
+    if let (Some(Command::GitSummary { repo, commits, author }), Some(client)) = (&args.command, explorer.summarizer()) {
+        let commits = git_log::read_commits(repo, *commits as usize, author.as_deref())?;
+        println!("{}", client.summarize_git_log(&commits).await?.text);
+        return Ok(Exit::Ok);
+    }
+
+    // Questions in the interactive session keep the default Ctrl-C
+    if let Some(client) = explorer.summarizer().filter(|_| !args.interactive) {
+        handle_interrupts(client.interrupt_flag());
+    }
+
+    if let Some(Command::Precache { paths }) = &args.command {
+        let mut total = PrecacheStats::default();
+        for path in paths.iter().take_while(|_| !explorer.is_interrupted()) {
+            let stats = explorer.precache(path).await?;
+            total.newly_cached += stats.newly_cached;
+            total.already_cached += stats.already_cached;
+            total.failed += stats.failed;
+        }
+        println!("Newly cached: {}, already cached: {}", total.newly_cached, total.already_cached);
+        if total.failed > 0 {
+            println!("Failed: {}", total.failed);
+            if args.fail_on_error {
+                return Ok(Exit::PartialFailure);
+            }
+        }
+        if explorer.is_interrupted() {
+            return Ok(Exit::Interrupted);
+        }
+        return Ok(Exit::Ok);
+    }
 
     // Process each path provided
-    for path in args.paths {
-        explorer.explore(path).await?;
+    explorer.start_run(args.resume)?;
+    for (path, range) in &targets {
+        match range {
+            Some(range) => explorer.explore_lines(path, *range).await?,
+            None => explorer.explore(path).await?,
+        }
+        if explorer.is_interrupted() {
+            break;
+        }
+    }
+    explorer.finish_run()?;
+    if explorer.is_interrupted() {
+        if !batch && !args.dir_summaries {
+            eprintln!("Interrupted. Run again with --resume to summarize the rest.");
+        }
+        return Ok(Exit::Interrupted);
+    }
+
+    if args.watch {
+        watch::watch(&mut explorer, &paths).await?;
+    }
+
+    if let (Some(path), Some(client)) = (interactive_file, explorer.summarizer()) {
+        run_interactive(client, &path).await?;
+    }
+
+    if args.fail_on_error && explorer.summary_errors() > 0 {
+        return Ok(Exit::PartialFailure);
+    }
+    Ok(Exit::Ok)
+}
+
+/// Stop the run on the first Ctrl-C, letting summaries in flight finish,
+/// and quit at once on a second one.
+fn handle_interrupts(interrupted: Arc<AtomicBool>) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        interrupted.store(true, Ordering::Relaxed);
+        eprintln!("\nInterrupted: finishing summaries in progress (Ctrl-C again to quit now)");
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(Exit::Interrupted as i32);
+        }
+    });
+}
+
+fn print_type_breakdown(breakdown: &TypeBreakdown) {
+    println!("{:<24} {:>8} {:>12}", "Type", "Files", "Size");
+    println!("{}", "-".repeat(46));
+    for (kind, stats) in breakdown.rows() {
+        println!("{:<24} {:>8} {:>12}", kind, stats.files, format_size(stats.bytes));
+    }
+}
+
+fn print_cache_entry(inspection: &CacheInspection, file: &Path) {
+    let Some(entry) = &inspection.entry else {
+        println!("No cache entry for {}", file.display());
+        println!("Looked for hash {} at {}", inspection.content_hash, inspection.cache_path.display());
+        return;
+    };
+    println!("Cache entry for {} ({})", file.display(), inspection.cache_path.display());
+    println!("Hash:           {}", entry.content_hash);
+    println!("Written:        {}", utils::format_timestamp(entry.timestamp));
+    println!("Language:       {}", entry.language);
+    println!("Summary length: {}", entry.summary_length);
+    println!("Version:        {}", entry.version);
+    if let Some(confidence) = entry.confidence {
+        println!("Confidence:     {:.2}", confidence);
+    }
+    if !inspection.usable {
+        println!("Status:         ignored (expired, from an older version, or written with other settings)");
     }
+    println!("\n{}", entry.summary);
+}
+
+fn print_token_counts(counts: &TokenCounts) {
+    println!("{:<50} {:<16} {:>10}", "File", "Type", "Tokens");
+    println!("{}", "-".repeat(78));
+    for (path, kind, tokens) in counts.files() {
+        println!("{:<50} {:<16} {:>10}", path.display(), kind, tokens);
+    }
+
+    println!("\n{:<24} {:>8} {:>10}", "Type", "Files", "Tokens");
+    println!("{}", "-".repeat(44));
+    for (kind, files, tokens) in counts.by_kind() {
+        println!("{:<24} {:>8} {:>10}", kind, files, tokens);
+    }
+    println!("\nTotal: {} tokens in {} files", counts.total(), counts.files().len());
+}
 
+/// The default excluded directory names (unless disabled) plus the ones
+/// given on the command line.
+fn excluded_dirs(extra: Option<&str>, no_defaults: bool) -> Vec<String> {
+    let defaults = if no_defaults { &[][..] } else { DEFAULT_EXCLUDED_DIRS };
+    defaults
+        .iter()
+        .map(|name| name.to_string())
+        .chain(extra.into_iter()
+            .flat_map(|names| names.split(','))
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty()))
+        .collect()
+}
+
+/// Read questions from stdin until EOF or `/quit`, answering each in the
+/// context of the previous ones.
+async fn run_interactive(client: &GPTClient, path: &Path) -> Result<()> {
+    println!("\nAsk about {} (/quit or Ctrl-D to exit)", path.display());
+    let mut conversation = Conversation::new(path);
+    let stdin = io::stdin();
+
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let question = line.trim();
+        if question.is_empty() {
+            continue;
+        }
+        if question == "/quit" {
+            break;
+        }
+
+        match client.ask(&mut conversation, question).await {
+            Ok(answer) => println!("📝 {}\n", answer),
+            Err(e) => eprintln!("⚠️ {}", e),
+        }
+    }
     Ok(())
 }
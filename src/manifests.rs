@@ -0,0 +1,188 @@
+use std::path::Path;
+use serde_json::Value as Json;
+use toml::Value as Toml;
+
+/// What a package manifest says about its project, whichever ecosystem
+/// it comes from.
+#[derive(Debug, Default, PartialEq)]
+pub struct Manifest {
+    /// The manifest's file name, e.g. `Cargo.toml`
+    pub kind: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// `name version` or just the name when no plain version is given
+    pub dependencies: Vec<String>,
+    pub dev_dependencies: Vec<String>,
+    /// Binaries, libraries and main modules the project provides
+    pub entry_points: Vec<String>,
+    /// Script names with their commands
+    pub scripts: Vec<(String, String)>,
+}
+
+impl Manifest {
+    /// Content to summarize in place of the manifest: the parsed fields,
+    /// introduced by a request to describe the project rather than the file.
+    pub fn describe(&self) -> String {
+        let mut text = format!(
+            "This is the {} manifest of a project. Describe the project it defines: what it is, \
+            what it depends on and how it is built or run, rather than the file itself.\n\n",
+            self.kind
+        );
+        if let Some(name) = &self.name {
+            text.push_str(&format!("Name: {}\n", name));
+        }
+        if let Some(description) = &self.description {
+            text.push_str(&format!("Description: {}\n", description));
+        }
+        for (label, items) in [
+            ("Dependencies", &self.dependencies),
+            ("Development dependencies", &self.dev_dependencies),
+            ("Entry points", &self.entry_points),
+        ] {
+            if !items.is_empty() {
+                text.push_str(&format!("{}: {}\n", label, items.join(", ")));
+            }
+        }
+        if !self.scripts.is_empty() {
+            text.push_str("Scripts:\n");
+            for (name, command) in &self.scripts {
+                text.push_str(&format!("- {}: {}\n", name, command));
+            }
+        }
+        text
+    }
+}
+
+/// Parse `content` if `path` names a known manifest. `None` for other
+/// files and for manifests that don't parse.
+pub fn parse(path: &Path, content: &str) -> Option<Manifest> {
+    let kind = path.file_name()?.to_str()?;
+    let mut manifest = match kind {
+        "Cargo.toml" => parse_cargo(&toml::from_str(content).ok()?),
+        "package.json" => parse_package_json(&serde_json::from_str(content).ok()?),
+        "pyproject.toml" => parse_pyproject(&toml::from_str(content).ok()?),
+        _ => return None,
+    };
+    manifest.kind = kind.to_string();
+    Some(manifest)
+}
+
+fn parse_cargo(manifest: &Toml) -> Manifest {
+    let package = manifest.get("package");
+    let mut entry_points: Vec<String> = manifest.get("bin")
+        .and_then(Toml::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|bin| bin.get("name").and_then(Toml::as_str))
+        .map(|name| format!("binary {}", name))
+        .collect();
+    if let Some(lib) = manifest.get("lib") {
+        entry_points.push(format!("library {}", lib.get("path").and_then(Toml::as_str).unwrap_or("src/lib.rs")));
+    }
+    if let Some(members) = manifest.get("workspace").and_then(|workspace| workspace.get("members")).and_then(Toml::as_array) {
+        entry_points.extend(members.iter().filter_map(Toml::as_str).map(|member| format!("workspace member {}", member)));
+    }
+
+    Manifest {
+        name: toml_str(package, "name"),
+        description: toml_str(package, "description"),
+        dependencies: ["dependencies", "build-dependencies"]
+            .iter()
+            .flat_map(|table| toml_dependencies(manifest.get(*table)))
+            .collect(),
+        dev_dependencies: toml_dependencies(manifest.get("dev-dependencies")),
+        entry_points,
+        ..Manifest::default()
+    }
+}
+
+fn parse_package_json(manifest: &Json) -> Manifest {
+    let mut entry_points: Vec<String> = ["main", "module"]
+        .iter()
+        .filter_map(|key| manifest.get(*key).and_then(Json::as_str))
+        .map(|path| format!("module {}", path))
+        .collect();
+    match manifest.get("bin") {
+        Some(Json::String(path)) => entry_points.push(format!("binary {}", path)),
+        Some(Json::Object(bins)) => entry_points.extend(bins.keys().map(|name| format!("binary {}", name))),
+        _ => {}
+    }
+
+    Manifest {
+        name: json_str(manifest, "name"),
+        description: json_str(manifest, "description"),
+        dependencies: ["dependencies", "peerDependencies"]
+            .iter()
+            .flat_map(|key| json_map(manifest.get(*key)))
+            .map(|(name, version)| format!("{} {}", name, version))
+            .collect(),
+        dev_dependencies: json_map(manifest.get("devDependencies"))
+            .into_iter()
+            .map(|(name, version)| format!("{} {}", name, version))
+            .collect(),
+        entry_points,
+        scripts: json_map(manifest.get("scripts")),
+        ..Manifest::default()
+    }
+}
+
+/// PEP 621 `[project]` tables, with Poetry's `[tool.poetry]` as fallback.
+fn parse_pyproject(manifest: &Toml) -> Manifest {
+    let project = manifest.get("project");
+    let poetry = manifest.get("tool").and_then(|tool| tool.get("poetry"));
+    let scripts = project.or(poetry)
+        .and_then(|table| table.get("scripts"))
+        .and_then(Toml::as_table)
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, target)| Some((name.clone(), target.as_str()?.to_string())))
+        .collect();
+
+    let dependencies = match project.and_then(|project| project.get("dependencies")).and_then(Toml::as_array) {
+        Some(requirements) => requirements.iter().filter_map(Toml::as_str).map(str::to_string).collect(),
+        None => toml_dependencies(poetry.and_then(|poetry| poetry.get("dependencies")))
+            .into_iter()
+            .filter(|dependency| dependency != "python" && !dependency.starts_with("python "))
+            .collect(),
+    };
+
+    Manifest {
+        name: toml_str(project, "name").or_else(|| toml_str(poetry, "name")),
+        description: toml_str(project, "description").or_else(|| toml_str(poetry, "description")),
+        dependencies,
+        scripts,
+        ..Manifest::default()
+    }
+}
+
+fn toml_str(table: Option<&Toml>, key: &str) -> Option<String> {
+    table?.get(key)?.as_str().map(str::to_string)
+}
+
+/// A dependency table's entries: `name = "1.0"` or `name = { version = "1.0", ... }`.
+fn toml_dependencies(table: Option<&Toml>) -> Vec<String> {
+    table.and_then(Toml::as_table)
+        .into_iter()
+        .flatten()
+        .map(|(name, spec)| {
+            let version = spec.as_str().or_else(|| spec.get("version").and_then(Toml::as_str));
+            match version {
+                Some(version) => format!("{} {}", name, version),
+                None => name.clone(),
+            }
+        })
+        .collect()
+}
+
+fn json_str(object: &Json, key: &str) -> Option<String> {
+    object.get(key)?.as_str().map(str::to_string)
+}
+
+/// The string-valued entries of a JSON object, sorted by key.
+fn json_map(object: Option<&Json>) -> Vec<(String, String)> {
+    object.and_then(Json::as_object)
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+        .collect()
+}
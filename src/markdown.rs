@@ -0,0 +1,91 @@
+/// Metadata from the YAML front-matter block at the top of a Markdown
+/// document. Only the keys useful as summary hints are read.
+#[derive(Debug, Default, PartialEq)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl FrontMatter {
+    fn is_empty(&self) -> bool {
+        self.title.is_none() && self.description.is_none() && self.tags.is_empty()
+    }
+}
+
+/// Split `content` into its front matter and the body after it. `None`
+/// when it doesn't open with a `---` block that is closed again.
+pub fn split_front_matter(content: &str) -> Option<(FrontMatter, &str)> {
+    let (opening, rest) = content.trim_start_matches('\u{feff}').split_once('\n')?;
+    if opening.trim_end() != "---" {
+        return None;
+    }
+
+    // The block ends at a line of `---` (or YAML's `...`)
+    let mut offset = 0;
+    let mut block_end = None;
+    for line in rest.split_inclusive('\n') {
+        if matches!(line.trim_end(), "---" | "...") {
+            block_end = Some((offset, offset + line.len()));
+            break;
+        }
+        offset += line.len();
+    }
+    let (block_end, body_start) = block_end?;
+
+    let mut front_matter = FrontMatter::default();
+    let mut list_key = None;
+    for line in rest[..block_end].lines() {
+        // `- item` lines continue the list started by `tags:`
+        if let Some(item) = line.trim_start().strip_prefix("- ") {
+            if list_key == Some("tags") {
+                front_matter.tags.push(unquote(item).to_string());
+            }
+            continue;
+        }
+        list_key = None;
+        let Some((key, value)) = line.split_once(':').filter(|_| !line.starts_with(char::is_whitespace)) else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "title" if !value.is_empty() => front_matter.title = Some(unquote(value).to_string()),
+            "description" if !value.is_empty() => front_matter.description = Some(unquote(value).to_string()),
+            "tags" | "keywords" if value.is_empty() => list_key = Some("tags"),
+            "tags" | "keywords" => front_matter.tags.extend(
+                value.trim_start_matches('[').trim_end_matches(']')
+                    .split(',')
+                    .map(|tag| unquote(tag.trim()).to_string())
+                    .filter(|tag| !tag.is_empty()),
+            ),
+            _ => {}
+        }
+    }
+    Some((front_matter, &rest[body_start..]))
+}
+
+/// What to summarize for a document with front matter: its title,
+/// description and tags as hints ahead of the body. `None` without front
+/// matter.
+pub fn with_hints(content: &str) -> Option<String> {
+    let (front_matter, body) = split_front_matter(content)?;
+    if front_matter.is_empty() {
+        return Some(body.to_string());
+    }
+
+    let mut hints = String::from("Document metadata from its front matter:\n");
+    if let Some(title) = &front_matter.title {
+        hints.push_str(&format!("Title: {}\n", title));
+    }
+    if let Some(description) = &front_matter.description {
+        hints.push_str(&format!("Description: {}\n", description));
+    }
+    if !front_matter.tags.is_empty() {
+        hints.push_str(&format!("Tags: {}\n", front_matter.tags.join(", ")));
+    }
+    Some(format!("{}\nDocument body:\n{}", hints, body))
+}
+
+fn unquote(value: &str) -> &str {
+    value.trim_matches(|c| c == '"' || c == '\'')
+}
@@ -0,0 +1,101 @@
+use serde::Serialize;
+
+/// Line counts for --diffstat.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FileMetrics {
+    pub lines: usize,
+    pub blank_lines: usize,
+    /// `None` for languages whose comment syntax isn't known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment_lines: Option<usize>,
+}
+
+impl FileMetrics {
+    /// Lines that aren't blank, comments included.
+    pub fn loc(&self) -> usize {
+        self.lines - self.blank_lines
+    }
+
+    /// Comment lines as a share of the non-blank ones, rounded.
+    pub fn comment_percent(&self) -> Option<usize> {
+        let comments = self.comment_lines?;
+        Some(match self.loc() {
+            0 => 0,
+            loc => (comments * 100 + loc / 2) / loc,
+        })
+    }
+}
+
+/// How a language writes comments.
+struct CommentSyntax {
+    line: &'static [&'static str],
+    block: &'static [(&'static str, &'static str)],
+}
+
+const C_LIKE: CommentSyntax = CommentSyntax { line: &["//"], block: &[("/*", "*/")] };
+const HASH: CommentSyntax = CommentSyntax { line: &["#"], block: &[] };
+// Docstrings count as comments
+const PYTHON: CommentSyntax = CommentSyntax { line: &["#"], block: &[("\"\"\"", "\"\"\""), ("'''", "'''")] };
+const CSS: CommentSyntax = CommentSyntax { line: &[], block: &[("/*", "*/")] };
+const MARKUP: CommentSyntax = CommentSyntax { line: &[], block: &[("<!--", "-->")] };
+const SQL: CommentSyntax = CommentSyntax { line: &["--"], block: &[("/*", "*/")] };
+const LUA: CommentSyntax = CommentSyntax { line: &["--"], block: &[("--[[", "]]")] };
+const INI: CommentSyntax = CommentSyntax { line: &[";", "#"], block: &[] };
+
+/// The comment syntax for an interpreter as `get_file_info` reports it.
+fn comment_syntax(interpreter: &str) -> Option<&'static CommentSyntax> {
+    let syntax = match interpreter {
+        "rust" | "c" | "cpp" | "csharp" | "java" | "kotlin" | "scala" | "swift" | "go" | "javascript"
+        | "javascript-react" | "typescript" | "typescript-react" | "node" | "deno" | "php" | "scss"
+        | "less" | "fsharp" => &C_LIKE,
+        "python" | "python3" => &PYTHON,
+        "bash" | "sh" | "zsh" | "fish" | "ksh" | "dash" | "ruby" | "perl" | "r" | "julia" | "tcl"
+        | "awk" | "sed" | "yaml" | "toml" => &HASH,
+        "css" => &CSS,
+        "html" | "vue" | "svelte" => &MARKUP,
+        "sql" => &SQL,
+        "lua" => &LUA,
+        "ini" => &INI,
+        _ => return None,
+    };
+    Some(syntax)
+}
+
+/// Count the lines of `content`, with comments counted for the languages
+/// `interpreter` is known for. A line counts as a comment only when it
+/// holds nothing else, so code with a trailing comment is code.
+pub fn measure(content: &str, interpreter: Option<&str>) -> FileMetrics {
+    let syntax = interpreter.and_then(comment_syntax);
+    let mut metrics = FileMetrics { comment_lines: syntax.map(|_| 0), ..FileMetrics::default() };
+    // The end marker of the block comment the current line is in
+    let mut block_end: Option<&str> = None;
+
+    for line in content.lines() {
+        metrics.lines += 1;
+        let line = line.trim();
+        if line.is_empty() && block_end.is_none() {
+            metrics.blank_lines += 1;
+            continue;
+        }
+        let (Some(syntax), Some(comments)) = (syntax, metrics.comment_lines.as_mut()) else {
+            continue;
+        };
+
+        if let Some(end) = block_end {
+            *comments += 1;
+            if line.contains(end) {
+                block_end = None;
+            }
+        } else if syntax.line.iter().any(|prefix| line.starts_with(prefix))
+            && !syntax.block.iter().any(|(start, _)| line.starts_with(start))
+        {
+            *comments += 1;
+        } else if let Some((start, end)) = syntax.block.iter().find(|(start, _)| line.starts_with(start)) {
+            *comments += 1;
+            if !line[start.len()..].contains(end) {
+                block_end = Some(end);
+            }
+        }
+    }
+    metrics
+}
@@ -0,0 +1,70 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct Notebook {
+    cells: Vec<Cell>,
+    #[serde(default)]
+    metadata: Metadata,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Metadata {
+    language_info: Option<LanguageInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LanguageInfo {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Cell {
+    cell_type: String,
+    #[serde(default)]
+    source: Source,
+}
+
+/// Cell source, stored either as one string or as a list of lines.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Source {
+    Text(String),
+    Lines(Vec<String>),
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Source::Text(String::new())
+    }
+}
+
+impl Source {
+    fn text(&self) -> String {
+        match self {
+            Source::Text(text) => text.clone(),
+            Source::Lines(lines) => lines.concat(),
+        }
+    }
+}
+
+/// The markdown and code cells of a Jupyter notebook as one document, in
+/// order and without outputs. `None` when `raw` isn't a notebook.
+pub fn cells_text(raw: &str) -> Option<String> {
+    let notebook: Notebook = serde_json::from_str(raw).ok()?;
+    let language = notebook.metadata.language_info.map(|info| info.name).unwrap_or_default();
+
+    let sections: Vec<String> = notebook.cells
+        .iter()
+        .filter_map(|cell| {
+            let source = cell.source.text();
+            let source = source.trim_end();
+            match cell.cell_type.as_str() {
+                _ if source.trim().is_empty() => None,
+                "markdown" => Some(source.to_string()),
+                "code" => Some(format!("```{}\n{}\n```", language, source)),
+                _ => None,
+            }
+        })
+        .collect();
+    Some(sections.join("\n\n"))
+}
@@ -0,0 +1,1280 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::warn;
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::gpt_client::{DryRunEstimate, StructuredSummary};
+use crate::metrics::FileMetrics;
+use crate::pricing::CostReport;
+use crate::utils::{file_kind, format_size, FileInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Decorated tree output for humans
+    #[default]
+    Text,
+    /// A single JSON document per explored path
+    Json,
+    /// One JSON object per line
+    Jsonl,
+}
+
+/// How --group-by clusters text output instead of listing the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    /// Under a heading per interpreter (or extension)
+    Interpreter,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileRecord {
+    pub path: String,
+    pub size: u64,
+    pub is_text: bool,
+    pub interpreter: Option<String>,
+    /// A string, or an object for summaries written with --structured
+    pub summary: Option<Value>,
+    /// With --diffstat
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<FileMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DirectorySummary {
+    path: String,
+    summary: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BatchSummary {
+    path: String,
+    summary: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BatchFailure {
+    path: String,
+    error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Stats {
+    pub total_dirs: u32,
+    pub total_files: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<DryRunEstimate>,
+    /// Estimated tokens spent on requests, when a summarizer ran
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens_used: Option<u64>,
+    /// What the requests cost, unless --no-cost-report
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<CostReport>,
+    /// Only reported with --verbose-cache
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheStats>,
+    /// Files whose summary changed since the last run, with
+    /// --only-changed-cache
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed: Option<Vec<String>>,
+}
+
+/// How many summaries came from the cache and how many API requests were
+/// made instead.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub api_calls: u64,
+}
+
+/// Receives exploration events from `FileExplorer` and renders them.
+pub trait OutputWriter: Send {
+    fn begin(&mut self, root: &Path) -> Result<()>;
+    fn directory(&mut self, path: &Path, depth: usize) -> Result<()>;
+    /// The purpose of the directory just reported, with --dir-summaries.
+    fn directory_summary(&mut self, _path: &Path, _summary: &str, _depth: usize) -> Result<()> {
+        Ok(())
+    }
+    fn file(&mut self, path: &Path, info: &FileInfo, depth: usize) -> Result<()>;
+    /// Line counts for the file just reported, with --diffstat.
+    fn metrics(&mut self, _metrics: &FileMetrics, _depth: usize) -> Result<()> {
+        Ok(())
+    }
+    fn summary(&mut self, summary: &str, depth: usize) -> Result<()>;
+    /// A piece of a summary that is still being streamed.
+    fn summary_chunk(&mut self, _chunk: &str, _first: bool, _depth: usize) -> Result<()> {
+        Ok(())
+    }
+    /// The complete summary after its chunks were streamed.
+    fn summary_streamed(&mut self, summary: &str, depth: usize) -> Result<()> {
+        self.summary(summary, depth)
+    }
+    fn summary_error(&mut self, error: &anyhow::Error, depth: usize) -> Result<()>;
+    fn batch_started(&mut self) -> Result<()>;
+    fn batch_summaries(&mut self, summaries: &HashMap<String, String>) -> Result<()>;
+    /// Files of the batch that couldn't be summarized, by path, with their
+    /// errors. Comes after `batch_summaries`.
+    fn batch_failures(&mut self, errors: &HashMap<String, String>) -> Result<()>;
+    fn batch_answer(&mut self, answer: &str) -> Result<()>;
+    fn batch_error(&mut self, error: &anyhow::Error) -> Result<()>;
+    fn finish(&mut self, stats: &Stats) -> Result<()>;
+}
+
+/// `quiet` drops the tree from text output, leaving `path: summary` lines.
+pub fn writer_for<W: Write + Send + 'static>(format: OutputFormat, quiet: bool, out: W) -> Box<dyn OutputWriter> {
+    match format {
+        OutputFormat::Text if quiet => Box::new(TextWriter::quiet(out)),
+        OutputFormat::Text => Box::new(TextWriter::new(out)),
+        OutputFormat::Json => Box::new(JsonWriter::new(out, false)),
+        OutputFormat::Jsonl => Box::new(JsonWriter::new(out, true)),
+    }
+}
+
+enum OutputMessage {
+    Line(Vec<u8>),
+    /// Acknowledged once everything sent before it was written
+    Flush(mpsc::SyncSender<()>),
+}
+
+/// The sending end of the output actor started by `spawn_output`. Bytes
+/// are held back until a line is complete and sent as one message, so
+/// lines written from concurrent tasks through clones never interleave.
+/// Flushing also sends an unfinished line, for streamed summaries.
+pub struct OutputSender {
+    sender: mpsc::Sender<OutputMessage>,
+    pending: Vec<u8>,
+    /// The first error the output task got, returned by every later send
+    failed: Arc<Mutex<Option<(io::ErrorKind, String)>>>,
+}
+
+impl Clone for OutputSender {
+    fn clone(&self) -> Self {
+        Self { sender: self.sender.clone(), pending: Vec::new(), failed: self.failed.clone() }
+    }
+}
+
+/// Start a single task writing everything sent through the returned
+/// sender (and its clones) to `out`, in the order it arrives. The task
+/// ends once every sender is dropped. After a failed write (such as a
+/// closed pipe) nothing more is written, and the error is returned by
+/// the next send or flush.
+pub fn spawn_output<W: Write + Send + 'static>(mut out: W) -> OutputSender {
+    let (sender, receiver) = mpsc::channel();
+    let failed = Arc::new(Mutex::new(None));
+    let task_failed = failed.clone();
+    std::thread::spawn(move || {
+        let fail = |e: io::Error| {
+            task_failed.lock().unwrap().get_or_insert((e.kind(), e.to_string()));
+        };
+        for message in receiver {
+            let stopped = task_failed.lock().unwrap().is_some();
+            match message {
+                OutputMessage::Line(line) if !stopped => {
+                    if let Err(e) = out.write_all(&line) {
+                        fail(e);
+                    }
+                }
+                OutputMessage::Line(_) => {}
+                OutputMessage::Flush(done) => {
+                    if !stopped {
+                        if let Err(e) = out.flush() {
+                            fail(e);
+                        }
+                    }
+                    let _ = done.send(());
+                }
+            }
+        }
+    });
+    OutputSender { sender, pending: Vec::new(), failed }
+}
+
+impl OutputSender {
+    fn send(&self, message: OutputMessage) -> io::Result<()> {
+        self.check()?;
+        self.sender.send(message)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "output task stopped"))
+    }
+
+    /// The error the output task stopped writing on, if any.
+    fn check(&self) -> io::Result<()> {
+        match &*self.failed.lock().unwrap() {
+            Some((kind, message)) => Err(io::Error::new(*kind, message.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Write for OutputSender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        if let Some(end) = self.pending.iter().rposition(|&byte| byte == b'\n') {
+            let rest = self.pending.split_off(end + 1);
+            let lines = std::mem::replace(&mut self.pending, rest);
+            self.send(OutputMessage::Line(lines))?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Returns once the output task has written everything sent so far.
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let partial = std::mem::take(&mut self.pending);
+            self.send(OutputMessage::Line(partial))?;
+        }
+        let (done, written) = mpsc::sync_channel(1);
+        self.send(OutputMessage::Flush(done))?;
+        written.recv().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "output task stopped"))?;
+        self.check()
+    }
+}
+
+pub struct TextWriter<W: Write> {
+    out: W,
+    quiet: bool,
+    /// The path that summaries belong to, for quiet output
+    current: PathBuf,
+}
+
+impl<W: Write> TextWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, quiet: false, current: PathBuf::new() }
+    }
+
+    /// Only summaries (as `path: summary`) and the final stats.
+    pub fn quiet(out: W) -> Self {
+        Self { quiet: true, ..Self::new(out) }
+    }
+
+    /// One line per field, under the file (or its path when quiet).
+    fn structured_summary(&mut self, summary: &StructuredSummary, depth: usize) -> Result<()> {
+        let indent = if self.quiet {
+            writeln!(self.out, "{}:", self.current.display())?;
+            "  ".to_string()
+        } else {
+            format!("{}   ", "  ".repeat(depth))
+        };
+        for (label, value) in summary.fields() {
+            writeln!(self.out, "{}{}: {}", indent, label, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> OutputWriter for TextWriter<W> {
+    fn begin(&mut self, root: &Path) -> Result<()> {
+        if self.quiet {
+            return Ok(());
+        }
+        writeln!(self.out, "\nExploring: {}", root.display())?;
+        writeln!(self.out, "{}", "=".repeat(80))?;
+        Ok(())
+    }
+
+    fn directory(&mut self, path: &Path, depth: usize) -> Result<()> {
+        if self.quiet {
+            return Ok(());
+        }
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        writeln!(self.out, "{}📁 {}/", "  ".repeat(depth), name.to_string_lossy())?;
+        Ok(())
+    }
+
+    fn directory_summary(&mut self, path: &Path, summary: &str, depth: usize) -> Result<()> {
+        if self.quiet {
+            writeln!(self.out, "{}/: {}", path.display(), summary)?;
+        } else {
+            writeln!(self.out, "{}   📝 Purpose: {}", "  ".repeat(depth), summary)?;
+        }
+        Ok(())
+    }
+
+    fn file(&mut self, path: &Path, info: &FileInfo, depth: usize) -> Result<()> {
+        if self.quiet {
+            self.current = path.to_path_buf();
+            return Ok(());
+        }
+        writeln!(self.out, "{}📄 {} ({})",
+            "  ".repeat(depth),
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            format_size(info.size)
+        )?;
+        Ok(())
+    }
+
+    fn metrics(&mut self, metrics: &FileMetrics, depth: usize) -> Result<()> {
+        let line = match metrics.comment_percent() {
+            Some(percent) => format!("{} LOC, {}% comments", metrics.loc(), percent),
+            None => format!("{} LOC", metrics.loc()),
+        };
+        if self.quiet {
+            writeln!(self.out, "{}: 📊 {}", self.current.display(), line)?;
+        } else {
+            writeln!(self.out, "{}   📊 {}", "  ".repeat(depth), line)?;
+        }
+        Ok(())
+    }
+
+    fn summary(&mut self, summary: &str, depth: usize) -> Result<()> {
+        if let Some(structured) = StructuredSummary::parse(summary) {
+            return self.structured_summary(&structured, depth);
+        }
+        if self.quiet {
+            writeln!(self.out, "{}: {}", self.current.display(), summary)?;
+        } else {
+            writeln!(self.out, "{}   📝 Summary: {}", "  ".repeat(depth), summary)?;
+        }
+        Ok(())
+    }
+
+    fn summary_chunk(&mut self, chunk: &str, first: bool, depth: usize) -> Result<()> {
+        if first && self.quiet {
+            write!(self.out, "{}: ", self.current.display())?;
+        } else if first {
+            write!(self.out, "{}   📝 Summary: ", "  ".repeat(depth))?;
+        }
+        write!(self.out, "{}", chunk)?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn summary_streamed(&mut self, _summary: &str, _depth: usize) -> Result<()> {
+        writeln!(self.out)?;
+        Ok(())
+    }
+
+    fn summary_error(&mut self, error: &anyhow::Error, depth: usize) -> Result<()> {
+        eprintln!("{}   ⚠️ Failed to generate summary: {}", "  ".repeat(depth), error);
+        Ok(())
+    }
+
+    fn batch_started(&mut self) -> Result<()> {
+        writeln!(self.out, "\nGenerating batch summaries...")?;
+        Ok(())
+    }
+
+    fn batch_summaries(&mut self, summaries: &HashMap<String, String>) -> Result<()> {
+        if !summaries.is_empty() {
+            writeln!(self.out, "\nFile Summaries:")?;
+            writeln!(self.out, "{}", "=".repeat(80))?;
+            for (file_name, summary) in summaries {
+                writeln!(self.out, "\n📄 {}:", file_name)?;
+                writeln!(self.out, "   📝 {}", summary)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn batch_failures(&mut self, errors: &HashMap<String, String>) -> Result<()> {
+        let mut entries: Vec<_> = errors.iter().collect();
+        entries.sort();
+        eprintln!("\nFailed Summaries:");
+        eprintln!("{}", "=".repeat(80));
+        for (file_name, error) in entries {
+            eprintln!("⚠️ {}: {}", file_name, error);
+        }
+        Ok(())
+    }
+
+    fn batch_answer(&mut self, answer: &str) -> Result<()> {
+        // For custom queries, display the direct answer
+        writeln!(self.out, "\n📝 {}", answer)?;
+        Ok(())
+    }
+
+    fn batch_error(&mut self, error: &anyhow::Error) -> Result<()> {
+        eprintln!("\n⚠️ Error processing files: {}", error);
+        Ok(())
+    }
+
+    fn finish(&mut self, stats: &Stats) -> Result<()> {
+        writeln!(self.out, "\nSummary:")?;
+        writeln!(self.out, "Total directories: {}", stats.total_dirs)?;
+        writeln!(self.out, "Total files: {}", stats.total_files)?;
+        if let Some(estimate) = &stats.dry_run {
+            writeln!(self.out, "Files to summarize: {}", estimate.files)?;
+            writeln!(self.out, "Estimated tokens: {} prompt, {} completion",
+                estimate.prompt_tokens, estimate.output_tokens)?;
+            match estimate.cost {
+                Some(cost) => writeln!(self.out, "Estimated cost: ${:.4}", cost)?,
+                None => writeln!(self.out, "Estimated cost: unknown (no pricing for this model)")?,
+            }
+        }
+        if let Some(tokens) = stats.tokens_used {
+            writeln!(self.out, "Estimated tokens used: {}", tokens)?;
+        }
+        if let Some(report) = &stats.cost {
+            match report.cost {
+                Some(cost) => writeln!(self.out, "Estimated cost: ${:.4} ({} input, {} output tokens in {} requests)",
+                    cost, report.input_tokens, report.output_tokens, report.requests)?,
+                None => writeln!(self.out, "Estimated cost: unknown (no pricing for this model)")?,
+            }
+        }
+        if let Some(cache) = &stats.cache {
+            writeln!(self.out, "Cache hits: {}", cache.hits)?;
+            writeln!(self.out, "API calls: {}", cache.api_calls)?;
+        }
+        if let Some(changed) = &stats.changed {
+            writeln!(self.out, "\nChanged since last run:")?;
+            if changed.is_empty() {
+                writeln!(self.out, "  (none)")?;
+            }
+            for path in changed {
+                writeln!(self.out, "  {}", path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Text output grouped by interpreter: files and their summaries are
+/// held back until the exploration finishes, then listed under a heading
+/// per group, both sorted. The tree isn't shown.
+pub struct GroupedWriter<W: Write> {
+    text: TextWriter<W>,
+    /// Group name -> files in it, with their summaries once known
+    groups: BTreeMap<String, Vec<GroupedFile>>,
+    /// Group of the file the next summary belongs to
+    current: Option<String>,
+}
+
+struct GroupedFile {
+    path: PathBuf,
+    size: u64,
+    summary: Option<String>,
+}
+
+impl<W: Write> GroupedWriter<W> {
+    /// Renders summaries (quiet or not) the way `text` does.
+    pub fn new(text: TextWriter<W>) -> Self {
+        Self { text, groups: BTreeMap::new(), current: None }
+    }
+
+    fn set_summary(&mut self, group: &str, path: &Path, summary: &str) {
+        if let Some(file) = self.groups.get_mut(group)
+            .and_then(|files| files.iter_mut().rev().find(|file| file.path == path))
+        {
+            file.summary = Some(summary.to_string());
+        }
+    }
+}
+
+impl<W: Write + Send> OutputWriter for GroupedWriter<W> {
+    fn begin(&mut self, root: &Path) -> Result<()> {
+        self.text.begin(root)
+    }
+
+    fn directory(&mut self, _path: &Path, _depth: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn file(&mut self, path: &Path, info: &FileInfo, _depth: usize) -> Result<()> {
+        let group = file_kind(path, info);
+        self.groups.entry(group.clone()).or_default()
+            .push(GroupedFile { path: path.to_path_buf(), size: info.size, summary: None });
+        self.current = Some(group);
+        Ok(())
+    }
+
+    fn summary(&mut self, summary: &str, _depth: usize) -> Result<()> {
+        if let Some(file) = self.current.as_ref()
+            .and_then(|group| self.groups.get_mut(group))
+            .and_then(|files| files.last_mut())
+        {
+            file.summary = Some(summary.to_string());
+        }
+        Ok(())
+    }
+
+    fn summary_error(&mut self, error: &anyhow::Error, depth: usize) -> Result<()> {
+        self.text.summary_error(error, depth)
+    }
+
+    fn batch_started(&mut self) -> Result<()> {
+        self.text.batch_started()
+    }
+
+    /// Batch summaries are keyed by path, which finds their files' groups.
+    fn batch_summaries(&mut self, summaries: &HashMap<String, String>) -> Result<()> {
+        let groups: Vec<(String, PathBuf)> = self.groups.iter()
+            .flat_map(|(group, files)| files.iter().map(move |file| (group.clone(), file.path.clone())))
+            .collect();
+        for (group, path) in groups {
+            if let Some(summary) = summaries.get(path.to_string_lossy().as_ref()) {
+                self.set_summary(&group, &path, summary);
+            }
+        }
+        Ok(())
+    }
+
+    fn batch_failures(&mut self, errors: &HashMap<String, String>) -> Result<()> {
+        self.text.batch_failures(errors)
+    }
+
+    fn batch_answer(&mut self, answer: &str) -> Result<()> {
+        self.text.batch_answer(answer)
+    }
+
+    fn batch_error(&mut self, error: &anyhow::Error) -> Result<()> {
+        self.text.batch_error(error)
+    }
+
+    fn finish(&mut self, stats: &Stats) -> Result<()> {
+        for (group, mut files) in std::mem::take(&mut self.groups) {
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            writeln!(self.text.out, "\n== {} ==", group)?;
+            for file in files {
+                if self.text.quiet {
+                    self.text.current = file.path.clone();
+                } else {
+                    writeln!(self.text.out, "📄 {} ({})", file.path.display(), format_size(file.size))?;
+                }
+                if let Some(summary) = &file.summary {
+                    self.text.summary(summary, 0)?;
+                }
+            }
+        }
+        self.current = None;
+        self.text.finish(stats)
+    }
+}
+
+/// Renders exploration results as JSON. In `lines` mode every file is
+/// written as soon as it is complete; otherwise a single document is
+/// written once the exploration finishes.
+pub struct JsonWriter<W: Write> {
+    out: W,
+    lines: bool,
+    root: Option<String>,
+    files: Vec<FileRecord>,
+    pending: Option<FileRecord>,
+    directory_summaries: Vec<DirectorySummary>,
+    batch_summaries: Vec<BatchSummary>,
+    batch_failures: Vec<BatchFailure>,
+    batch_answer: Option<String>,
+}
+
+impl<W: Write> JsonWriter<W> {
+    pub fn new(out: W, lines: bool) -> Self {
+        Self {
+            out,
+            lines,
+            root: None,
+            files: Vec::new(),
+            pending: None,
+            directory_summaries: Vec::new(),
+            batch_summaries: Vec::new(),
+            batch_failures: Vec::new(),
+            batch_answer: None,
+        }
+    }
+
+    fn flush_pending(&mut self) -> Result<()> {
+        if let Some(record) = self.pending.take() {
+            if self.lines {
+                self.write_line(&serde_json::to_value(&record)?)?;
+            } else {
+                self.files.push(record);
+            }
+        }
+        Ok(())
+    }
+
+    fn write_line(&mut self, value: &Value) -> Result<()> {
+        serde_json::to_writer(&mut self.out, value)?;
+        writeln!(self.out)?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> OutputWriter for JsonWriter<W> {
+    fn begin(&mut self, root: &Path) -> Result<()> {
+        self.root = Some(root.to_string_lossy().into_owned());
+        Ok(())
+    }
+
+    fn directory(&mut self, _path: &Path, _depth: usize) -> Result<()> {
+        self.flush_pending()
+    }
+
+    fn directory_summary(&mut self, path: &Path, summary: &str, _depth: usize) -> Result<()> {
+        let record = DirectorySummary { path: path.to_string_lossy().into_owned(), summary: summary.to_string() };
+        if self.lines {
+            self.write_line(&json!({ "directory_summary": record }))
+        } else {
+            self.directory_summaries.push(record);
+            Ok(())
+        }
+    }
+
+    fn file(&mut self, path: &Path, info: &FileInfo, _depth: usize) -> Result<()> {
+        self.flush_pending()?;
+        self.pending = Some(FileRecord {
+            path: path.to_string_lossy().into_owned(),
+            size: info.size,
+            is_text: info.is_text,
+            interpreter: info.interpreter.clone(),
+            summary: None,
+            metrics: None,
+        });
+        Ok(())
+    }
+
+    fn metrics(&mut self, metrics: &FileMetrics, _depth: usize) -> Result<()> {
+        if let Some(record) = self.pending.as_mut() {
+            record.metrics = Some(*metrics);
+        }
+        Ok(())
+    }
+
+    fn summary(&mut self, summary: &str, _depth: usize) -> Result<()> {
+        if let Some(record) = self.pending.as_mut() {
+            record.summary = Some(match StructuredSummary::parse(summary) {
+                Some(structured) => serde_json::to_value(structured)?,
+                None => Value::String(summary.to_string()),
+            });
+        }
+        Ok(())
+    }
+
+    fn summary_error(&mut self, error: &anyhow::Error, _depth: usize) -> Result<()> {
+        let path = self.pending.as_ref().map(|r| r.path.as_str()).unwrap_or_default();
+        eprintln!("⚠️ Failed to generate summary for {}: {}", path, error);
+        Ok(())
+    }
+
+    fn batch_started(&mut self) -> Result<()> {
+        self.flush_pending()
+    }
+
+    fn batch_summaries(&mut self, summaries: &HashMap<String, String>) -> Result<()> {
+        let mut entries: Vec<BatchSummary> = summaries
+            .iter()
+            .map(|(path, summary)| BatchSummary { path: path.clone(), summary: summary.clone() })
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        self.batch_summaries = entries;
+        Ok(())
+    }
+
+    fn batch_failures(&mut self, errors: &HashMap<String, String>) -> Result<()> {
+        let mut entries: Vec<BatchFailure> = errors
+            .iter()
+            .map(|(path, error)| BatchFailure { path: path.clone(), error: error.clone() })
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        self.batch_failures = entries;
+        Ok(())
+    }
+
+    fn batch_answer(&mut self, answer: &str) -> Result<()> {
+        self.batch_answer = Some(answer.to_string());
+        Ok(())
+    }
+
+    fn batch_error(&mut self, error: &anyhow::Error) -> Result<()> {
+        eprintln!("⚠️ Error processing files: {}", error);
+        Ok(())
+    }
+
+    fn finish(&mut self, stats: &Stats) -> Result<()> {
+        self.flush_pending()?;
+        let batch_summaries = std::mem::take(&mut self.batch_summaries);
+        let batch_failures = std::mem::take(&mut self.batch_failures);
+        let batch_answer = self.batch_answer.take();
+
+        if self.lines {
+            if !batch_summaries.is_empty() {
+                self.write_line(&json!({ "batch_summaries": batch_summaries }))?;
+            }
+            if !batch_failures.is_empty() {
+                self.write_line(&json!({ "batch_errors": batch_failures }))?;
+            }
+            if let Some(answer) = batch_answer {
+                self.write_line(&json!({ "batch_answer": answer }))?;
+            }
+            self.write_line(&json!({ "stats": stats }))?;
+        } else {
+            let mut document = json!({
+                "root": self.root.take(),
+                "files": std::mem::take(&mut self.files),
+                "stats": stats,
+            });
+            let directory_summaries = std::mem::take(&mut self.directory_summaries);
+            if !directory_summaries.is_empty() {
+                document["directory_summaries"] = serde_json::to_value(directory_summaries)?;
+            }
+            if !batch_summaries.is_empty() {
+                document["batch_summaries"] = serde_json::to_value(batch_summaries)?;
+            }
+            if !batch_failures.is_empty() {
+                document["batch_errors"] = serde_json::to_value(batch_failures)?;
+            }
+            if let Some(answer) = batch_answer {
+                document["batch_answer"] = Value::String(answer);
+            }
+            serde_json::to_writer_pretty(&mut self.out, &document)?;
+            writeln!(self.out)?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// What `FileExplorer::explore_collect` found under one path.
+#[derive(Debug, Default)]
+pub struct ExplorationReport {
+    /// In the order they were listed
+    pub files: Vec<ExploredFile>,
+    /// With --dir-summaries
+    pub directory_summaries: Vec<(PathBuf, String)>,
+    pub batch_answer: Option<String>,
+    /// Why a batch request failed
+    pub batch_error: Option<String>,
+    pub stats: Stats,
+}
+
+#[derive(Debug)]
+pub struct ExploredFile {
+    pub path: PathBuf,
+    pub info: FileInfo,
+    /// JSON for summaries written with --structured
+    pub summary: Option<String>,
+    /// With --diffstat
+    pub metrics: Option<FileMetrics>,
+    /// Why the summary failed
+    pub error: Option<String>,
+}
+
+/// Fills in an `ExplorationReport` instead of rendering anything.
+pub(crate) struct ReportCollector(pub(crate) Arc<Mutex<ExplorationReport>>);
+
+impl ReportCollector {
+    /// The file named by a batch result, which uses displayed paths.
+    fn batch_file<'a>(report: &'a mut ExplorationReport, path: &str) -> Option<&'a mut ExploredFile> {
+        report.files.iter_mut().find(|file| file.path.display().to_string() == path)
+    }
+}
+
+impl OutputWriter for ReportCollector {
+    fn begin(&mut self, _root: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    fn directory(&mut self, _path: &Path, _depth: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn directory_summary(&mut self, path: &Path, summary: &str, _depth: usize) -> Result<()> {
+        self.0.lock().unwrap().directory_summaries.push((path.to_path_buf(), summary.to_string()));
+        Ok(())
+    }
+
+    fn file(&mut self, path: &Path, info: &FileInfo, _depth: usize) -> Result<()> {
+        self.0.lock().unwrap().files.push(ExploredFile {
+            path: path.to_path_buf(),
+            info: info.clone(),
+            summary: None,
+            metrics: None,
+            error: None,
+        });
+        Ok(())
+    }
+
+    fn metrics(&mut self, metrics: &FileMetrics, _depth: usize) -> Result<()> {
+        if let Some(file) = self.0.lock().unwrap().files.last_mut() {
+            file.metrics = Some(*metrics);
+        }
+        Ok(())
+    }
+
+    fn summary(&mut self, summary: &str, _depth: usize) -> Result<()> {
+        if let Some(file) = self.0.lock().unwrap().files.last_mut() {
+            file.summary = Some(summary.to_string());
+        }
+        Ok(())
+    }
+
+    fn summary_error(&mut self, error: &anyhow::Error, _depth: usize) -> Result<()> {
+        if let Some(file) = self.0.lock().unwrap().files.last_mut() {
+            file.error = Some(format!("{:#}", error));
+        }
+        Ok(())
+    }
+
+    fn batch_started(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn batch_summaries(&mut self, summaries: &HashMap<String, String>) -> Result<()> {
+        let mut report = self.0.lock().unwrap();
+        for (path, summary) in summaries {
+            if let Some(file) = Self::batch_file(&mut report, path) {
+                file.summary = Some(summary.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn batch_failures(&mut self, errors: &HashMap<String, String>) -> Result<()> {
+        let mut report = self.0.lock().unwrap();
+        for (path, error) in errors {
+            if let Some(file) = Self::batch_file(&mut report, path) {
+                file.error = Some(error.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn batch_answer(&mut self, answer: &str) -> Result<()> {
+        self.0.lock().unwrap().batch_answer = Some(answer.to_string());
+        Ok(())
+    }
+
+    fn batch_error(&mut self, error: &anyhow::Error) -> Result<()> {
+        self.0.lock().unwrap().batch_error = Some(format!("{:#}", error));
+        Ok(())
+    }
+
+    fn finish(&mut self, stats: &Stats) -> Result<()> {
+        self.0.lock().unwrap().stats = stats.clone();
+        Ok(())
+    }
+}
+
+/// Forwards every event to each of its writers in turn.
+pub struct TeeWriter {
+    writers: Vec<Box<dyn OutputWriter>>,
+}
+
+impl TeeWriter {
+    pub fn new(writers: Vec<Box<dyn OutputWriter>>) -> Self {
+        Self { writers }
+    }
+}
+
+impl OutputWriter for TeeWriter {
+    fn begin(&mut self, root: &Path) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.begin(root))
+    }
+
+    fn directory(&mut self, path: &Path, depth: usize) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.directory(path, depth))
+    }
+
+    fn directory_summary(&mut self, path: &Path, summary: &str, depth: usize) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.directory_summary(path, summary, depth))
+    }
+
+    fn file(&mut self, path: &Path, info: &FileInfo, depth: usize) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.file(path, info, depth))
+    }
+
+    fn metrics(&mut self, metrics: &FileMetrics, depth: usize) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.metrics(metrics, depth))
+    }
+
+    fn summary(&mut self, summary: &str, depth: usize) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.summary(summary, depth))
+    }
+
+    fn summary_chunk(&mut self, chunk: &str, first: bool, depth: usize) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.summary_chunk(chunk, first, depth))
+    }
+
+    fn summary_streamed(&mut self, summary: &str, depth: usize) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.summary_streamed(summary, depth))
+    }
+
+    fn summary_error(&mut self, error: &anyhow::Error, depth: usize) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.summary_error(error, depth))
+    }
+
+    fn batch_started(&mut self) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.batch_started())
+    }
+
+    fn batch_summaries(&mut self, summaries: &HashMap<String, String>) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.batch_summaries(summaries))
+    }
+
+    fn batch_failures(&mut self, errors: &HashMap<String, String>) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.batch_failures(errors))
+    }
+
+    fn batch_answer(&mut self, answer: &str) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.batch_answer(answer))
+    }
+
+    fn batch_error(&mut self, error: &anyhow::Error) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.batch_error(error))
+    }
+
+    fn finish(&mut self, stats: &Stats) -> Result<()> {
+        self.writers.iter_mut().try_for_each(|w| w.finish(stats))
+    }
+}
+
+/// Collects a markdown section per file and writes the report when an
+/// exploration finishes. The file is replaced atomically, so an
+/// interrupted run leaves the previous report intact.
+pub struct MarkdownReport {
+    path: PathBuf,
+    content: String,
+}
+
+impl MarkdownReport {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, content: String::new() }
+    }
+
+    fn push_quote(&mut self, text: &str) {
+        for line in text.lines() {
+            if line.is_empty() {
+                self.content.push_str(">\n");
+            } else {
+                self.content.push_str(&format!("> {}\n", line));
+            }
+        }
+        self.content.push('\n');
+    }
+
+    fn persist(&self) -> Result<()> {
+        let dir = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut file = tempfile::NamedTempFile::new_in(dir)
+            .with_context(|| format!("Failed to create report in {}", dir.display()))?;
+        file.write_all(self.content.as_bytes())?;
+        file.persist(&self.path)
+            .with_context(|| format!("Failed to write report {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+impl OutputWriter for MarkdownReport {
+    fn begin(&mut self, root: &Path) -> Result<()> {
+        self.content.push_str(&format!("# {}\n\n", root.display()));
+        Ok(())
+    }
+
+    fn directory(&mut self, _path: &Path, _depth: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn directory_summary(&mut self, path: &Path, summary: &str, _depth: usize) -> Result<()> {
+        self.content.push_str(&format!("## {}/\n\n", path.display()));
+        self.push_quote(summary);
+        Ok(())
+    }
+
+    fn file(&mut self, path: &Path, info: &FileInfo, _depth: usize) -> Result<()> {
+        self.content.push_str(&format!("## {}\n\n", path.display()));
+        self.content.push_str(&format!("- Size: {}\n", format_size(info.size)));
+        if let Some(interpreter) = &info.interpreter {
+            self.content.push_str(&format!("- Interpreter: {}\n", interpreter));
+        }
+        self.content.push('\n');
+        Ok(())
+    }
+
+    fn summary(&mut self, summary: &str, _depth: usize) -> Result<()> {
+        match StructuredSummary::parse(summary) {
+            Some(structured) => {
+                for (label, value) in structured.fields() {
+                    self.content.push_str(&format!("- **{}:** {}\n", label, value));
+                }
+                self.content.push('\n');
+            }
+            None => self.push_quote(summary),
+        }
+        Ok(())
+    }
+
+    fn summary_error(&mut self, error: &anyhow::Error, _depth: usize) -> Result<()> {
+        self.content.push_str(&format!("_Failed to generate summary: {}_\n\n", error));
+        Ok(())
+    }
+
+    fn batch_started(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn batch_summaries(&mut self, summaries: &HashMap<String, String>) -> Result<()> {
+        let mut entries: Vec<_> = summaries.iter().collect();
+        entries.sort();
+        for (path, summary) in entries {
+            self.content.push_str(&format!("## {}\n\n", path));
+            self.push_quote(summary);
+        }
+        Ok(())
+    }
+
+    fn batch_failures(&mut self, errors: &HashMap<String, String>) -> Result<()> {
+        let mut entries: Vec<_> = errors.iter().collect();
+        entries.sort();
+        for (path, error) in entries {
+            self.content.push_str(&format!("## {}\n\n_Failed to generate summary: {}_\n\n", path, error));
+        }
+        Ok(())
+    }
+
+    fn batch_answer(&mut self, answer: &str) -> Result<()> {
+        self.content.push_str("## Answer\n\n");
+        self.push_quote(answer);
+        Ok(())
+    }
+
+    fn batch_error(&mut self, error: &anyhow::Error) -> Result<()> {
+        self.content.push_str(&format!("_Error processing files: {}_\n\n", error));
+        Ok(())
+    }
+
+    fn finish(&mut self, stats: &Stats) -> Result<()> {
+        self.content.push_str(&format!(
+            "Total directories: {}, total files: {}\n\n",
+            stats.total_dirs, stats.total_files
+        ));
+        if let Some(changed) = stats.changed.as_ref().filter(|changed| !changed.is_empty()) {
+            self.content.push_str("## Changed since last run\n\n");
+            for path in changed {
+                self.content.push_str(&format!("- {}\n", path));
+            }
+            self.content.push('\n');
+        }
+        // Rewritten after every explored path so the report always holds
+        // everything seen so far
+        self.persist()
+    }
+}
+
+/// Writes each summary to its own markdown file under `dir`, at the
+/// summarized file's path relative to the explored root plus `.md`.
+pub struct SummaryFiles {
+    dir: PathBuf,
+    root: PathBuf,
+    current: PathBuf,
+    /// Output files written so far, with the source each one belongs to
+    written: HashMap<PathBuf, PathBuf>,
+}
+
+impl SummaryFiles {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, root: PathBuf::new(), current: PathBuf::new(), written: HashMap::new() }
+    }
+
+    /// Where the summary of `source` goes. Only the normal components of
+    /// its relative path are kept, so `..` or an absolute path can't lead
+    /// outside `dir`. A file that another source (from another explored
+    /// root, say) already claimed gets a numbered name instead.
+    fn output_path(&mut self, source: &Path) -> PathBuf {
+        let relative = source.strip_prefix(&self.root).unwrap_or(source);
+        let mut mirrored: PathBuf = relative.components()
+            .filter(|component| matches!(component, std::path::Component::Normal(_)))
+            .collect();
+        if mirrored.as_os_str().is_empty() {
+            mirrored = source.file_name().map(PathBuf::from).unwrap_or_else(|| PathBuf::from("summary"));
+        }
+        let name = mirrored.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+        let mut path = self.dir.join(mirrored.with_file_name(format!("{}.md", name)));
+        let mut attempt = 1;
+        while self.written.get(&path).is_some_and(|owner| owner != source) {
+            attempt += 1;
+            path = self.dir.join(mirrored.with_file_name(format!("{}.{}.md", name, attempt)));
+        }
+        self.written.insert(path.clone(), source.to_path_buf());
+        path
+    }
+
+    fn write(&mut self, source: &Path, summary: &str) -> Result<()> {
+        let path = self.output_path(source);
+        let mut content = format!("# {}\n\n", source.display());
+        match StructuredSummary::parse(summary) {
+            Some(structured) => {
+                for (label, value) in structured.fields() {
+                    content.push_str(&format!("- **{}:** {}\n", label, value));
+                }
+            }
+            None => content.push_str(&format!("{}\n", summary.trim_end())),
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+impl OutputWriter for SummaryFiles {
+    fn begin(&mut self, root: &Path) -> Result<()> {
+        // A single explored file mirrors to its own name
+        self.root = match root.parent() {
+            Some(parent) if root.is_file() => parent.to_path_buf(),
+            _ => root.to_path_buf(),
+        };
+        Ok(())
+    }
+
+    fn directory(&mut self, _path: &Path, _depth: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn file(&mut self, path: &Path, _info: &FileInfo, _depth: usize) -> Result<()> {
+        self.current = path.to_path_buf();
+        Ok(())
+    }
+
+    fn summary(&mut self, summary: &str, _depth: usize) -> Result<()> {
+        let current = self.current.clone();
+        self.write(&current, summary)
+    }
+
+    fn summary_error(&mut self, _error: &anyhow::Error, _depth: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn batch_started(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn batch_summaries(&mut self, summaries: &HashMap<String, String>) -> Result<()> {
+        let mut entries: Vec<_> = summaries.iter().collect();
+        entries.sort();
+        entries.into_iter().try_for_each(|(path, summary)| self.write(Path::new(path), summary))
+    }
+
+    fn batch_failures(&mut self, _errors: &HashMap<String, String>) -> Result<()> {
+        Ok(())
+    }
+
+    fn batch_answer(&mut self, _answer: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn batch_error(&mut self, _error: &anyhow::Error) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self, _stats: &Stats) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Posts each summary to a webhook as `{path, summary, text}`, where
+/// `text` is the `path: summary` line that chat webhooks display. A post
+/// that fails is logged and doesn't stop the run.
+///
+/// The posts are made in order on a thread of their own, so a slow
+/// webhook doesn't hold up the async summaries; `finish` waits for the
+/// ones queued so far.
+pub struct WebhookWriter {
+    sender: mpsc::Sender<WebhookMessage>,
+    root: PathBuf,
+    current: PathBuf,
+}
+
+enum WebhookMessage {
+    Post(PathBuf, Value),
+    Flush(mpsc::SyncSender<()>),
+}
+
+impl WebhookWriter {
+    pub fn new(url: String, agent: ureq::Agent) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for message in receiver {
+                match message {
+                    WebhookMessage::Post(path, payload) => {
+                        if let Err(e) = agent.post(&url).send_json(payload) {
+                            warn!("Failed to post the summary of {} to the webhook: {}", path.display(), e);
+                        }
+                    }
+                    WebhookMessage::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        Self { sender, root: PathBuf::new(), current: PathBuf::new() }
+    }
+
+    fn post(&self, path: &Path, summary: &str) {
+        let summary_value = match StructuredSummary::parse(summary) {
+            Some(structured) => serde_json::to_value(structured).unwrap_or_else(|_| Value::String(summary.to_string())),
+            None => Value::String(summary.to_string()),
+        };
+        let payload = json!({
+            "path": path.display().to_string(),
+            "summary": summary_value,
+            "text": format!("{}: {}", path.display(), summary),
+        });
+        // Only fails if the posting thread is gone, which leaves nothing to do
+        let _ = self.sender.send(WebhookMessage::Post(path.to_path_buf(), payload));
+    }
+}
+
+impl OutputWriter for WebhookWriter {
+    fn begin(&mut self, root: &Path) -> Result<()> {
+        self.root = root.to_path_buf();
+        Ok(())
+    }
+
+    fn directory(&mut self, _path: &Path, _depth: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn file(&mut self, path: &Path, _info: &FileInfo, _depth: usize) -> Result<()> {
+        self.current = path.to_path_buf();
+        Ok(())
+    }
+
+    fn summary(&mut self, summary: &str, _depth: usize) -> Result<()> {
+        self.post(&self.current, summary);
+        Ok(())
+    }
+
+    fn summary_error(&mut self, _error: &anyhow::Error, _depth: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn batch_started(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn batch_summaries(&mut self, summaries: &HashMap<String, String>) -> Result<()> {
+        let mut entries: Vec<_> = summaries.iter().collect();
+        entries.sort();
+        for (path, summary) in entries {
+            self.post(Path::new(path), summary);
+        }
+        Ok(())
+    }
+
+    fn batch_failures(&mut self, _errors: &HashMap<String, String>) -> Result<()> {
+        Ok(())
+    }
+
+    /// The answer covers the whole explored path, so it is posted once
+    /// under it.
+    fn batch_answer(&mut self, answer: &str) -> Result<()> {
+        self.post(&self.root, answer);
+        Ok(())
+    }
+
+    fn batch_error(&mut self, _error: &anyhow::Error) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self, _stats: &Stats) -> Result<()> {
+        let (done, posted) = mpsc::sync_channel(1);
+        if self.sender.send(WebhookMessage::Flush(done)).is_ok() {
+            let _ = posted.recv();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use serde::Serialize;
+
+/// Approximate list prices in USD per 1K tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub input_per_1k: f64,
+    pub output_per_1k: f64,
+}
+
+const PRICES: &[(&str, ModelPrice)] = &[
+    ("gpt-4o", ModelPrice { input_per_1k: 0.0025, output_per_1k: 0.01 }),
+    ("gpt-4o-mini", ModelPrice { input_per_1k: 0.00015, output_per_1k: 0.0006 }),
+    ("gpt-4-turbo", ModelPrice { input_per_1k: 0.01, output_per_1k: 0.03 }),
+    ("gpt-4", ModelPrice { input_per_1k: 0.03, output_per_1k: 0.06 }),
+    ("gpt-3.5-turbo", ModelPrice { input_per_1k: 0.0005, output_per_1k: 0.0015 }),
+    ("o1", ModelPrice { input_per_1k: 0.015, output_per_1k: 0.06 }),
+    ("o1-mini", ModelPrice { input_per_1k: 0.003, output_per_1k: 0.012 }),
+    ("claude-3-5-haiku-latest", ModelPrice { input_per_1k: 0.0008, output_per_1k: 0.004 }),
+    ("claude-3-5-sonnet-latest", ModelPrice { input_per_1k: 0.003, output_per_1k: 0.015 }),
+    ("claude-3-7-sonnet-latest", ModelPrice { input_per_1k: 0.003, output_per_1k: 0.015 }),
+    ("claude-3-opus-latest", ModelPrice { input_per_1k: 0.015, output_per_1k: 0.075 }),
+    ("claude-sonnet-4-0", ModelPrice { input_per_1k: 0.003, output_per_1k: 0.015 }),
+    ("claude-opus-4-0", ModelPrice { input_per_1k: 0.015, output_per_1k: 0.075 }),
+];
+
+pub fn price_for(model: &str) -> Option<ModelPrice> {
+    PRICES.iter()
+        .find(|(name, _)| *name == model)
+        .map(|(_, price)| *price)
+}
+
+/// Estimated cost in USD, or `None` when the model has no known price.
+pub fn estimate_cost(model: &str, input_tokens: u64, output_tokens: u64) -> Option<f64> {
+    price_for(model).map(|price| {
+        input_tokens as f64 / 1000.0 * price.input_per_1k
+            + output_tokens as f64 / 1000.0 * price.output_per_1k
+    })
+}
+
+/// Tokens sent and received by the requests of a run, with their cost.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CostReport {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// `None` once a request went to a model without a known price
+    pub cost: Option<f64>,
+}
+
+impl Default for CostReport {
+    fn default() -> Self {
+        Self { requests: 0, input_tokens: 0, output_tokens: 0, cost: Some(0.0) }
+    }
+}
+
+impl CostReport {
+    /// Count a request to `model` at its price.
+    pub fn add(&mut self, model: &str, input_tokens: u64, output_tokens: u64) {
+        let cost = estimate_cost(model, input_tokens, output_tokens);
+        self.cost = self.cost.zip(cost).map(|(total, cost)| total + cost);
+        self.requests += 1;
+        self.input_tokens += input_tokens;
+        self.output_tokens += output_tokens;
+    }
+}
+
+/// Rough token count using the ~4 characters per token rule of thumb.
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.chars().count() as u64).div_ceil(4)
+}
@@ -0,0 +1,35 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+const REPLACEMENT: &str = "[REDACTED]";
+
+// Secrets to hide from the model. Group 1 is context that is kept, such as
+// the name in `PASSWORD=...`; the rest of the match is replaced.
+static SECRET_PATTERNS: Lazy<Vec<Regex>> = Lazy::new(|| {
+    [
+        // PEM private key blocks, header to footer
+        r"()-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+        // AWS access key IDs
+        r"()\b(?:AKIA|ASIA)[0-9A-Z]{16}\b",
+        // GitHub and OpenAI-style API tokens
+        r"()\bgh[pousr]_[A-Za-z0-9]{36,}\b",
+        r"()\bsk-[A-Za-z0-9_-]{20,}",
+        // Authorization headers
+        r"(?i)(\bbearer\s+)[A-Za-z0-9\-._~+/]+=*",
+        // Assignments to names like PASSWORD, DB_PASSWD, api_key or
+        // aws_secret_access_key, in .env, config or source form
+        r#"(?i)(\b[\w.-]*(?:password|passwd|pwd|secret|token|api[_-]?key)[\w.-]*["']?\s*[=:]\s*)["']?[^\s"',;]+["']?"#,
+    ]
+    .iter()
+    .map(|pattern| Regex::new(pattern).expect("invalid secret pattern"))
+    .collect()
+});
+
+/// `content` with anything that looks like a key, token, password or
+/// private key replaced by `[REDACTED]`, for --redact-secrets.
+pub fn redact(content: &str) -> String {
+    let replacement = format!("${{1}}{}", REPLACEMENT);
+    SECRET_PATTERNS.iter().fold(content.to_string(), |content, pattern| {
+        pattern.replace_all(&content, replacement.as_str()).into_owned()
+    })
+}
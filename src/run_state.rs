@@ -0,0 +1,59 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+
+const RUN_STATE_FILE: &str = "run-state.txt";
+
+/// The files the current run has summarized, one absolute path per line
+/// in the cache dir. Each line is written as soon as its file is done, so
+/// an interrupted run leaves everything it finished for --resume.
+pub struct RunState {
+    path: PathBuf,
+    completed: HashSet<PathBuf>,
+    file: File,
+}
+
+impl RunState {
+    /// Start a run, continuing the files a previous run listed with
+    /// `resume` and starting over without it.
+    pub fn start(cache_dir: &Path, resume: bool) -> Result<Self> {
+        let path = cache_dir.join(RUN_STATE_FILE);
+        let completed = if resume {
+            fs::read_to_string(&path)
+                .map(|content| content.lines().map(PathBuf::from).collect())
+                .unwrap_or_default()
+        } else {
+            HashSet::new()
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open run state {}", path.display()))?;
+        if !resume {
+            file.set_len(0)?;
+        }
+        Ok(Self { path, completed, file })
+    }
+
+    /// Whether an earlier, interrupted run already summarized `path`.
+    pub fn is_completed(&self, path: &Path) -> bool {
+        std::path::absolute(path).is_ok_and(|path| self.completed.contains(&path))
+    }
+
+    pub fn complete(&mut self, path: &Path) -> Result<()> {
+        let path = std::path::absolute(path)?;
+        writeln!(self.file, "{}", path.display())?;
+        self.completed.insert(path);
+        Ok(())
+    }
+
+    /// The run finished, so there is nothing left to resume.
+    pub fn clear(self) -> Result<()> {
+        fs::remove_file(&self.path)
+            .with_context(|| format!("Failed to remove run state {}", self.path.display()))
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::Path;
+use crate::archive::{is_archive, ArchiveContents, ArchiveEntry};
+
+fn entry(path: &str) -> ArchiveEntry {
+    ArchiveEntry { path: path.to_string(), size: 10 }
+}
+
+#[test]
+fn test_is_archive() {
+    assert!(is_archive(Path::new("release.zip")));
+    assert!(is_archive(Path::new("dist/release-1.0.TAR.GZ")));
+    assert!(is_archive(Path::new("release.tgz")));
+    assert!(!is_archive(Path::new("release.gz")));
+    assert!(!is_archive(Path::new("zip.rs")));
+}
+
+#[test]
+fn test_describe_lists_top_level_and_fitting_texts() {
+    let contents = ArchiveContents {
+        entries: vec![entry("src/main.rs"), entry("src/lib.rs"), entry("README.md")],
+        texts: vec![
+            ("README.md".to_string(), "A tool".to_string()),
+            ("src/main.rs".to_string(), "x".repeat(100)),
+        ],
+    };
+
+    let description = contents.describe(120);
+    assert!(description.starts_with("An archive containing 3 files:\n- README.md\n- src/ (2 files)\n"), "{}", description);
+    assert!(description.contains("--- README.md ---\nA tool\n"));
+    assert!(!description.contains("src/main.rs"));
+}
+
+#[cfg(feature = "archives")]
+#[test]
+fn test_read_caps_extracted_bytes() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("release.zip");
+    let mut writer = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+    let options = zip::write::SimpleFileOptions::default();
+    writer.add_directory("docs/", options).unwrap();
+    writer.start_file("docs/guide.md", options).unwrap();
+    writer.write_all(b"# Guide").unwrap();
+    writer.start_file("big.txt", options).unwrap();
+    writer.write_all(&[b'a'; 1000]).unwrap();
+    writer.finish().unwrap();
+
+    let contents = crate::archive::read(&path, 100).unwrap();
+    assert_eq!(contents.entries.len(), 2);
+    assert_eq!(contents.texts, vec![("docs/guide.md".to_string(), "# Guide".to_string())]);
+}
@@ -0,0 +1,677 @@
+use std::collections::HashMap;
+use std::path::Path;
+use anyhow::Result;
+use crate::file_explorer::{FileExplorer, PrecacheStats, SortOrder, TokenCounts, TypeBreakdown, TypeStats};
+use crate::gpt_client::{ClientConfig, GPTClient};
+use crate::output::{OutputWriter, Stats, TextWriter};
+use crate::utils::FileInfo;
+use super::SharedOutput;
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_symlinked_files_follow_the_flag() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = tempfile::tempdir().unwrap();
+    std::fs::write(target.path().join("linked.txt"), "linked").unwrap();
+    std::fs::write(dir.path().join("plain.txt"), "plain").unwrap();
+    std::os::unix::fs::symlink(target.path().join("linked.txt"), dir.path().join("link.txt")).unwrap();
+
+    for (follow, expected_files) in [(false, 1), (true, 2)] {
+        let mut explorer = FileExplorer::new(3);
+        explorer.set_writer(Box::new(TextWriter::new(std::io::sink())));
+        explorer.set_follow_symlinks(follow);
+        explorer.explore(dir.path()).await.unwrap();
+        assert_eq!(explorer.total_files, expected_files, "follow_symlinks = {}", follow);
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_symlink_loop_is_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    std::fs::write(dir.path().join("sub/file.txt"), "text").unwrap();
+    std::os::unix::fs::symlink(dir.path(), dir.path().join("sub/loop")).unwrap();
+
+    let mut explorer = FileExplorer::new(5);
+    explorer.set_writer(Box::new(TextWriter::new(std::io::sink())));
+    explorer.set_follow_symlinks(true);
+    explorer.explore(dir.path()).await.unwrap();
+    assert_eq!(explorer.total_files, 1);
+    assert_eq!(explorer.total_dirs, 2);
+}
+
+/// Records the order in which files and directory summaries are reported.
+struct FileOrder(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+impl OutputWriter for FileOrder {
+    fn begin(&mut self, _root: &Path) -> Result<()> { Ok(()) }
+    fn directory(&mut self, _path: &Path, _depth: usize) -> Result<()> { Ok(()) }
+    fn directory_summary(&mut self, path: &Path, summary: &str, _depth: usize) -> Result<()> {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        self.0.lock().unwrap().push(format!("{}/: {}", name, summary));
+        Ok(())
+    }
+    fn file(&mut self, path: &Path, _info: &FileInfo, _depth: usize) -> Result<()> {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        self.0.lock().unwrap().push(name);
+        Ok(())
+    }
+    fn summary(&mut self, _summary: &str, _depth: usize) -> Result<()> { Ok(()) }
+    fn summary_error(&mut self, _error: &anyhow::Error, _depth: usize) -> Result<()> { Ok(()) }
+    fn batch_started(&mut self) -> Result<()> { Ok(()) }
+    fn batch_summaries(&mut self, _summaries: &HashMap<String, String>) -> Result<()> { Ok(()) }
+    fn batch_failures(&mut self, _errors: &HashMap<String, String>) -> Result<()> { Ok(()) }
+    fn batch_answer(&mut self, _answer: &str) -> Result<()> { Ok(()) }
+    fn batch_error(&mut self, _error: &anyhow::Error) -> Result<()> { Ok(()) }
+    fn finish(&mut self, _stats: &Stats) -> Result<()> { Ok(()) }
+}
+
+async fn explored_order(root: &Path, sort: SortOrder) -> Vec<String> {
+    let order = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_writer(Box::new(FileOrder(order.clone())));
+    explorer.set_sort(sort);
+    explorer.explore(root).await.unwrap();
+    let order = order.lock().unwrap().clone();
+    order
+}
+
+#[tokio::test]
+async fn test_sort_by_name_and_size() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("b.txt"), "x".repeat(10)).unwrap();
+    std::fs::write(dir.path().join("A.txt"), "x".repeat(5)).unwrap();
+    std::fs::write(dir.path().join("c.txt"), "x".repeat(20)).unwrap();
+
+    assert_eq!(explored_order(dir.path(), SortOrder::Name).await, ["A.txt", "b.txt", "c.txt"]);
+    assert_eq!(explored_order(dir.path(), SortOrder::Size).await, ["c.txt", "b.txt", "A.txt"]);
+}
+
+#[tokio::test]
+async fn test_excluded_dirs_are_pruned() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("node_modules/pkg")).unwrap();
+    std::fs::write(dir.path().join("node_modules/pkg/index.js"), "js").unwrap();
+    std::fs::create_dir(dir.path().join("build")).unwrap();
+    std::fs::write(dir.path().join("build/out.txt"), "out").unwrap();
+    std::fs::write(dir.path().join("main.rs"), "fn main() {}").unwrap();
+
+    let mut explorer = FileExplorer::new(5);
+    explorer.set_writer(Box::new(TextWriter::new(std::io::sink())));
+    explorer.explore(dir.path()).await.unwrap();
+    assert_eq!((explorer.total_files, explorer.total_dirs), (2, 2));
+
+    let mut explorer = FileExplorer::new(5);
+    explorer.set_writer(Box::new(TextWriter::new(std::io::sink())));
+    explorer.set_excluded_dirs(vec!["build".to_string()]);
+    explorer.explore(dir.path()).await.unwrap();
+    assert_eq!((explorer.total_files, explorer.total_dirs), (2, 3));
+
+    // Exploring an excluded directory directly still lists it
+    let mut explorer = FileExplorer::new(5);
+    explorer.set_writer(Box::new(TextWriter::new(std::io::sink())));
+    explorer.explore(dir.path().join("node_modules")).await.unwrap();
+    assert_eq!(explorer.total_files, 1);
+}
+
+#[tokio::test]
+async fn test_precache_counts_new_and_existing_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.rs"), "fn a() {}").unwrap();
+    std::fs::write(dir.path().join("b.rs"), "fn b() {}").unwrap();
+    std::fs::write(dir.path().join("empty.txt"), "").unwrap();
+    let cache_dir = tempfile::tempdir().unwrap();
+    let base_url = crate::tests::mock_openai("A summary");
+
+    let precache = || async {
+        let mut explorer = FileExplorer::new(3);
+        let client = GPTClient::new(ClientConfig {
+            cache_dir: Some(cache_dir.path().to_path_buf()),
+            base_url: Some(base_url.clone()),
+            model: "local".to_string(),
+            max_retries: 0,
+            ..ClientConfig::default()
+        })
+        .unwrap();
+        explorer.set_summarizer(client, None, None);
+        explorer.precache(dir.path()).await.unwrap()
+    };
+
+    assert_eq!(precache().await, PrecacheStats { newly_cached: 2, already_cached: 0, failed: 0 });
+    assert_eq!(precache().await, PrecacheStats { newly_cached: 0, already_cached: 2, failed: 0 });
+}
+
+#[test]
+fn test_file_types_are_tallied_by_interpreter_then_extension() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.rs"), "fn a() {}").unwrap();
+    std::fs::write(dir.path().join("b.rs"), "fn b() { let x = 1; }").unwrap();
+    std::fs::write(dir.path().join("image.PNG"), [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00]).unwrap();
+
+    let explorer = FileExplorer::new(3);
+    let mut breakdown = TypeBreakdown::default();
+    explorer.tally_file_types(dir.path(), &mut breakdown);
+    assert_eq!(breakdown.rows(), [
+        ("rust", TypeStats { files: 2, bytes: 30 }),
+        ("png", TypeStats { files: 1, bytes: 9 }),
+    ]);
+}
+
+#[tokio::test]
+async fn test_directory_summaries_follow_their_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.rs"), "fn a() {}").unwrap();
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    std::fs::write(dir.path().join("sub/x.rs"), "fn x() {}").unwrap();
+    std::fs::create_dir(dir.path().join("empty")).unwrap();
+    let cache_dir = tempfile::tempdir().unwrap();
+
+    let order = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_writer(Box::new(FileOrder(order.clone())));
+    explorer.set_sort(SortOrder::Name);
+    explorer.set_dir_summaries(true);
+    explorer.set_summarizer(GPTClient::new(ClientConfig {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        base_url: Some(crate::tests::mock_openai("Purpose")),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap(), None, None);
+    explorer.explore(dir.path()).await.unwrap();
+
+    let root = dir.path().file_name().unwrap().to_string_lossy().into_owned();
+    let order = order.lock().unwrap().clone();
+    assert_eq!(order, [format!("{}/: Purpose", root), "a.rs".into(), "sub/: Purpose".into(), "x.rs".into()]);
+}
+
+#[tokio::test]
+async fn test_failed_summaries_are_counted() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.rs"), "fn a() {}").unwrap();
+    std::fs::write(dir.path().join("empty.txt"), "").unwrap();
+    let cache_dir = tempfile::tempdir().unwrap();
+
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_writer(Box::new(TextWriter::new(std::io::sink())));
+    explorer.set_summarizer(GPTClient::new(ClientConfig {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        base_url: Some("http://127.0.0.1:9/v1".to_string()),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap(), None, None);
+    explorer.explore(dir.path()).await.unwrap();
+    assert_eq!(explorer.summary_errors(), 1);
+}
+
+#[test]
+fn test_token_counts_are_sorted_and_grouped() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.rs"), "fn a() {}").unwrap();
+    std::fs::write(dir.path().join("b.rs"), "fn b() { let x = 1; let y = 2; }").unwrap();
+    std::fs::write(dir.path().join("notes.md"), "Some notes").unwrap();
+    std::fs::write(dir.path().join("image.png"), [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00]).unwrap();
+
+    let explorer = FileExplorer::new(3);
+    let mut counts = TokenCounts::default();
+    explorer.count_tokens(dir.path(), "gpt-4o-mini", &mut counts);
+
+    let files = counts.files();
+    assert_eq!(files.len(), 3);
+    assert_eq!(files[0].0, dir.path().join("b.rs"));
+    assert!(files.windows(2).all(|pair| pair[0].2 >= pair[1].2));
+
+    let kinds = counts.by_kind();
+    assert_eq!(kinds.iter().map(|(kind, files, _)| (*kind, *files)).collect::<Vec<_>>(), [("rust", 2), ("md", 1)]);
+    assert_eq!(counts.total(), kinds.iter().map(|(_, _, tokens)| tokens).sum::<usize>());
+}
+
+#[tokio::test]
+async fn test_resumed_run_skips_the_files_already_summarized() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.rs"), "fn a() {}").unwrap();
+    std::fs::write(dir.path().join("b.rs"), "fn b() {}").unwrap();
+    let cache_dir = tempfile::tempdir().unwrap();
+    let client = |base_url: String| GPTClient::new(ClientConfig {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    // The first run gets through a.rs only
+    let failing_b = crate::tests::mock_openai_with(|request| match request.contains("fn b()") {
+        true => (500, "{}".to_string()),
+        false => (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": "A summary" } }] }).to_string()),
+    });
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_writer(Box::new(TextWriter::new(std::io::sink())));
+    explorer.set_summarizer(client(failing_b), None, None);
+    explorer.start_run(false).unwrap();
+    explorer.explore(dir.path()).await.unwrap();
+    explorer.finish_run().unwrap();
+    assert_eq!(explorer.summary_errors(), 1);
+
+    let order = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_writer(Box::new(FileOrder(order.clone())));
+    explorer.set_summarizer(client(crate::tests::mock_openai("A summary")), None, None);
+    explorer.start_run(true).unwrap();
+    explorer.explore(dir.path()).await.unwrap();
+    explorer.finish_run().unwrap();
+    assert_eq!(*order.lock().unwrap(), ["b.rs"]);
+    assert_eq!(explorer.summarizer().unwrap().api_calls(), 1);
+
+    // Finished, so a further --resume has nothing to skip
+    assert!(!cache_dir.path().join("run-state.txt").exists());
+}
+
+#[tokio::test]
+async fn test_summaries_are_posted_to_the_webhook() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.rs"), "fn a() {}").unwrap();
+    let cache_dir = tempfile::tempdir().unwrap();
+    let posted = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let webhook = crate::tests::mock_openai_with({
+        let posted = posted.clone();
+        move |request| {
+            posted.lock().unwrap().push(serde_json::from_str::<serde_json::Value>(request).unwrap());
+            (200, "ok".to_string())
+        }
+    });
+
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_summarizer(GPTClient::new(ClientConfig {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        base_url: Some(crate::tests::mock_openai("A summary")),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap(), None, None);
+    explorer.set_quiet(true);
+    explorer.set_webhook(webhook);
+    explorer.explore(dir.path()).await.unwrap();
+
+    let path = dir.path().join("a.rs").display().to_string();
+    assert_eq!(*posted.lock().unwrap(), [serde_json::json!({
+        "path": path,
+        "summary": "A summary",
+        "text": format!("{}: A summary", path),
+    })]);
+
+    // An unreachable webhook only warns
+    explorer.set_webhook("http://127.0.0.1:9/hook".to_string());
+    explorer.explore(dir.path()).await.unwrap();
+    assert_eq!(explorer.summary_errors(), 0);
+}
+
+#[tokio::test]
+async fn test_max_files_caps_the_summaries() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("src");
+    std::fs::create_dir(&root).unwrap();
+    for name in ["a", "b", "c", "d", "e"] {
+        std::fs::write(root.join(format!("{}.rs", name)), format!("fn {}() {{}}", name)).unwrap();
+    }
+    let report = dir.path().join("REPORT.md");
+
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_quiet(true);
+    explorer.set_report(report.clone());
+    explorer.set_max_files(2);
+    explorer.set_summarizer(GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(crate::tests::mock_openai("A summary")),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap(), None, None);
+    explorer.explore(&root).await.unwrap();
+
+    let content = std::fs::read_to_string(&report).unwrap();
+    assert_eq!(content.matches("> A summary").count(), 2, "{}", content);
+    assert_eq!(content.matches("> (skipped: file cap reached)").count(), 3, "{}", content);
+    assert_eq!(explorer.summarizer().unwrap().api_calls(), 2);
+    assert_eq!(explorer.total_files, 5);
+}
+
+#[tokio::test]
+async fn test_interrupted_run_finishes_in_flight_summaries_only() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["a", "b", "c"] {
+        std::fs::write(dir.path().join(format!("{}.rs", name)), format!("fn {}() {{}}", name)).unwrap();
+    }
+    let cache_dir = tempfile::tempdir().unwrap();
+
+    // Ctrl-C arrives while the first summary is being generated
+    let interrupt = std::sync::Arc::new(std::sync::OnceLock::<std::sync::Arc<std::sync::atomic::AtomicBool>>::new());
+    let base_url = crate::tests::mock_openai_with({
+        let interrupt = interrupt.clone();
+        move |_| {
+            interrupt.get().unwrap().store(true, std::sync::atomic::Ordering::Relaxed);
+            (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": "A summary" } }] }).to_string())
+        }
+    });
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    interrupt.set(client.interrupt_flag()).unwrap();
+
+    let order = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_writer(Box::new(FileOrder(order.clone())));
+    explorer.set_concurrency(1);
+    explorer.set_summarizer(client, None, None);
+    explorer.start_run(false).unwrap();
+    explorer.explore(dir.path()).await.unwrap();
+    explorer.finish_run().unwrap();
+
+    // Every file is still listed, but only the first was summarized
+    assert!(explorer.is_interrupted());
+    assert_eq!(*order.lock().unwrap(), ["a.rs", "b.rs", "c.rs"]);
+    assert_eq!(explorer.summarizer().unwrap().api_calls(), 1);
+    assert_eq!(explorer.summary_errors(), 0);
+
+    // What was done is kept for --resume
+    let state = std::fs::read_to_string(cache_dir.path().join("run-state.txt")).unwrap();
+    assert_eq!(state.lines().count(), 1);
+    assert!(state.contains("a.rs"), "{}", state);
+}
+
+#[tokio::test]
+async fn test_dockerignore_is_respected_only_with_the_flag() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("build")).unwrap();
+    std::fs::write(dir.path().join("main.rs"), "fn main() {}").unwrap();
+    std::fs::write(dir.path().join("build/bundle.js"), "var a = 1;").unwrap();
+    std::fs::write(dir.path().join(".dockerignore"), "build/\n").unwrap();
+
+    let base_url = crate::tests::mock_openai("A summary");
+    for respect_dockerignore in [false, true] {
+        let mut explorer = FileExplorer::new(3);
+        explorer.set_summarizer(GPTClient::new(ClientConfig {
+            cache_dir: Some(tempfile::tempdir().unwrap().keep()),
+            base_url: Some(base_url.clone()),
+            model: "local".to_string(),
+            max_retries: 0,
+            respect_dockerignore,
+            ..ClientConfig::default()
+        })
+        .unwrap(), None, None);
+        let stats = explorer.precache(dir.path()).await.unwrap();
+        // .dockerignore itself is a summarized text file too
+        let expected = if respect_dockerignore { 2 } else { 3 };
+        assert_eq!(stats.newly_cached, expected, "respect_dockerignore = {}", respect_dockerignore);
+    }
+}
+
+#[tokio::test]
+async fn test_summary_prefix_and_suffix_are_filled_in() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("deploy.sh");
+    std::fs::write(&script, "#!/bin/sh\necho deploying\n").unwrap();
+
+    let output = SharedOutput::default();
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_summarizer(GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(crate::tests::mock_openai("Deploys the app")),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap(), None, None);
+    explorer.set_writer(Box::new(TextWriter::new(output.clone())));
+    explorer.set_summary_frame("## {path} ({size}, {interpreter})\n".to_string(), "\n---".to_string());
+    explorer.explore(&script).await.unwrap();
+
+    let printed = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    let expected = format!("## {} (25 B, sh)\nDeploys the app\n---\n", script.display());
+    assert!(printed.contains(&expected), "{}", printed);
+}
+
+/// Fails writes of summary text, like a pipe closed in the middle of a
+/// streamed summary, and counts them.
+#[derive(Clone, Default)]
+struct FailingSummaries(std::sync::Arc<std::sync::Mutex<usize>>);
+
+impl std::io::Write for FailingSummaries {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !String::from_utf8_lossy(buf).contains("chunk") {
+            return Ok(buf.len());
+        }
+        *self.0.lock().unwrap() += 1;
+        Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Broken pipe"))
+    }
+    fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+}
+
+#[tokio::test]
+async fn test_streamed_chunk_write_errors_are_returned() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("main.rs");
+    std::fs::write(&file, "fn main() {}").unwrap();
+    let events: String = ["first chunk", "second chunk"]
+        .iter()
+        .map(|chunk| format!("data: {}\n\n", serde_json::json!({ "choices": [{ "delta": { "content": chunk } }] })))
+        .chain(["data: [DONE]\n\n".to_string()])
+        .collect();
+
+    let output = FailingSummaries::default();
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_summarizer(GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(crate::tests::mock_openai_with(move |_| (200, events.clone()))),
+        model: "local".to_string(),
+        max_retries: 0,
+        stream: true,
+        ..ClientConfig::default()
+    })
+    .unwrap(), None, None);
+    explorer.set_writer(Box::new(TextWriter::new(output.clone())));
+
+    let err = explorer.explore(&file).await.unwrap_err();
+    assert!(err.to_string().contains("Broken pipe"), "{:#}", err);
+    // Nothing more is written after the first chunk fails
+    assert_eq!(*output.0.lock().unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_explore_collect_returns_files_and_summaries_without_printing() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("src")).unwrap();
+    std::fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+    std::fs::write(dir.path().join("logo.bin"), [0u8, 159, 146, 150]).unwrap();
+
+    let output = SharedOutput::default();
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_summarizer(GPTClient::new(ClientConfig {
+        cache_dir: Some(tempfile::tempdir().unwrap().keep()),
+        base_url: Some(crate::tests::mock_openai("The entry point")),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap(), None, None);
+    explorer.set_writer(Box::new(TextWriter::new(output.clone())));
+    explorer.set_sort(SortOrder::Name);
+    let report = explorer.explore_collect(dir.path()).await.unwrap();
+
+    let files: Vec<_> = report.files.iter()
+        .map(|file| (file.path.strip_prefix(dir.path()).unwrap().to_path_buf(), file.info.is_text, file.summary.as_deref()))
+        .collect();
+    assert_eq!(files, [
+        (Path::new("logo.bin").to_path_buf(), false, None),
+        (Path::new("src/main.rs").to_path_buf(), true, Some("The entry point")),
+    ]);
+    assert_eq!((report.stats.total_files, report.stats.total_dirs), (2, 2));
+    assert!(output.0.lock().unwrap().is_empty());
+
+    // The writer is back for a normal exploration
+    explorer.explore(dir.path()).await.unwrap();
+    assert!(!output.0.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_interpreter_filter_limits_summaries_but_not_the_listing() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("app.py"), "print('hi')\n").unwrap();
+    std::fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+    // No extension, detected from its shebang
+    std::fs::write(dir.path().join("tool"), "#!/usr/bin/env python3\nprint('tool')\n").unwrap();
+
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_summarizer(GPTClient::new(ClientConfig {
+        cache_dir: Some(tempfile::tempdir().unwrap().keep()),
+        base_url: Some(crate::tests::mock_openai("A summary")),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap(), None, None);
+    explorer.set_sort(SortOrder::Name);
+    explorer.set_interpreter_filter(vec!["python3".to_string()]);
+    let report = explorer.explore_collect(dir.path()).await.unwrap();
+
+    let files: Vec<_> = report.files.iter()
+        .map(|file| (file.path.file_name().unwrap().to_string_lossy().into_owned(), file.summary.is_some()))
+        .collect();
+    assert_eq!(files, [
+        ("app.py".to_string(), true),
+        ("main.rs".to_string(), false),
+        ("tool".to_string(), true),
+    ]);
+}
+
+#[tokio::test]
+async fn test_readme_context_is_given_to_the_other_files() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("README.md"), "Widgetizer turns gadgets into widgets.\n").unwrap();
+    std::fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+    // Reply with the prompt itself, so the summary shows what was sent
+    let base_url = crate::tests::mock_openai_with(|request| {
+        let body: serde_json::Value = serde_json::from_str(request).unwrap();
+        let prompt = body["messages"][0]["content"].clone();
+        (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": prompt } }] }).to_string())
+    });
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_summarizer(GPTClient::new(ClientConfig {
+        cache_dir: Some(tempfile::tempdir().unwrap().keep()),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        verbose_cache: true,
+        ..ClientConfig::default()
+    })
+    .unwrap(), None, None);
+    explorer.set_sort(SortOrder::Name);
+    explorer.set_readme_context(true);
+    let report = explorer.explore_collect(dir.path()).await.unwrap();
+
+    let summary = |name: &str| report.files.iter()
+        .find(|file| file.path.ends_with(name))
+        .and_then(|file| file.summary.clone())
+        .unwrap();
+    let readme = summary("README.md");
+    assert!(!readme.contains("summary of the project's README"), "{}", readme);
+    let main = summary("main.rs");
+    assert!(main.contains("summary of the project's README"), "{}", main);
+    assert!(main.contains("Widgetizer turns gadgets into widgets."), "{}", main);
+    assert!(main.contains("fn main() {}"), "{}", main);
+
+    // The README summary made for the context is reused from the cache
+    assert_eq!(report.stats.cache.map(|cache| cache.api_calls), Some(2));
+}
+
+#[tokio::test]
+async fn test_readme_context_is_not_carried_over_to_the_next_root() {
+    let dir = tempfile::tempdir().unwrap();
+    let with_readme = dir.path().join("widgets");
+    let without_readme = dir.path().join("gadgets");
+    std::fs::create_dir(&with_readme).unwrap();
+    std::fs::create_dir(&without_readme).unwrap();
+    std::fs::write(with_readme.join("README.md"), "Widgetizer turns gadgets into widgets.\n").unwrap();
+    std::fs::write(with_readme.join("main.rs"), "fn main() {}\n").unwrap();
+    std::fs::write(without_readme.join("lib.rs"), "pub fn gadget() {}\n").unwrap();
+
+    // Reply with the prompt itself, so the summary shows what was sent
+    let base_url = crate::tests::mock_openai_with(|request| {
+        let body: serde_json::Value = serde_json::from_str(request).unwrap();
+        let prompt = body["messages"][0]["content"].clone();
+        (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": prompt } }] }).to_string())
+    });
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_summarizer(GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap(), None, None);
+    explorer.set_readme_context(true);
+    let first = explorer.explore_collect(&with_readme).await.unwrap();
+    let main = first.files.iter().find(|file| file.path.ends_with("main.rs")).and_then(|file| file.summary.clone()).unwrap();
+    assert!(main.contains("Widgetizer"), "{}", main);
+
+    // Neither a directory without a README nor a file root gets the
+    // previous root's context
+    for root in [without_readme.clone(), without_readme.join("lib.rs")] {
+        let report = explorer.explore_collect(&root).await.unwrap();
+        let lib = report.files.iter().find(|file| file.path.ends_with("lib.rs")).and_then(|file| file.summary.clone()).unwrap();
+        assert!(!lib.contains("summary of the project's README") && !lib.contains("Widgetizer"), "{}", lib);
+    }
+}
+
+#[tokio::test]
+async fn test_concurrent_summaries_are_printed_in_sorted_order() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("lib")).unwrap();
+    // Created out of order, so traversal order alone wouldn't sort them
+    for name in ["e.rs", "lib/d.rs", "c.rs", "lib/a.rs", "B.rs"] {
+        std::fs::write(dir.path().join(name), format!("// {}", name)).unwrap();
+    }
+
+    // Reply with the last line of the prompt, the file's content
+    let base_url = crate::tests::mock_openai_with(|request| {
+        let body: serde_json::Value = serde_json::from_str(request).unwrap();
+        let content = body["messages"][0]["content"].as_str().unwrap().lines().last().unwrap().to_string();
+        (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": content } }] }).to_string())
+    });
+    let output = SharedOutput::default();
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_summarizer(GPTClient::new(ClientConfig {
+        cache_dir: Some(tempfile::tempdir().unwrap().keep()),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap(), None, None);
+    explorer.set_writer(Box::new(TextWriter::quiet(output.clone())));
+    explorer.set_concurrency(4);
+    explorer.explore(dir.path()).await.unwrap();
+
+    let printed = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    let summaries: Vec<String> = printed.lines()
+        .filter_map(|line| line.split_once(": "))
+        .filter_map(|(path, summary)| {
+            Some(format!("{} {}", Path::new(path).strip_prefix(dir.path()).ok()?.display(), summary))
+        })
+        .collect();
+    assert_eq!(summaries, ["B.rs // B.rs", "c.rs // c.rs", "e.rs // e.rs", "lib/a.rs // lib/a.rs", "lib/d.rs // lib/d.rs"]);
+}
@@ -0,0 +1,117 @@
+use std::path::Path;
+use std::process::Command;
+use crate::git_log::{build_prompt, read_commits, Commit};
+use crate::gpt_client::{ClientConfig, GPTClient};
+
+fn git(repo: &Path, args: &[&str]) {
+    let status = Command::new("git").arg("-C").arg(repo).args(args).status().unwrap();
+    assert!(status.success(), "git {:?}", args);
+}
+
+fn commit_as(repo: &Path, author: &str, message: &str) {
+    std::fs::write(repo.join("log.txt"), message).unwrap();
+    git(repo, &["add", "log.txt"]);
+    git(repo, &["-c", &format!("user.name={}", author), "-c", "user.email=dev@example.com",
+        "commit", "-q", "-m", message]);
+}
+
+fn temp_repo() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    git(dir.path(), &["init", "-q"]);
+    commit_as(dir.path(), "Alice", "Add the parser");
+    commit_as(dir.path(), "Bob", "Fix a crash on empty input\n\nThe tokenizer assumed at least one line.");
+    commit_as(dir.path(), "Alice", "Document the config file");
+    dir
+}
+
+#[test]
+fn test_recent_commits_are_read_newest_first_and_filtered_by_author() {
+    let repo = temp_repo();
+
+    let commits = read_commits(repo.path(), 2, None).unwrap();
+    let subjects: Vec<_> = commits.iter().map(|commit| commit.subject.as_str()).collect();
+    assert_eq!(subjects, ["Document the config file", "Fix a crash on empty input"]);
+    assert_eq!(commits[1].author, "Bob");
+    assert_eq!(commits[1].body, "The tokenizer assumed at least one line.");
+    assert_eq!(commits[0].hash.len(), 40);
+
+    let by_alice = read_commits(repo.path(), 10, Some("Alice")).unwrap();
+    let subjects: Vec<_> = by_alice.iter().map(|commit| commit.subject.as_str()).collect();
+    assert_eq!(subjects, ["Document the config file", "Add the parser"]);
+
+    assert!(read_commits(&repo.path().join("missing"), 10, None).is_err());
+}
+
+#[test]
+fn test_prompt_lists_commits_oldest_first_with_bodies() {
+    let commit = |hash: &str, subject: &str, body: &str| Commit {
+        hash: hash.to_string(),
+        author: "Alice".to_string(),
+        date: "2024-05-01".to_string(),
+        subject: subject.to_string(),
+        body: body.to_string(),
+    };
+    let prompt = build_prompt(&[
+        commit("bbbbbbbbbbbb", "Fix a crash", "Empty input\nno longer panics"),
+        commit("aaaaaaaaaaaa", "Add the parser", ""),
+    ], "english");
+
+    assert!(prompt.contains("Summarize in english"), "{}", prompt);
+    assert!(prompt.ends_with(
+        "aaaaaaaa 2024-05-01 (Alice): Add the parser\n\
+        bbbbbbbb 2024-05-01 (Alice): Fix a crash\n    Empty input\n    no longer panics\n"
+    ), "{}", prompt);
+}
+
+#[tokio::test]
+async fn test_git_summary_is_cached_until_new_commits_arrive() {
+    let repo = temp_repo();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(repo.path().join(".git/nexplorer-cache")),
+        base_url: Some(crate::tests::mock_openai("Work on parsing and docs")),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    let commits = read_commits(repo.path(), 2, None).unwrap();
+    let summary = client.summarize_git_log(&commits).await.unwrap();
+    assert!(summary.generated);
+    assert_eq!(summary.text, "Work on parsing and docs");
+    assert!(client.summarize_git_log(&commits).await.unwrap().from_cache);
+
+    commit_as(repo.path(), "Bob", "Speed up the tokenizer");
+    let commits = read_commits(repo.path(), 2, None).unwrap();
+    assert!(!client.summarize_git_log(&commits).await.unwrap().from_cache);
+    assert_eq!(client.api_calls(), 2);
+
+    assert!(client.summarize_git_log(&[]).await.is_err());
+}
+
+#[tokio::test]
+async fn test_git_summary_after_a_model_fallback_caches_under_the_fallback() {
+    let repo = temp_repo();
+    let base_url = crate::tests::mock_openai_with(|request| {
+        if request.contains(r#""model":"missing""#) {
+            (404, r#"{"error":{"message":"The model does not exist","type":"invalid_request_error"}}"#.to_string())
+        } else {
+            (200, r#"{"choices":[{"message":{"role":"assistant","content":"Work on parsing"}}]}"#.to_string())
+        }
+    });
+    let config = ClientConfig {
+        cache_dir: Some(repo.path().join(".git/nexplorer-cache")),
+        base_url: Some(base_url),
+        model: "missing".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    };
+    let commits = read_commits(repo.path(), 2, None).unwrap();
+    let client = GPTClient::new(ClientConfig { model_fallback: Some("local".to_string()), ..config.clone() }).unwrap();
+    assert!(client.summarize_git_log(&commits).await.unwrap().generated);
+
+    let fallback = GPTClient::new(ClientConfig { model: "local".to_string(), ..config }).unwrap();
+    let cached = fallback.summarize_git_log(&commits).await.unwrap();
+    assert!(cached.from_cache);
+    assert_eq!(cached.text, "Work on parsing");
+}
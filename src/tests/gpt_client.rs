@@ -0,0 +1,1689 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::gpt_client::{
+    adaptive_preset_length, clean_cache, combine_batch_contents, expand_braces, format_prompt, is_expired, is_timeout, parse_language_map, prune_cache, resolve_api_key, resolve_cache_dir, split_into_chunks,
+    parse_summary_length, validate_model, validate_temperature, BatchResult, ClientConfig, Conversation, GPTClient, Provider,
+    SummaryLength, BATCH_ANSWER_MARKER, STDIN_NAME,
+};
+use crate::gpt_client::language::{detect_language, normalize_language};
+use crate::gpt_client::openai::{
+    chat_completions_url, completion_confidence, completion_text, parse_stream_line, ChatResponse, OpenAIClient, StreamEvent,
+};
+use crate::gpt_client::provider::{backoff_delay, is_retryable, retry_after, ChatMessage, Image};
+use crate::gpt_client::structured::normalize_reply;
+use crate::gpt_client::validate::{references, validate_summary};
+use crate::gpt_client::StructuredSummary;
+use crate::tokenizer::{count_words, is_far_from_target};
+use crate::utils::LineRange;
+use super::with_interpreter_overrides;
+
+fn status_error(code: u16, retry_after_header: Option<&str>) -> ureq::Error {
+    let mut raw = format!("HTTP/1.1 {} Error\r\n", code);
+    if let Some(value) = retry_after_header {
+        raw.push_str(&format!("Retry-After: {}\r\n", value));
+    }
+    raw.push_str("\r\n");
+    let response: ureq::Response = raw.parse().unwrap();
+    ureq::Error::Status(code, response)
+}
+
+#[test]
+fn test_backoff_doubles_from_500ms() {
+    assert_eq!(backoff_delay(1), Duration::from_millis(500));
+    assert_eq!(backoff_delay(2), Duration::from_millis(1000));
+    assert_eq!(backoff_delay(3), Duration::from_millis(2000));
+}
+
+#[test]
+fn test_retryable_status_codes() {
+    assert!(is_retryable(&status_error(429, None)));
+    assert!(is_retryable(&status_error(500, None)));
+    assert!(is_retryable(&status_error(503, None)));
+    assert!(!is_retryable(&status_error(400, None)));
+    assert!(!is_retryable(&status_error(401, None)));
+}
+
+#[test]
+fn test_retry_after_header_is_honored() {
+    assert_eq!(retry_after(&status_error(429, Some("7"))), Some(Duration::from_secs(7)));
+    assert_eq!(retry_after(&status_error(429, None)), None);
+}
+
+#[test]
+fn test_model_allow_list() {
+    assert!(validate_model(Provider::OpenAI, "gpt-4o", false).is_ok());
+    assert!(validate_model(Provider::OpenAI, "gpt-4o-mini", false).is_ok());
+    assert!(validate_model(Provider::OpenAI, "ft:gpt-4o-mini:acme::abc123", false).is_err());
+    assert!(validate_model(Provider::OpenAI, "ft:gpt-4o-mini:acme::abc123", true).is_ok());
+    assert!(validate_model(Provider::Anthropic, "claude-3-5-haiku-latest", false).is_ok());
+    assert!(validate_model(Provider::Anthropic, "gpt-4o", false).is_err());
+}
+
+#[test]
+fn test_cache_ttl_expiration() {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let three_days_ago = now - 3 * 24 * 60 * 60;
+
+    assert!(!is_expired(three_days_ago, 0));
+    assert!(!is_expired(three_days_ago, 7));
+    assert!(is_expired(three_days_ago, 2));
+    assert!(!is_expired(now, 1));
+}
+
+#[test]
+fn test_cache_dir_is_created() {
+    let root = tempfile::tempdir().unwrap();
+    let cache_dir = root.path().join("nested").join("cache");
+    let resolved = resolve_cache_dir(Some(cache_dir.clone())).unwrap();
+    assert_eq!(resolved, cache_dir);
+    assert!(cache_dir.is_dir());
+
+    // A dry run writes nothing, so it leaves the directory alone
+    let untouched = root.path().join("untouched");
+    GPTClient::new(ClientConfig { cache_dir: Some(untouched.clone()), dry_run: true, ..Default::default() }).unwrap();
+    assert!(!untouched.exists());
+}
+
+#[test]
+fn test_cache_dir_pointing_at_file_is_rejected() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let err = resolve_cache_dir(Some(file.path().to_path_buf())).unwrap_err();
+    assert!(err.to_string().contains("is not a directory"));
+}
+
+fn write_cache_entry(dir: &std::path::Path, name: &str, timestamp: u64) {
+    let entry = serde_json::json!({
+        "content_hash": name,
+        "summary": "summary",
+        "timestamp": timestamp,
+        "language": "english",
+        "summary_length": "medium",
+    });
+    std::fs::write(dir.join(format!("{}.json", name)), entry.to_string()).unwrap();
+}
+
+#[test]
+fn test_clean_cache_older_than() {
+    let dir = tempfile::tempdir().unwrap();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    write_cache_entry(dir.path(), "old", now - 10 * 24 * 60 * 60);
+    write_cache_entry(dir.path(), "new", now);
+    std::fs::write(dir.path().join("garbage.json"), "not json").unwrap();
+
+    let cleanup = clean_cache(dir.path(), Some(5)).unwrap();
+    assert_eq!(cleanup.removed, 1);
+    assert!(cleanup.bytes_freed > 0);
+    assert!(!dir.path().join("old.json").exists());
+    assert!(dir.path().join("new.json").exists());
+    assert!(dir.path().join("garbage.json").exists());
+
+    let cleanup = clean_cache(dir.path(), None).unwrap();
+    assert_eq!(cleanup.removed, 1);
+    assert!(dir.path().join("garbage.json").exists());
+
+    // Cleaning a cache that was never written doesn't create it
+    let missing = dir.path().join("missing");
+    assert_eq!(clean_cache(&missing, None).unwrap().removed, 0);
+    assert!(!missing.exists());
+}
+
+#[test]
+fn test_writing_past_the_cache_limit_evicts_the_oldest_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    write_cache_entry(dir.path(), "oldest", now - 300);
+    write_cache_entry(dir.path(), "middle", now - 200);
+    write_cache_entry(dir.path(), "newest", now - 100);
+    let size = |name: &str| std::fs::metadata(dir.path().join(format!("{}.json", name))).unwrap().len();
+
+    // Room for everything but the oldest entry once the new one is written,
+    // though pruning leaves the cache at 90% of the limit
+    let probe = tempfile::tempdir().unwrap();
+    GPTClient::new(ClientConfig { cache_dir: Some(probe.path().to_path_buf()), dry_run: true, ..Default::default() })
+        .unwrap()
+        .add_to_cache("written".to_string(), "summary".to_string(), "english")
+        .unwrap();
+    let written = std::fs::metadata(probe.path().join("written.json")).unwrap().len();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().to_path_buf()),
+        dry_run: true,
+        cache_max_size: Some(size("middle") + size("newest") + written),
+        ..Default::default()
+    })
+    .unwrap();
+    client.add_to_cache("written".to_string(), "summary".to_string(), "english").unwrap();
+
+    for name in ["oldest", "middle"] {
+        assert!(!dir.path().join(format!("{}.json", name)).exists(), "{}", name);
+    }
+    for name in ["newest", "written"] {
+        assert!(dir.path().join(format!("{}.json", name)).exists(), "{}", name);
+    }
+
+    let cleanup = prune_cache(dir.path(), 0).unwrap();
+    assert_eq!((cleanup.removed, cleanup.bytes_kept), (2, 0));
+}
+
+#[test]
+fn test_api_key_precedence() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_file = dir.path().join("key");
+    std::fs::write(&key_file, "file-key\n").unwrap();
+    let config_file = dir.path().join("config.toml");
+    std::fs::write(&config_file, "api_key = \"config-key\"\n").unwrap();
+
+    let key = resolve_api_key(Provider::OpenAI, Some("env-key".to_string()), Some(&key_file), Some(&config_file)).unwrap();
+    assert_eq!(key, "env-key");
+    let key = resolve_api_key(Provider::OpenAI, None, Some(&key_file), Some(&config_file)).unwrap();
+    assert_eq!(key, "file-key");
+    let key = resolve_api_key(Provider::OpenAI, None, None, Some(&config_file)).unwrap();
+    assert_eq!(key, "config-key");
+}
+
+#[test]
+fn test_missing_api_key_lists_locations() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_file = dir.path().join("config.toml");
+    let err = resolve_api_key(Provider::OpenAI, None, None, Some(&config_file)).unwrap_err().to_string();
+    assert!(err.contains("OPENAI_API_KEY"));
+    assert!(err.contains("config.toml"));
+}
+
+#[test]
+fn test_anthropic_key_uses_its_own_config_field() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_file = dir.path().join("config.toml");
+    std::fs::write(&config_file, "api_key = \"openai-key\"\nanthropic_api_key = \"claude-key\"\n").unwrap();
+
+    let key = resolve_api_key(Provider::Anthropic, None, None, Some(&config_file)).unwrap();
+    assert_eq!(key, "claude-key");
+
+    std::fs::write(&config_file, "api_key = \"openai-key\"\n").unwrap();
+    let err = resolve_api_key(Provider::Anthropic, None, None, Some(&config_file)).unwrap_err().to_string();
+    assert!(err.contains("ANTHROPIC_API_KEY"));
+}
+
+fn dry_run_client(cache_dir: &std::path::Path) -> GPTClient {
+    GPTClient::new(ClientConfig {
+        cache_dir: Some(cache_dir.to_path_buf()),
+        dry_run: true,
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_batch_collection_skips_binary_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let text_path = dir.path().join("notes.txt");
+    std::fs::write(&text_path, "plain text notes").unwrap();
+    let binary_path = dir.path().join("program.bin");
+    std::fs::write(&binary_path, [0x7F, 0x45, 0x4C, 0x46, 0x00, 0x01, 0x02, 0x03]).unwrap();
+
+    let client = dry_run_client(&dir.path().join("cache"));
+    client.collect_for_batch(&text_path).await.unwrap();
+    client.collect_for_batch(&binary_path).await.unwrap();
+
+    match client.summarize_batch(None).await.unwrap() {
+        BatchResult::Summaries { summaries, errors } => {
+            assert!(errors.is_empty());
+            assert_eq!(summaries.len(), 1);
+            assert!(summaries.contains_key(text_path.to_string_lossy().as_ref()));
+        }
+        BatchResult::Answer(_) => panic!("expected per-file summaries"),
+    }
+}
+
+#[tokio::test]
+async fn test_batch_collection_truncates_on_a_char_boundary() {
+    // Reply with the prompt itself, so the answer shows what was sent
+    let base_url = crate::tests::mock_openai_with(|request| {
+        let body: serde_json::Value = serde_json::from_str(request).unwrap();
+        let prompt = body["messages"][0]["content"].clone();
+        (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": prompt } }] }).to_string())
+    });
+    let dir = tempfile::tempdir().unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(base_url),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    // "é" takes bytes 1999 and 2000, so a byte cut at 2000 would split it
+    client.collect_content("accents.txt", &format!("{}é{}", "a".repeat(1999), "b".repeat(100)));
+
+    match client.summarize_batch(Some("Any bugs?")).await.unwrap() {
+        BatchResult::Answer(prompt) => {
+            assert!(prompt.contains(&format!("{}é", "a".repeat(1999))));
+            assert!(!prompt.contains("éb"));
+        }
+        BatchResult::Summaries { .. } => panic!("expected an answer"),
+    }
+}
+
+#[tokio::test]
+async fn test_batch_keeps_other_summaries_when_a_file_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let kept = dir.path().join("kept.txt");
+    std::fs::write(&kept, "still here").unwrap();
+    let removed = dir.path().join("removed.txt");
+    std::fs::write(&removed, "gone before the batch runs").unwrap();
+
+    let client = dry_run_client(&dir.path().join("cache"));
+    client.collect_for_batch(&kept).await.unwrap();
+    client.collect_for_batch(&removed).await.unwrap();
+    std::fs::remove_file(&removed).unwrap();
+
+    match client.summarize_batch(None).await.unwrap() {
+        BatchResult::Summaries { summaries, errors } => {
+            assert_eq!(summaries.keys().collect::<Vec<_>>(), [&kept.to_string_lossy().into_owned()]);
+            assert_eq!(errors.keys().collect::<Vec<_>>(), [&removed.to_string_lossy().into_owned()]);
+        }
+        BatchResult::Answer(_) => panic!("expected per-file summaries"),
+    }
+}
+
+#[test]
+fn test_parse_stream_lines() {
+    let delta = r#"data: {"choices":[{"delta":{"content":"Hello"}}]}"#;
+    assert_eq!(parse_stream_line(delta), Some(StreamEvent::Delta("Hello".to_string())));
+    let role_only = r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#;
+    assert_eq!(parse_stream_line(role_only), None);
+    assert_eq!(parse_stream_line("data: [DONE]"), Some(StreamEvent::Done));
+    assert_eq!(parse_stream_line(""), None);
+}
+
+#[test]
+fn test_nested_gitignore_files() {
+    let root = tempfile::tempdir().unwrap();
+    let sub = root.path().join("sub");
+    std::fs::create_dir_all(root.path().join(".git")).unwrap();
+    std::fs::create_dir_all(&sub).unwrap();
+    std::fs::write(root.path().join(".gitignore"), "*.log\n").unwrap();
+    std::fs::write(sub.join(".gitignore"), "secret.txt\n!keep.log\n").unwrap();
+    for name in ["secret.txt", "app.log"] {
+        std::fs::write(root.path().join(name), "x").unwrap();
+    }
+    for name in ["secret.txt", "app.log", "keep.log", "main.rs"] {
+        std::fs::write(sub.join(name), "x").unwrap();
+    }
+
+    let client = dry_run_client(&root.path().join("cache"));
+    assert!(!client.should_ignore(&root.path().join("secret.txt")));
+    assert!(client.should_ignore(&root.path().join("app.log")));
+    assert!(client.should_ignore(&sub.join("secret.txt")));
+    assert!(client.should_ignore(&sub.join("app.log")));
+    assert!(!client.should_ignore(&sub.join("keep.log")));
+    assert!(!client.should_ignore(&sub.join("main.rs")));
+}
+
+#[test]
+fn test_nexplorerignore_excludes_and_overrides_gitignore() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(root.path().join(".git")).unwrap();
+    std::fs::write(root.path().join(".gitignore"), "*.log\n").unwrap();
+    std::fs::write(root.path().join(".nexplorerignore"), "data/\n!debug.log\n").unwrap();
+    std::fs::create_dir_all(root.path().join("data")).unwrap();
+    for name in ["data/big.csv", "main.rs", "app.log", "debug.log"] {
+        std::fs::write(root.path().join(name), "x").unwrap();
+    }
+
+    let client = dry_run_client(&root.path().join("cache"));
+    assert!(client.should_ignore(&root.path().join("data/big.csv")));
+    assert!(!client.should_ignore(&root.path().join("main.rs")));
+    assert!(client.should_ignore(&root.path().join("app.log")));
+    assert!(!client.should_ignore(&root.path().join("debug.log")));
+}
+
+#[test]
+fn test_include_and_ignore_patterns_compose() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        custom_include: Some("src/**/*.rs".to_string()),
+        custom_ignore: Some("*generated*".to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+
+    assert!(!client.should_ignore(std::path::Path::new("src/main.rs")));
+    assert!(!client.should_ignore(std::path::Path::new("./src/gpt_client/mod.rs")));
+    assert!(client.should_ignore(std::path::Path::new("src/generated_test.rs")));
+    assert!(client.should_ignore(std::path::Path::new("README.md")));
+}
+
+#[tokio::test]
+async fn test_excluded_extensions_are_skipped_case_insensitively() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        exclude_extensions: Some(".map, LOCK,min.js".to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+
+    assert!(client.should_ignore(Path::new("dist/app.js.map")));
+    assert!(client.should_ignore(Path::new("Cargo.LOCK")));
+    assert!(client.should_ignore(Path::new("vendor/jquery.min.js")));
+    assert!(!client.should_ignore(Path::new("src/app.js")));
+    // The whole name isn't an extension
+    assert!(!client.should_ignore(Path::new("map")));
+    assert!(!client.should_ignore(Path::new(".map")));
+
+    let map = dir.path().join("app.js.map");
+    let source = dir.path().join("app.js");
+    std::fs::write(&map, "{\"version\":3}").unwrap();
+    std::fs::write(&source, "console.log(1)").unwrap();
+    assert!(client.summarize_file(&map, None).await.unwrap().is_none());
+    assert!(client.summarize_file(&source, None).await.unwrap().is_some());
+
+    // Both lists compose with the globs
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        include_extensions: Some("rs,toml".to_string()),
+        exclude_extensions: Some("toml".to_string()),
+        custom_ignore: Some("generated.rs".to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(!client.should_ignore(Path::new("src/MAIN.RS")));
+    assert!(client.should_ignore(Path::new("src/generated.rs")));
+    assert!(client.should_ignore(Path::new("Cargo.toml")));
+    assert!(client.should_ignore(Path::new("README.md")));
+}
+
+fn ignore_client(dir: &std::path::Path, ignore: &str) -> GPTClient {
+    GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.join("cache")),
+        dry_run: true,
+        custom_ignore: Some(ignore.to_string()),
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+#[test]
+fn test_ignore_patterns_without_slash_match_at_any_depth() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = ignore_client(dir.path(), "*.log,build");
+    assert!(client.should_ignore(Path::new("app.log")));
+    assert!(client.should_ignore(Path::new("src/app.log")));
+    assert!(client.should_ignore(Path::new("./logs/2024/app.log")));
+    assert!(client.should_ignore(Path::new("src/build/out.rs")));
+    assert!(!client.should_ignore(Path::new("src/app.log.rs")));
+    assert!(!client.should_ignore(Path::new("src/builder.rs")));
+}
+
+#[test]
+fn test_ignore_patterns_with_slash_are_anchored() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = ignore_client(dir.path(), "vendor/**,src/*.tmp,docs/");
+    assert!(client.should_ignore(Path::new("vendor/serde/src/lib.rs")));
+    assert!(client.should_ignore(Path::new("src/a.tmp")));
+    assert!(!client.should_ignore(Path::new("src/nested/a.tmp")));
+    assert!(!client.should_ignore(Path::new("crates/vendor/lib.rs")));
+    assert!(client.should_ignore(Path::new("docs/guide/intro.md")));
+}
+
+#[test]
+fn test_ignore_patterns_question_mark_and_double_star() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = ignore_client(dir.path(), "file?.txt,**/fixtures/*.json");
+    assert!(client.should_ignore(Path::new("data/file1.txt")));
+    assert!(!client.should_ignore(Path::new("data/file10.txt")));
+    assert!(!client.should_ignore(Path::new("data/file/.txt")));
+    assert!(client.should_ignore(Path::new("fixtures/a.json")));
+    assert!(client.should_ignore(Path::new("tests/unit/fixtures/a.json")));
+    assert!(!client.should_ignore(Path::new("tests/fixtures/deep/a.json")));
+}
+
+#[test]
+fn test_ignore_patterns_expand_braces() {
+    assert_eq!(expand_braces("file{1,2}.txt"), ["file1.txt", "file2.txt"]);
+    assert_eq!(expand_braces("*.{j{s,sx},ts}"), ["*.js", "*.jsx", "*.ts"]);
+    assert_eq!(expand_braces("config{,.local}.toml"), ["config.toml", "config.local.toml"]);
+    assert_eq!(expand_braces("{a,b}/{c,d}"), ["a/c", "a/d", "b/c", "b/d"]);
+    assert_eq!(expand_braces("odd{a,b"), ["odd{a,b"]);
+
+    // The comma inside the braces doesn't end the pattern
+    let dir = tempfile::tempdir().unwrap();
+    let client = ignore_client(dir.path(), "*.{rs,toml},file{1,2}.txt");
+    assert!(client.should_ignore(Path::new("src/main.rs")));
+    assert!(client.should_ignore(Path::new("Cargo.toml")));
+    assert!(!client.should_ignore(Path::new("README.md")));
+    assert!(client.should_ignore(Path::new("data/file2.txt")));
+    assert!(!client.should_ignore(Path::new("data/file3.txt")));
+}
+
+#[test]
+fn test_ignore_case_matches_patterns_regardless_of_case() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = ignore_client(dir.path(), "readme.*,docs/*.MD");
+    assert!(!client.should_ignore(Path::new("README.md")));
+    assert!(!client.should_ignore(Path::new("docs/guide.md")));
+
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        custom_ignore: Some("readme.*,docs/*.MD".to_string()),
+        custom_include: Some("*.MD,*.rs".to_string()),
+        ignore_case: true,
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(client.should_ignore(Path::new("README.md")));
+    assert!(client.should_ignore(Path::new("src/Readme.txt")));
+    assert!(client.should_ignore(Path::new("docs/guide.md")));
+    assert!(!client.should_ignore(Path::new("CHANGELOG.md")));
+    assert!(!client.should_ignore(Path::new("src/MAIN.RS")));
+    assert!(client.should_ignore(Path::new("Cargo.toml")));
+}
+
+#[cfg(feature = "pdf")]
+#[test]
+fn test_pdf_files_are_summarizable() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = dry_run_client(&dir.path().join("cache"));
+    let binary = crate::utils::FileInfo { size: 10, is_text: false, interpreter: None };
+    assert!(client.can_summarize(std::path::Path::new("manual.pdf"), &binary));
+    assert!(!client.can_summarize(std::path::Path::new("image.png"), &binary));
+}
+
+#[test]
+fn test_split_into_chunks_on_line_boundaries() {
+    let content = "first line\nsecond line\nthird line\n";
+    let chunks = split_into_chunks(content, 24);
+    assert_eq!(chunks, vec!["first line\nsecond line\n", "third line\n"]);
+    assert_eq!(chunks.concat(), content);
+}
+
+#[test]
+fn test_split_into_chunks_breaks_long_lines() {
+    let content = "ああああ\nb";
+    let chunks = split_into_chunks(content, 7);
+    assert!(chunks.iter().all(|chunk| chunk.len() <= 7));
+    assert_eq!(chunks.concat(), content);
+}
+
+#[tokio::test]
+async fn test_chunk_large_summarizes_each_section() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("big.log");
+    let line = "a".repeat(99) + "\n";
+    std::fs::write(&path, line.repeat(400)).unwrap();
+
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        max_file_size: 1024,
+        chunk_large: true,
+        ..Default::default()
+    })
+    .unwrap();
+
+    let summary = client.summarize_file(&path, None).await.unwrap().unwrap();
+    assert!(summary.text.starts_with("[dry run]"));
+    // Three 16K sections plus the combining request
+    assert_eq!(client.dry_run_estimate().unwrap().files, 4);
+}
+
+#[test]
+fn test_base_url_joining_ignores_trailing_slash() {
+    assert_eq!(chat_completions_url("http://localhost:11434/v1"), "http://localhost:11434/v1/chat/completions");
+    assert_eq!(chat_completions_url("http://localhost:11434/v1/"), "http://localhost:11434/v1/chat/completions");
+}
+
+#[test]
+fn test_client_with_base_url_needs_no_key_or_known_model() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        api_key_file: Some(dir.path().join("missing-key")),
+        model: "llama3".to_string(),
+        base_url: Some("http://localhost:11434/v1/".to_string()),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    assert_eq!(client.base_url(), Some("http://localhost:11434/v1/"));
+}
+
+#[test]
+fn test_no_cache_neither_reads_nor_writes() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache_dir = dir.path().to_path_buf();
+    let config = |bypass_cache| ClientConfig {
+        cache_dir: Some(cache_dir.clone()),
+        dry_run: true,
+        bypass_cache,
+        ..ClientConfig::default()
+    };
+
+    let cached = GPTClient::new(config(false)).unwrap();
+    cached.add_to_cache("abc".to_string(), "summary".to_string(), "english").unwrap();
+    assert_eq!(cached.get_from_cache(Path::new("a.rs"), "abc", "english").as_deref(), Some("summary"));
+
+    let bypass = GPTClient::new(config(true)).unwrap();
+    assert_eq!(bypass.get_from_cache(Path::new("a.rs"), "abc", "english"), None);
+    bypass.add_to_cache("def".to_string(), "summary".to_string(), "english").unwrap();
+    assert!(!cache_dir.join("def.json").exists());
+}
+
+#[test]
+fn test_summary_lengths_parse_as_presets_numbers_and_ranges() {
+    assert_eq!(parse_summary_length("100-200").unwrap(), SummaryLength::Range(100, 200));
+    assert_eq!(parse_summary_length("150").unwrap(), SummaryLength::Tokens(150));
+    assert_eq!(parse_summary_length("smart").unwrap(), SummaryLength::Smart);
+    assert_eq!(parse_summary_length("short").unwrap(), SummaryLength::Preset(0));
+    assert_eq!(parse_summary_length("super").unwrap(), SummaryLength::Preset(3));
+
+    let err = parse_summary_length("200-100").unwrap_err().to_string();
+    assert!(err.contains("'200-100'") && err.contains("at most the maximum"), "{}", err);
+    assert!(parse_summary_length("0-100").is_err());
+    assert!(parse_summary_length("100-lots").is_err());
+
+    // The prompt asks for the range, and the upper bound is the budget
+    let dir = tempfile::tempdir().unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        summary_length: "100-200".to_string(),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    let path = Path::new("main.rs");
+    assert_eq!(client.calculate_summary_length("fn main() {}", path), 200);
+    let prompt = client.build_prompt(path, "fn main() {}", None, 200, "english", None);
+    assert!(prompt.contains("in between 75 and 150 words"), "{}", prompt);
+}
+
+#[test]
+fn test_summary_budget_is_in_tokens() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = |summary_length: &str| GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        summary_length: summary_length.to_string(),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    let path = std::path::Path::new("main.rs");
+
+    // Presets are token budgets, and the prompt asks for ~0.75 words per token
+    let long = client("long");
+    assert_eq!(long.calculate_summary_length("fn main() {}", path), 200);
+    assert!(long.build_prompt(Path::new("main.rs"), "fn main() {}", None, 200, "english", None).contains("approximately 150 words"));
+
+    // A tiny Rust file gets the tiny budget times the source code multiplier
+    let smart = client("smart");
+    assert_eq!(smart.calculate_summary_length("fn main() {}", path), 90);
+    let large = "word ".repeat(30_000);
+    assert_eq!(smart.calculate_summary_length(&large, path), 420);
+}
+
+#[test]
+fn test_interpreter_overrides_keep_the_file_type_multiplier() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = GPTClient::new(ClientConfig { cache_dir: Some(dir.path().join("cache")), dry_run: true, ..ClientConfig::default() })
+        .unwrap();
+    let multiplier = |name: &str| client.get_file_type_multiplier(Path::new(name));
+    assert_eq!(multiplier("notes.rst"), 1.5);
+    assert_eq!(multiplier("plot.m"), 1.0);
+
+    with_interpreter_overrides("rst=python, m=rust", || {
+        // An interpreter with no extensions of its own leaves the entry alone
+        assert_eq!(multiplier("notes.rst"), 1.5);
+        // Otherwise the file counts as that interpreter's extensions
+        assert_eq!(multiplier("plot.m"), 1.2);
+    });
+}
+
+#[test]
+fn test_adaptive_presets_grow_with_size_and_keep_their_order() {
+    // One content size per bucket, tiny to very large
+    let sizes = [100, 1_000, 10_000, 100_000, 200_000];
+    for preset in 0..4 {
+        let lengths: Vec<u32> = sizes.iter().map(|&tokens| adaptive_preset_length(preset, tokens)).collect();
+        assert!(lengths.windows(2).all(|pair| pair[0] < pair[1]), "preset {} isn't monotonic: {:?}", preset, lengths);
+    }
+    for tokens in sizes {
+        let lengths: Vec<u32> = (0..4).map(|preset| adaptive_preset_length(preset, tokens)).collect();
+        assert!(lengths.windows(2).all(|pair| pair[0] < pair[1]), "presets cross at {} tokens: {:?}", tokens, lengths);
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let client = |summary_length: &str| GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        summary_length: summary_length.to_string(),
+        adaptive_presets: true,
+        ..ClientConfig::default()
+    });
+    let path = std::path::Path::new("notes.txt");
+    let short = client("short").unwrap();
+    assert!(short.calculate_summary_length("tiny", path) < short.calculate_summary_length(&"word ".repeat(3_000), path));
+    assert!(client("smart").is_err());
+    assert!(client("120").is_err());
+}
+
+#[test]
+fn test_language_map_overrides_by_extension() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        language: "french".to_string(),
+        language_map: Some("rs=english, .MD=japanese".to_string()),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    assert_eq!(client.language_for(std::path::Path::new("src/main.rs")), "english");
+    assert_eq!(client.language_for(std::path::Path::new("README.md")), "japanese");
+    assert_eq!(client.language_for(std::path::Path::new("notes.txt")), "french");
+
+    // The same content in two languages must not share a cache entry
+    assert_ne!(
+        client.calculate_content_hash("same", None, "english"),
+        client.calculate_content_hash("same", None, "japanese"),
+    );
+    assert!(parse_language_map("rs").is_err());
+}
+
+#[test]
+fn test_languages_are_normalized_from_codes_and_names() {
+    for language in ["ja", "jp", "japanese", " Japanese "] {
+        assert_eq!(normalize_language(language, false).unwrap(), "japanese");
+    }
+    assert_eq!(normalize_language("EN", false).unwrap(), "english");
+    let err = normalize_language("japenese", false).unwrap_err().to_string();
+    assert!(err.contains("japenese") && err.contains("--allow-unknown-language"), "{}", err);
+    assert_eq!(normalize_language("Klingon", true).unwrap(), "Klingon");
+
+    // A code gets the same longer budget as the full name
+    let dir = tempfile::tempdir().unwrap();
+    let client = |language: &str| GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        summary_length: "smart".to_string(),
+        language: language.to_string(),
+        language_map: Some("md=jp".to_string()),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    let path = Path::new("main.rs");
+    assert_eq!(client("ja").calculate_summary_length("fn main() {}", path), 135);
+    assert_eq!(client("english").calculate_summary_length("fn main() {}", path), 90);
+    assert_eq!(client("english").language_for(Path::new("README.md")), "japanese");
+    let err = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        language: "japenese".to_string(),
+        ..ClientConfig::default()
+    });
+    assert!(err.is_err_and(|err| err.to_string().contains("Unknown language")));
+}
+
+#[tokio::test]
+async fn test_auto_language_is_detected_from_the_content() {
+    let japanese = "// 設定ファイルを読み込み、既定値とマージする。\n\
+        // ファイルが存在しない場合は既定値だけを返す。\n\
+        fn load() {}\n";
+    assert_eq!(detect_language(japanese), "japanese");
+    assert_eq!(detect_language("fn load() {}\n"), "english");
+    assert_eq!(normalize_language("Auto", false).unwrap(), "auto");
+
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("config.rs");
+    std::fs::write(&file, japanese).unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        language: "auto".to_string(),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    let language = client.content_language(&file, japanese);
+    assert_eq!(language, "japanese");
+    // The detected language keys the cache
+    let content_hash = client.inspect_cache(&file, None).unwrap().content_hash;
+    assert_eq!(content_hash, client.calculate_content_hash(japanese, None, &language));
+    assert_ne!(content_hash, client.calculate_content_hash(japanese, None, "english"));
+}
+
+#[test]
+fn test_empty_choices_is_an_error_not_a_panic() {
+    let raw = r#"{"id":"chatcmpl-1","choices":[]}"#;
+    let response: ChatResponse = serde_json::from_str(raw).unwrap();
+    let err = completion_text(response, raw).unwrap_err().to_string();
+    assert!(err.contains("no choices"));
+    assert!(err.contains("chatcmpl-1"));
+
+    let raw = r#"{"error":{"message":"Rate limit reached","type":"requests"}}"#;
+    let response: ChatResponse = serde_json::from_str(raw).unwrap();
+    let err = completion_text(response, raw).unwrap_err().to_string();
+    assert_eq!(err, "OpenAI API error (requests): Rate limit reached");
+
+    let raw = r#"{"choices":[{"message":{"role":"assistant","content":"Hi"}}]}"#;
+    let response: ChatResponse = serde_json::from_str(raw).unwrap();
+    assert_eq!(completion_text(response, raw).unwrap(), "Hi");
+}
+
+#[tokio::test]
+async fn test_failed_question_is_dropped_from_history() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("main.rs");
+    std::fs::write(&file, "fn main() {}").unwrap();
+    // Nothing listens on the discard port, so the request fails right away
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some("http://127.0.0.1:9/v1".to_string()),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    let mut conversation = Conversation::new(&file);
+    assert!(client.ask(&mut conversation, "What does main do?").await.is_err());
+    assert!(conversation.messages().is_empty());
+}
+
+#[tokio::test]
+async fn test_batch_answer_is_served_from_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("main.rs");
+    std::fs::write(&file, "fn main() {}").unwrap();
+    // Any request would fail, so a returned answer must come from the cache
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some("http://127.0.0.1:9/v1".to_string()),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    client.collect_for_batch(&file).await.unwrap();
+
+    let combined = combine_batch_contents(&[(file.to_string_lossy().into_owned(), "fn main() {}".to_string())]);
+    let hash = client.calculate_content_hash(&combined, Some("Any bugs?"), "english");
+    client.write_cache(hash.clone(), "No bugs".to_string(), "english", BATCH_ANSWER_MARKER).unwrap();
+
+    match client.summarize_batch(Some("Any bugs?")).await.unwrap() {
+        BatchResult::Answer(answer) => assert_eq!(answer, "No bugs"),
+        BatchResult::Summaries { .. } => panic!("expected an answer"),
+    }
+    // The marker keeps batch answers out of single-file lookups
+    assert_eq!(client.get_from_cache(&file, &hash, "english"), None);
+}
+
+#[test]
+fn test_word_count_and_length_tolerance() {
+    assert_eq!(count_words("A short  summary\nof a file", "english"), 6);
+    assert_eq!(count_words("ファイルの 概要", "japanese"), 7);
+    assert_eq!(count_words("ファイルの 概要", "Japanese"), 7);
+
+    assert!(!is_far_from_target(75, 75));
+    assert!(!is_far_from_target(40, 75));
+    assert!(!is_far_from_target(110, 75));
+    assert!(is_far_from_target(30, 75));
+    assert!(is_far_from_target(150, 75));
+}
+
+#[tokio::test]
+async fn test_stdin_content_uses_medium_length() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        summary_length: "smart".to_string(),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    let summary = client.summarize_content(STDIN_NAME, "fn main() {}", None).await.unwrap().unwrap();
+    assert!(summary.text.contains("summary length 250 tokens"), "{}", summary.text);
+    let empty = client.summarize_content(STDIN_NAME, "  \n", None).await.unwrap().unwrap();
+    assert_eq!(empty.text, "Empty file");
+}
+
+#[tokio::test]
+async fn test_summaries_report_whether_they_were_cached() {
+    let dir = tempfile::tempdir().unwrap();
+    let cached_file = dir.path().join("cached.rs");
+    std::fs::write(&cached_file, "fn cached() {}").unwrap();
+    let new_file = dir.path().join("new.rs");
+    std::fs::write(&new_file, "fn new() {}").unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some("http://127.0.0.1:9/v1".to_string()),
+        model: "local".to_string(),
+        max_retries: 0,
+        verbose_cache: true,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    let hash = client.calculate_content_hash("fn cached() {}", None, "english");
+    client.add_to_cache(hash, "Cached summary".to_string(), "english").unwrap();
+
+    let summary = client.summarize_file(&cached_file, None).await.unwrap().unwrap();
+    assert_eq!(summary.text, "Cached summary");
+    assert!(summary.from_cache);
+    assert_eq!(client.api_calls(), 0);
+
+    // A miss goes to the (unreachable) API
+    assert!(client.summarize_file(&new_file, None).await.is_err());
+    assert_eq!(client.api_calls(), 1);
+}
+
+#[tokio::test]
+async fn test_lock_files_are_skipped_unless_requested() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = dir.path().join("Cargo.lock");
+    std::fs::write(&lock, "[[package]]\nname = \"nexplorer\"\n").unwrap();
+
+    let client = dry_run_client(&dir.path().join("cache"));
+    let summary = client.summarize_file(&lock, None).await.unwrap().unwrap();
+    assert_eq!(summary.text, "Lock/minified file skipped");
+
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        summarize_generated: true,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    let summary = client.summarize_file(&lock, None).await.unwrap().unwrap();
+    assert!(summary.text.starts_with("[dry run]"));
+}
+
+#[tokio::test]
+async fn test_timeout_error_names_the_limit() {
+    // Accepts connections but never answers
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("main.rs");
+    std::fs::write(&file, "fn main() {}").unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(format!("http://{}/v1", listener.local_addr().unwrap())),
+        model: "local".to_string(),
+        max_retries: 0,
+        timeout_secs: 1,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    let err = client.summarize_file(&file, None).await.unwrap_err();
+    assert!(is_timeout(&err), "{:#}", err);
+    assert!(err.to_string().contains("timed out after 1s"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_prompt_names_the_detected_interpreter() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("main.rs");
+    std::fs::write(&file, "fn main() {}").unwrap();
+    let client = dry_run_client(&dir.path().join("cache"));
+
+    let prompt = client.build_prompt(Path::new("main.rs"), "fn main() {}", None, 100, "english", Some("rust"));
+    assert!(prompt.starts_with("This is a rust file. Provide a detailed summary"), "{}", prompt);
+    let prompt = client.build_prompt(Path::new("main.rs"), "fn main() {}", None, 100, "english", None);
+    assert!(prompt.starts_with("Provide a detailed summary"), "{}", prompt);
+
+    // The hint adds to the dry-run estimate of the real prompt
+    let summary = client.summarize_file(&file, None).await.unwrap().unwrap();
+    let with_hint = crate::pricing::estimate_tokens(&client.build_prompt(Path::new("main.rs"), "fn main() {}", None, 100, "english", Some("rust")));
+    assert!(summary.text.ends_with(&format!("~{} prompt tokens", with_hint)), "{}", summary.text);
+}
+
+#[test]
+fn test_unversioned_cache_entries_are_misses() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = dry_run_client(dir.path());
+    let path = Path::new("main.rs");
+
+    client.add_to_cache("current".to_string(), "summary".to_string(), "english").unwrap();
+    assert_eq!(client.get_from_cache(path, "current", "english").as_deref(), Some("summary"));
+
+    // Written before entries carried a version
+    let old_entry = serde_json::json!({
+        "content_hash": "old",
+        "summary": "stale summary",
+        "timestamp": SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        "language": "english",
+        "summary_length": "medium",
+    });
+    std::fs::write(client.get_cache_path("old"), old_entry.to_string()).unwrap();
+    assert_eq!(client.get_from_cache(path, "old", "english"), None);
+}
+
+#[test]
+fn test_corrupt_cache_entries_are_misses_and_get_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache_dir = dir.path();
+    let client = dry_run_client(cache_dir);
+    let path = Path::new("main.rs");
+
+    // As left by a write that was cut off
+    std::fs::write(client.get_cache_path("torn"), "{\"version\": 1, \"summ").unwrap();
+    assert_eq!(client.get_from_cache(path, "torn", "english"), None);
+
+    client.add_to_cache("torn".to_string(), "summary".to_string(), "english").unwrap();
+    assert_eq!(client.get_from_cache(path, "torn", "english").as_deref(), Some("summary"));
+    // No temp files are left behind
+    assert_eq!(std::fs::read_dir(cache_dir).unwrap().count(), 1);
+}
+
+#[tokio::test]
+async fn test_token_budget_stops_requests() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("first.rs");
+    std::fs::write(&first, "fn first() {}").unwrap();
+    let second = dir.path().join("second.rs");
+    std::fs::write(&second, "fn second() {}").unwrap();
+    // Enough for one prompt plus its 100-token reply budget, not two
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(crate::tests::mock_openai("A summary")),
+        model: "local".to_string(),
+        max_retries: 0,
+        max_total_tokens: Some(200),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    assert_eq!(client.summarize_file(&first, None).await.unwrap().unwrap().text, "A summary");
+    let used = client.tokens_used().unwrap();
+    assert!(used > 100 && used <= 200, "{}", used);
+
+    let skipped = client.summarize_file(&second, None).await.unwrap().unwrap();
+    assert_eq!(skipped.text, "Token budget exhausted");
+    assert_eq!(client.api_calls(), 1);
+    assert_eq!(client.tokens_used(), Some(used));
+}
+
+#[tokio::test]
+async fn test_translate_from_cache_reuses_other_language_summary() {
+    let dir = tempfile::tempdir().unwrap();
+    let english = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().to_path_buf()),
+        base_url: Some("http://127.0.0.1:9/v1".to_string()),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    let hash = english.calculate_content_hash("fn main() {}", None, "english");
+    english.add_to_cache(hash, "Prints nothing".to_string(), "english").unwrap();
+
+    // Only a request to translate the English summary gets an answer
+    let base_url = crate::tests::mock_openai_with(|request| {
+        let body: serde_json::Value = serde_json::from_str(request).unwrap();
+        let prompt = body["messages"][0]["content"].as_str().unwrap();
+        if !prompt.starts_with("Translate the following file summary from english into japanese") || !prompt.ends_with("Prints nothing") {
+            return (400, format!("unexpected prompt: {}", prompt));
+        }
+        (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": "何も出力しない" } }] }).to_string())
+    });
+    let config = ClientConfig {
+        language: "japanese".to_string(),
+        cache_dir: Some(dir.path().to_path_buf()),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    };
+    let without = GPTClient::new(config.clone()).unwrap();
+    assert_eq!(without.get_from_cache_any_language("fn main() {}", None, "japanese"), None);
+
+    let japanese = GPTClient::new(ClientConfig { translate_from_cache: true, ..config }).unwrap();
+    assert_eq!(
+        japanese.get_from_cache_any_language("fn main() {}", None, "japanese"),
+        Some(("english".to_string(), "Prints nothing".to_string()))
+    );
+    let summary = japanese.summarize_content("main.rs", "fn main() {}", None).await.unwrap().unwrap();
+    assert_eq!(summary.text, "何も出力しない");
+    assert!(summary.generated);
+
+    let hash = japanese.calculate_content_hash("fn main() {}", None, "japanese");
+    assert_eq!(japanese.get_from_cache(Path::new("main.rs"), &hash, "japanese").as_deref(), Some("何も出力しない"));
+}
+
+#[test]
+fn test_normalize_structured_reply() {
+    let fenced = "```json\n{\"purpose\": \"Entry point\", \"risks\": [\"panics\"]}\n```";
+    assert_eq!(
+        normalize_reply(fenced).unwrap(),
+        r#"{"purpose":"Entry point","key_components":[],"dependencies":[],"risks":["panics"]}"#
+    );
+    assert!(normalize_reply("Entry point").is_err());
+    assert!(normalize_reply(r#"{"risks": []}"#).is_err());
+}
+
+#[tokio::test]
+async fn test_structured_summaries_are_validated_and_cached_apart() {
+    let dir = tempfile::tempdir().unwrap();
+    let reply = r#"{"purpose": "Entry point", "key_components": ["main"], "dependencies": [], "risks": []}"#;
+    let config = ClientConfig {
+        cache_dir: Some(dir.path().to_path_buf()),
+        base_url: Some(crate::tests::mock_openai(reply)),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    };
+    let prose = GPTClient::new(config.clone()).unwrap();
+    let structured = GPTClient::new(ClientConfig { structured: true, ..config }).unwrap();
+    assert_ne!(
+        prose.calculate_content_hash("fn main() {}", None, "english"),
+        structured.calculate_content_hash("fn main() {}", None, "english")
+    );
+
+    let summary = structured.summarize_content("main.rs", "fn main() {}", None).await.unwrap().unwrap();
+    let parsed = StructuredSummary::parse(&summary.text).unwrap();
+    assert_eq!(parsed.purpose, "Entry point");
+    assert_eq!(parsed.key_components, vec!["main"]);
+
+    let cached = structured.summarize_content("main.rs", "fn main() {}", None).await.unwrap().unwrap();
+    assert!(cached.from_cache);
+    assert_eq!(cached.text, summary.text);
+}
+
+#[test]
+fn test_temperature_changes_content_hash() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = ClientConfig {
+        cache_dir: Some(dir.path().to_path_buf()),
+        dry_run: true,
+        ..ClientConfig::default()
+    };
+    let default = GPTClient::new(config.clone()).unwrap();
+    let deterministic = GPTClient::new(ClientConfig { temperature: 0.0, ..config }).unwrap();
+    assert_ne!(
+        default.calculate_content_hash("fn main() {}", None, "english"),
+        deterministic.calculate_content_hash("fn main() {}", None, "english")
+    );
+}
+
+#[test]
+fn test_validate_temperature() {
+    assert!(validate_temperature(Provider::OpenAI, 0.0).is_ok());
+    assert!(validate_temperature(Provider::OpenAI, 2.0).is_ok());
+    assert!(validate_temperature(Provider::OpenAI, 2.5).is_err());
+    assert!(validate_temperature(Provider::OpenAI, -0.1).is_err());
+    assert!(validate_temperature(Provider::Anthropic, 1.5).is_err());
+}
+
+#[tokio::test]
+async fn test_unavailable_model_falls_back_and_caches_under_fallback() {
+    let dir = tempfile::tempdir().unwrap();
+    let base_url = crate::tests::mock_openai_with(|request| {
+        if request.contains(r#""model":"missing""#) {
+            (404, r#"{"error":{"message":"The model does not exist","type":"invalid_request_error"}}"#.to_string())
+        } else {
+            (200, r#"{"choices":[{"message":{"role":"assistant","content":"Fallback summary"}}]}"#.to_string())
+        }
+    });
+    let config = ClientConfig {
+        cache_dir: Some(dir.path().to_path_buf()),
+        base_url: Some(base_url),
+        model: "missing".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    };
+    let client = GPTClient::new(ClientConfig { model_fallback: Some("local".to_string()), ..config.clone() }).unwrap();
+
+    let summary = client.summarize_content("a.rs", "fn a() {}", None).await.unwrap().unwrap();
+    assert_eq!(summary.text, "Fallback summary");
+    assert_eq!(client.api_calls(), 2);
+    client.summarize_content("b.rs", "fn b() {}", None).await.unwrap();
+    assert_eq!(client.api_calls(), 3);
+
+    let primary = GPTClient::new(config.clone()).unwrap();
+    let hash = primary.calculate_content_hash("fn a() {}", None, "english");
+    assert_eq!(primary.get_from_cache(Path::new("a.rs"), &hash, "english"), None);
+    assert!(primary.summarize_content("a.rs", "fn a() {}", None).await.is_err());
+
+    let fallback = GPTClient::new(ClientConfig { model: "local".to_string(), ..config }).unwrap();
+    let hash = fallback.calculate_content_hash("fn a() {}", None, "english");
+    assert_eq!(fallback.get_from_cache(Path::new("a.rs"), &hash, "english").as_deref(), Some("Fallback summary"));
+}
+
+#[tokio::test]
+async fn test_system_prompt_leads_the_messages_and_keys_the_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let base_url = crate::tests::mock_openai_with(|request| {
+        let body: serde_json::Value = serde_json::from_str(request).unwrap();
+        let roles: Vec<&str> = body["messages"].as_array().unwrap().iter().map(|m| m["role"].as_str().unwrap()).collect();
+        let reply = if roles == ["system", "user"] && body["messages"][0]["content"] == "You are a security auditor." {
+            "Audited summary"
+        } else {
+            "Plain summary"
+        };
+        (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": reply } }] }).to_string())
+    });
+    let config = ClientConfig {
+        cache_dir: Some(dir.path().to_path_buf()),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    };
+    let auditor = GPTClient::new(ClientConfig { system_prompt: Some("You are a security auditor.".to_string()), ..config.clone() }).unwrap();
+    let plain = GPTClient::new(config).unwrap();
+
+    let summary = auditor.summarize_content("a.rs", "fn a() {}", None).await.unwrap().unwrap();
+    assert_eq!(summary.text, "Audited summary");
+    assert_ne!(auditor.calculate_content_hash("fn a() {}", None, "english"), plain.calculate_content_hash("fn a() {}", None, "english"));
+    let summary = plain.summarize_content("a.rs", "fn a() {}", None).await.unwrap().unwrap();
+    assert_eq!(summary.text, "Plain summary");
+}
+
+#[tokio::test]
+async fn test_unchanged_file_is_matched_through_the_file_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("a.rs");
+    std::fs::write(&file, "fn a() {}").unwrap();
+    let modified = std::fs::metadata(&file).unwrap().modified().unwrap();
+    let cache_dir = tempfile::tempdir().unwrap();
+    let config = ClientConfig {
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        base_url: Some(crate::tests::mock_openai("A summary")),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    };
+    let client = GPTClient::new(config.clone()).unwrap();
+    assert!(client.summarize_file(&file, None).await.unwrap().unwrap().generated);
+    client.save_index().unwrap();
+    assert!(cache_dir.path().join("index.json").exists());
+
+    // Same size and modification time: the new content is never read, so
+    // the summary of the old content comes back
+    std::fs::write(&file, "fn b() {}").unwrap();
+    std::fs::File::options().write(true).open(&file).unwrap().set_modified(modified).unwrap();
+    let client = GPTClient::new(config.clone()).unwrap();
+    let summary = client.summarize_file(&file, None).await.unwrap().unwrap();
+    assert!(summary.from_cache);
+    assert_eq!(client.api_calls(), 0);
+
+    std::fs::File::options().write(true).open(&file).unwrap()
+        .set_modified(modified + Duration::from_secs(5)).unwrap();
+    let summary = client.summarize_file(&file, None).await.unwrap().unwrap();
+    assert!(summary.generated);
+    assert_eq!(client.api_calls(), 1);
+}
+
+#[test]
+fn test_add_to_cache_reports_a_replaced_summary() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().to_path_buf()),
+        dry_run: true,
+        report_changes: true,
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(!client.add_to_cache("abc".to_string(), "First".to_string(), "english").unwrap());
+    assert!(!client.add_to_cache("abc".to_string(), "First".to_string(), "english").unwrap());
+    assert!(client.add_to_cache("abc".to_string(), "Second".to_string(), "english").unwrap());
+
+    // Without change reports the previous entry isn't read
+    let client = dry_run_client(dir.path());
+    assert!(!client.add_to_cache("abc".to_string(), "Third".to_string(), "english").unwrap());
+}
+
+#[tokio::test]
+async fn test_only_files_with_changed_summaries_are_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let changed = dir.path().join("changed.rs");
+    let unchanged = dir.path().join("unchanged.rs");
+    std::fs::write(&changed, "fn old() {}").unwrap();
+    std::fs::write(&unchanged, "fn same() {}").unwrap();
+
+    // The summary follows the content, so new content gets a new summary
+    let base_url = crate::tests::mock_openai_with(|request| {
+        let body: serde_json::Value = serde_json::from_str(request).unwrap();
+        let prompt = body["messages"][0]["content"].as_str().unwrap();
+        let reply = format!("Defines {}", prompt.lines().last().unwrap());
+        (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": reply } }] }).to_string())
+    });
+    let config = ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        report_changes: true,
+        ..ClientConfig::default()
+    };
+
+    let client = GPTClient::new(config.clone()).unwrap();
+    for file in [&changed, &unchanged] {
+        client.summarize_file(file, None).await.unwrap();
+    }
+    client.save_index().unwrap();
+    assert_eq!(client.take_changed(), Some(vec![]));
+
+    std::fs::write(&changed, "fn new_name() {}").unwrap();
+    let client = GPTClient::new(config).unwrap();
+    for file in [&changed, &unchanged] {
+        client.summarize_file(file, None).await.unwrap();
+    }
+    assert_eq!(client.take_changed(), Some(vec![changed.display().to_string()]));
+    assert_eq!(client.take_changed(), Some(vec![]));
+}
+
+#[tokio::test]
+async fn test_summarize_file_uses_ignore_patterns_and_text_detection() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("main.rs"), "fn main() {}").unwrap();
+    std::fs::write(dir.path().join("app.log"), "started").unwrap();
+    std::fs::write(dir.path().join("image.png"), [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00]).unwrap();
+    std::fs::write(dir.path().join("empty.txt"), "").unwrap();
+    let client = ignore_client(dir.path(), "*.log");
+
+    let summary = client.summarize_file(&dir.path().join("main.rs"), None).await.unwrap().unwrap();
+    assert!(summary.text.starts_with("[dry run]"), "{}", summary.text);
+    assert_eq!(client.summarize_file(&dir.path().join("app.log"), None).await.unwrap(), None);
+    assert_eq!(client.summarize_file(&dir.path().join("image.png"), None).await.unwrap(), None);
+    let empty = client.summarize_file(&dir.path().join("empty.txt"), None).await.unwrap().unwrap();
+    assert_eq!(empty.text, "Empty file");
+}
+
+#[test]
+fn test_prompt_template_fills_every_placeholder() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        dry_run: true,
+        prompt_template: Some(
+            "Summarize {path} ({interpreter}) in {length} words of {language}:\n{content}".to_string(),
+        ),
+        ..ClientConfig::default()
+    };
+    let client = GPTClient::new(config.clone()).unwrap();
+
+    // Placeholder-like text in the content is left as it is
+    let prompt = client.build_prompt(Path::new("src/main.rs"), "let s = \"{path}\";", None, 100, "japanese", Some("rust"));
+    assert_eq!(prompt, "Summarize src/main.rs (rust) in 75 words of japanese:\nlet s = \"{path}\";");
+    let prompt = client.build_prompt(Path::new("notes"), "text", None, 100, "english", None);
+    assert_eq!(prompt, "Summarize notes (unknown) in 75 words of english:\ntext");
+    // Custom queries keep their own prompt
+    let prompt = client.build_prompt(Path::new("notes"), "text", Some("What is this?"), 100, "english", None);
+    assert!(prompt.starts_with("What is this?"), "{}", prompt);
+
+    // Editing the template invalidates what was cached with it
+    let edited = GPTClient::new(ClientConfig {
+        prompt_template: Some("Briefly: {content}".to_string()),
+        ..config.clone()
+    })
+    .unwrap();
+    assert_ne!(
+        client.calculate_content_hash("text", None, "english"),
+        edited.calculate_content_hash("text", None, "english")
+    );
+
+    let missing = GPTClient::new(ClientConfig { prompt_template: Some("Summarize {path}".to_string()), ..config });
+    assert!(missing.is_err_and(|err| err.to_string().contains("{content}")));
+}
+
+#[tokio::test]
+async fn test_rpm_spaces_requests_out() {
+    let dir = tempfile::tempdir().unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(crate::tests::mock_openai("A summary")),
+        model: "local".to_string(),
+        max_retries: 0,
+        rpm: Some(60),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    // The first request goes out at once, then one per second
+    let started = std::time::Instant::now();
+    for index in 0..3 {
+        let file = dir.path().join(format!("file{}.rs", index));
+        std::fs::write(&file, format!("fn f{}() {{}}", index)).unwrap();
+        client.summarize_file(&file, None).await.unwrap();
+    }
+    let elapsed = started.elapsed();
+    assert_eq!(client.api_calls(), 3);
+    assert!(elapsed >= Duration::from_secs(2), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(4), "{:?}", elapsed);
+}
+
+#[tokio::test]
+async fn test_inspect_cache_finds_the_entry_a_summary_uses() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("main.rs");
+    std::fs::write(&file, "fn main() {}").unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(crate::tests::mock_openai("Entry point")),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    let missing = client.inspect_cache(&file, None).unwrap();
+    assert!(missing.entry.is_none() && !missing.usable);
+
+    client.summarize_file(&file, None).await.unwrap();
+    let found = client.inspect_cache(&file, None).unwrap();
+    assert_eq!(found.content_hash, missing.content_hash);
+    assert_eq!(found.cache_path, client.get_cache_path(&found.content_hash));
+    let entry = found.entry.unwrap();
+    assert_eq!((entry.summary.as_str(), entry.language.as_str(), entry.summary_length.as_str()), ("Entry point", "english", "medium"));
+    assert!(found.usable);
+
+    // A question is cached apart from the summary
+    assert!(client.inspect_cache(&file, Some("What does it do?")).unwrap().entry.is_none());
+}
+
+#[tokio::test]
+async fn test_compare_sends_both_versions_and_caches_the_change_summary() {
+    let dir = tempfile::tempdir().unwrap();
+    let old = dir.path().join("old.rs");
+    let new = dir.path().join("new.rs");
+    std::fs::write(&old, "fn total(items: &[u32]) -> u32 { items.iter().sum() }").unwrap();
+    std::fs::write(&new, "fn total(items: &[u64]) -> u64 { items.iter().sum() }").unwrap();
+
+    // Reply with the prompt itself, so the summary shows what was sent
+    let base_url = crate::tests::mock_openai_with(|request| {
+        let body: serde_json::Value = serde_json::from_str(request).unwrap();
+        let prompt = body["messages"][0]["content"].clone();
+        (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": prompt } }] }).to_string())
+    });
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    let summary = client.compare_files(&old, &new).await.unwrap();
+    assert!(summary.generated);
+    let prompt = summary.text;
+    assert!(prompt.contains(&format!("--- Old version: {} ---\nfn total(items: &[u32])", old.display())), "{}", prompt);
+    assert!(prompt.contains(&format!("--- New version: {} ---\nfn total(items: &[u64])", new.display())), "{}", prompt);
+
+    let cached = client.compare_files(&old, &new).await.unwrap();
+    assert!(cached.from_cache);
+    assert_eq!(client.api_calls(), 1);
+
+    // The other direction is a different change
+    assert!(!client.compare_files(&new, &old).await.unwrap().from_cache);
+}
+
+#[tokio::test]
+async fn test_compare_after_a_model_fallback_caches_under_the_fallback() {
+    let dir = tempfile::tempdir().unwrap();
+    let old = dir.path().join("old.rs");
+    let new = dir.path().join("new.rs");
+    std::fs::write(&old, "fn a() {}").unwrap();
+    std::fs::write(&new, "fn b() {}").unwrap();
+    let base_url = crate::tests::mock_openai_with(|request| {
+        if request.contains(r#""model":"missing""#) {
+            (404, r#"{"error":{"message":"The model does not exist","type":"invalid_request_error"}}"#.to_string())
+        } else {
+            (200, r#"{"choices":[{"message":{"role":"assistant","content":"Renames a to b"}}]}"#.to_string())
+        }
+    });
+    let config = ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(base_url),
+        model: "missing".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    };
+    let client = GPTClient::new(ClientConfig { model_fallback: Some("local".to_string()), ..config.clone() }).unwrap();
+    assert!(client.compare_files(&old, &new).await.unwrap().generated);
+
+    let fallback = GPTClient::new(ClientConfig { model: "local".to_string(), ..config }).unwrap();
+    let cached = fallback.compare_files(&old, &new).await.unwrap();
+    assert!(cached.from_cache);
+    assert_eq!(cached.text, "Renames a to b");
+}
+
+#[tokio::test]
+async fn test_line_range_summary_names_the_lines_and_is_cached_on_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("lib.rs");
+    std::fs::write(&file, "// header\nfn parse() {}\nfn render() {}\n// footer\n").unwrap();
+
+    // Reply with the prompt itself, so the summary shows what was sent
+    let base_url = crate::tests::mock_openai_with(|request| {
+        let body: serde_json::Value = serde_json::from_str(request).unwrap();
+        let prompt = body["messages"][0]["content"].clone();
+        (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": prompt } }] }).to_string())
+    });
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        strip_html: true,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    let range = LineRange { start: 2, end: 3 };
+    let summary = client.summarize_file_range(&file, range, None).await.unwrap().unwrap();
+    assert!(summary.generated);
+    assert!(summary.text.contains("lines 2\u{2013}3 of the file"), "{}", summary.text);
+    assert!(summary.text.contains("fn parse() {}\nfn render() {}"), "{}", summary.text);
+    assert!(!summary.text.contains("header") && !summary.text.contains("footer"), "{}", summary.text);
+
+    // Edits outside the lines don't change their summary
+    std::fs::write(&file, "// new header\nfn parse() {}\nfn render() {}\n").unwrap();
+    assert!(client.summarize_file_range(&file, LineRange { start: 2, end: 9 }, None).await.unwrap().unwrap().from_cache);
+    assert!(!client.summarize_file(&file, None).await.unwrap().unwrap().from_cache);
+    assert!(client.summarize_file_range(&file, LineRange { start: 5, end: 9 }, None).await.is_err());
+    assert_eq!(client.api_calls(), 2);
+
+    // The lines are prepared like a whole file would be
+    let page = dir.path().join("page.html");
+    std::fs::write(&page, "<html>\n<body>\n<p>Hello <b>there</b></p>\n<script>track()</script>\n</body>\n</html>\n").unwrap();
+    let summary = client.summarize_file_range(&page, LineRange { start: 3, end: 4 }, None).await.unwrap().unwrap();
+    assert!(summary.text.contains("Hello there"), "{}", summary.text);
+    assert!(!summary.text.contains("<p>") && !summary.text.contains("track()"), "{}", summary.text);
+}
+
+#[test]
+fn test_printed_prompts_shorten_long_content() {
+    let content = format!("{}{}", "a".repeat(2_000), "é".repeat(150));
+    let printed = format_prompt(&[ChatMessage::system("Be brief"), ChatMessage::user(content)]);
+    assert_eq!(printed, format!("--- system ---\nBe brief\n--- user ---\n{}[...150 more chars]\n", "a".repeat(2_000)));
+}
+
+#[test]
+fn test_images_are_sent_as_data_url_content_parts() {
+    let client = OpenAIClient::new(ureq::Agent::new(), String::new(), "gpt-4o".to_string(), 0, 0.7, None);
+    let image = Image { media_type: "image/png".to_string(), data: "iVBORw0KGgo=".to_string() };
+    let body = client.request_body(&[ChatMessage::system("Be brief"), ChatMessage::user_with_image("Describe it", image)], 100);
+    assert_eq!(body["messages"][0], serde_json::json!({ "role": "system", "content": "Be brief" }));
+    assert_eq!(body["messages"][1]["content"], serde_json::json!([
+        { "type": "text", "text": "Describe it" },
+        { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBORw0KGgo=" } },
+    ]));
+}
+
+#[tokio::test]
+async fn test_vision_describes_images_and_skips_them_otherwise() {
+    let dir = tempfile::tempdir().unwrap();
+    let image = dir.path().join("logo.png");
+    std::fs::write(&image, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+    let info = crate::utils::get_file_info(&image).unwrap();
+
+    let sent = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let base_url = crate::tests::mock_openai_with({
+        let sent = sent.clone();
+        move |request| {
+            sent.lock().unwrap().push(serde_json::from_str::<serde_json::Value>(request).unwrap());
+            (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": "A logo" } }] }).to_string())
+        }
+    });
+    let config = |vision| ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(base_url.clone()),
+        model: "local".to_string(),
+        max_retries: 0,
+        vision,
+        ..ClientConfig::default()
+    };
+
+    let client = GPTClient::new(config(false)).unwrap();
+    assert!(!client.can_summarize(&image, &info));
+
+    let client = GPTClient::new(config(true)).unwrap();
+    assert!(client.can_summarize(&image, &info));
+    assert_eq!(client.summarize_file(&image, None).await.unwrap().unwrap().text, "A logo");
+    let url = sent.lock().unwrap()[0]["messages"][0]["content"][1]["image_url"]["url"].as_str().unwrap().to_string();
+    assert_eq!(url, "data:image/png;base64,iVBORw0KGgoAAAANSUhEUg==");
+    assert!(client.summarize_file(&image, None).await.unwrap().unwrap().from_cache);
+
+    // Known text-only models are refused
+    assert!(GPTClient::new(ClientConfig { model: "gpt-3.5-turbo".to_string(), base_url: None, ..config(true) }).is_err());
+}
+
+#[test]
+fn test_summaries_mentioning_missing_code_are_flagged() {
+    let content = "fn load_config(path: &Path) -> Config {\n    parse(&std::fs::read_to_string(path).unwrap())\n}\n";
+    let summary = "Defines `load_config`, which reads `config.toml` and calls validateSchema() before parse_config_file returns.";
+    assert_eq!(references(summary), ["load_config", "config.toml", "validateSchema", "parse_config_file"]);
+    assert_eq!(validate_summary(content, summary), ["config.toml", "validateSchema", "parse_config_file"]);
+
+    // Qualified names are found by their last part; plain prose has no references
+    assert!(validate_summary(content, "Calls `std::fs::read_to_string` and `self.parse`.").is_empty());
+    assert!(validate_summary(content, "Loads the configuration, e.g. from a file.").is_empty());
+}
+
+#[tokio::test]
+async fn test_validate_summary_appends_a_warning() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("lib.rs");
+    std::fs::write(&file, "pub fn load_config() {}\npub fn save_config() {}\n").unwrap();
+    let client = |reply: &str, threshold| GPTClient::new(ClientConfig {
+        cache_dir: Some(tempfile::tempdir_in(dir.path()).unwrap().keep()),
+        base_url: Some(crate::tests::mock_openai(reply)),
+        model: "local".to_string(),
+        max_retries: 0,
+        validation_threshold: Some(threshold),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    let accurate = client("Provides `load_config` and `save_config`.", 0.3);
+    assert_eq!(accurate.summarize_file(&file, None).await.unwrap().unwrap().text, "Provides `load_config` and `save_config`.");
+
+    let inaccurate = client("Provides `load_config` and `delete_config`.", 0.3);
+    let summary = inaccurate.summarize_file(&file, None).await.unwrap().unwrap();
+    assert_eq!(summary.text, "Provides `load_config` and `delete_config`.\n\n⚠️ possibly inaccurate: mentions `delete_config`, not found in the file");
+    // Cached without the note, which is added again on a hit
+    let entry = inaccurate.inspect_cache(&file, None).unwrap().entry.unwrap();
+    assert_eq!(entry.summary, "Provides `load_config` and `delete_config`.");
+    assert_eq!(inaccurate.summarize_file(&file, None).await.unwrap().unwrap().text, summary.text);
+
+    // Half the references missing is within a higher threshold
+    let lenient = client("Provides `load_config` and `delete_config`.", 0.5);
+    assert_eq!(lenient.summarize_file(&file, None).await.unwrap().unwrap().text, "Provides `load_config` and `delete_config`.");
+}
+
+#[tokio::test]
+async fn test_head_tail_bytes_summarizes_a_sample_of_large_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let small = dir.path().join("small.log");
+    let large = dir.path().join("large.log");
+    std::fs::write(&small, "started\nstopped\n").unwrap();
+    std::fs::write(&large, (0..2_000).map(|i| format!("event {}\n", i)).collect::<String>()).unwrap();
+
+    // Reply with the prompt itself, so the summary shows what was sent
+    let base_url = crate::tests::mock_openai_with(|request| {
+        let body: serde_json::Value = serde_json::from_str(request).unwrap();
+        let prompt = body["messages"][0]["content"].clone();
+        (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": prompt } }] }).to_string())
+    });
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        head_tail_bytes: Some(256),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    let prompt = client.summarize_file(&large, None).await.unwrap().unwrap().text;
+    assert!(prompt.contains("[Partial view: the start, middle and end of a"), "{}", prompt);
+    assert!(prompt.contains("event 0\n") && prompt.contains("event 1999") && !prompt.contains("event 500\n"), "{}", prompt);
+
+    let prompt = client.summarize_file(&small, None).await.unwrap().unwrap().text;
+    assert!(prompt.ends_with("started\nstopped\n"), "{}", prompt);
+}
+
+#[test]
+fn test_confidence_is_the_geometric_mean_token_probability() {
+    let raw = r#"{"choices": [{
+        "message": { "role": "assistant", "content": "Parses config" },
+        "logprobs": { "content": [
+            { "token": "Parses", "logprob": -0.1, "bytes": [80], "top_logprobs": [] },
+            { "token": " config", "logprob": -0.5, "bytes": [32], "top_logprobs": [] }
+        ] }
+    }]}"#;
+    let ChatResponse::Completion(completion) = serde_json::from_str(raw).unwrap() else { panic!("not a completion") };
+    let confidence = completion_confidence(&completion).unwrap();
+    assert!((confidence - (-0.3f64).exp()).abs() < 1e-9, "{}", confidence);
+
+    // Replies sent without logprobs have no confidence
+    let raw = r#"{"choices": [{ "message": { "role": "assistant", "content": "Parses config" } }]}"#;
+    let ChatResponse::Completion(completion) = serde_json::from_str(raw).unwrap() else { panic!("not a completion") };
+    assert_eq!(completion_confidence(&completion), None);
+}
+
+#[tokio::test]
+async fn test_low_confidence_summaries_are_asked_for_again() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("config.rs");
+    std::fs::write(&file, "fn load() {}\n").unwrap();
+
+    // The plain prompt gets an unsure reply, the explicit one a sure one
+    let sent = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let base_url = crate::tests::mock_openai_with({
+        let sent = sent.clone();
+        move |request| {
+            let body: serde_json::Value = serde_json::from_str(request).unwrap();
+            let explicit = body["messages"][0]["content"].as_str().unwrap().contains("rather than guessing");
+            sent.lock().unwrap().push(body);
+            let (content, logprob) = if explicit { ("Loads the config", -0.05) } else { ("Does something", -1.0) };
+            (200, serde_json::json!({ "choices": [{
+                "message": { "role": "assistant", "content": content },
+                "logprobs": { "content": [{ "token": content, "logprob": logprob }] }
+            }] }).to_string())
+        }
+    });
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        logprobs: true,
+        confidence_threshold: Some(0.7),
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    let summary = client.summarize_file(&file, None).await.unwrap().unwrap();
+    assert_eq!(summary.text, "Loads the config");
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[0]["logprobs"], true);
+
+    let entry = client.inspect_cache(&file, None).unwrap().entry.unwrap();
+    assert!((entry.confidence.unwrap() - (-0.05f64).exp()).abs() < 1e-9);
+}
@@ -0,0 +1,28 @@
+use crate::html::to_text;
+
+#[test]
+fn test_html_text_drops_scripts_and_keeps_headings_and_links() {
+    let raw = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>Docs</title>
+    <style>body { color: red; }</style>
+    <script>window.secretTracker = "SECRET SCRIPT";</script>
+  </head>
+  <body>
+    <h1>Getting started</h1>
+
+
+    <p>Read the <a href="https://example.com/guide">installation guide</a>   first.</p>
+    <script>console.log("SECRET SCRIPT")</script>
+  </body>
+</html>"#;
+
+    let text = to_text(raw).unwrap();
+    assert!(!text.contains("SECRET SCRIPT"), "{}", text);
+    assert!(!text.contains("color: red"), "{}", text);
+    assert!(!text.contains("example.com"), "{}", text);
+    assert!(text.contains("Getting started"), "{}", text);
+    assert!(text.contains("installation guide"), "{}", text);
+    assert!(!text.contains("\n\n\n"), "{}", text);
+}
@@ -0,0 +1,68 @@
+use std::path::Path;
+use crate::gpt_client::{ClientConfig, GPTClient};
+use crate::manifests::{parse, Manifest};
+
+const PACKAGE_JSON: &str = r#"{
+  "name": "web-app",
+  "description": "Dashboard for the build farm",
+  "main": "dist/index.js",
+  "bin": { "web-app": "bin/cli.js" },
+  "scripts": { "build": "tsc", "test": "jest" },
+  "dependencies": { "react": "^18.2.0", "express": "^4.18.0" },
+  "devDependencies": { "typescript": "^5.0.0" }
+}"#;
+
+#[test]
+fn test_package_json_dependencies_and_scripts_are_parsed() {
+    let manifest = parse(Path::new("app/package.json"), PACKAGE_JSON).unwrap();
+    assert_eq!(manifest, Manifest {
+        kind: "package.json".to_string(),
+        name: Some("web-app".to_string()),
+        description: Some("Dashboard for the build farm".to_string()),
+        dependencies: vec!["express ^4.18.0".to_string(), "react ^18.2.0".to_string()],
+        dev_dependencies: vec!["typescript ^5.0.0".to_string()],
+        entry_points: vec!["module dist/index.js".to_string(), "binary web-app".to_string()],
+        scripts: vec![("build".to_string(), "tsc".to_string()), ("test".to_string(), "jest".to_string())],
+    });
+
+    let cargo = parse(Path::new("Cargo.toml"), "[package]\nname = \"tool\"\n[dependencies]\nserde = { version = \"1.0\", features = [\"derive\"] }\nlocal = { path = \"../local\" }\n").unwrap();
+    assert_eq!(cargo.dependencies, ["local", "serde 1.0"]);
+
+    // Broken manifests and other files aren't manifests
+    assert_eq!(parse(Path::new("package.json"), "{ \"name\": "), None);
+    assert_eq!(parse(Path::new("config.json"), PACKAGE_JSON), None);
+}
+
+#[tokio::test]
+async fn test_manifests_are_summarized_as_projects() {
+    let dir = tempfile::tempdir().unwrap();
+    let manifest = dir.path().join("package.json");
+    std::fs::write(&manifest, PACKAGE_JSON).unwrap();
+    let broken = dir.path().join("broken").join("package.json");
+    std::fs::create_dir(broken.parent().unwrap()).unwrap();
+    std::fs::write(&broken, "{ \"name\": \"half").unwrap();
+
+    // Reply with the prompt itself, so the summary shows what was sent
+    let base_url = crate::tests::mock_openai_with(|request| {
+        let body: serde_json::Value = serde_json::from_str(request).unwrap();
+        let prompt = body["messages"][0]["content"].clone();
+        (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": prompt } }] }).to_string())
+    });
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    let prompt = client.summarize_file(&manifest, None).await.unwrap().unwrap().text;
+    assert!(prompt.contains("Describe the project it defines"), "{}", prompt);
+    assert!(prompt.contains("Dependencies: express ^4.18.0, react ^18.2.0"), "{}", prompt);
+    assert!(prompt.contains("- build: tsc"), "{}", prompt);
+
+    // Falls back to the file as it is
+    let prompt = client.summarize_file(&broken, None).await.unwrap().unwrap().text;
+    assert!(prompt.ends_with("{ \"name\": \"half"), "{}", prompt);
+}
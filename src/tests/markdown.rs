@@ -0,0 +1,59 @@
+use crate::gpt_client::{ClientConfig, GPTClient};
+use crate::markdown::{split_front_matter, FrontMatter};
+
+const DOCUMENT: &str = "---
+title: \"Getting Started\"
+description: Installing and running the tool
+tags:
+  - setup
+  - cli
+layout: page
+---
+# Install
+
+Run the installer.
+";
+
+#[test]
+fn test_front_matter_is_split_from_the_body() {
+    let (front_matter, body) = split_front_matter(DOCUMENT).unwrap();
+    assert_eq!(front_matter, FrontMatter {
+        title: Some("Getting Started".to_string()),
+        description: Some("Installing and running the tool".to_string()),
+        tags: vec!["setup".to_string(), "cli".to_string()],
+    });
+    assert_eq!(body, "# Install\n\nRun the installer.\n");
+
+    let (front_matter, _) = split_front_matter("---\ntags: [a, 'b']\n---\nbody").unwrap();
+    assert_eq!(front_matter.tags, ["a", "b"]);
+    assert_eq!(split_front_matter("# No front matter\n---\n"), None);
+    assert_eq!(split_front_matter("---\nunclosed: block\n"), None);
+}
+
+#[tokio::test]
+async fn test_front_matter_title_is_a_prompt_hint() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("guide.md");
+    std::fs::write(&file, DOCUMENT).unwrap();
+
+    // Reply with the prompt itself, so the summary shows what was sent
+    let base_url = crate::tests::mock_openai_with(|request| {
+        let body: serde_json::Value = serde_json::from_str(request).unwrap();
+        let prompt = body["messages"][0]["content"].clone();
+        (200, serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": prompt } }] }).to_string())
+    });
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(base_url),
+        model: "local".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+
+    let prompt = client.summarize_file(&file, None).await.unwrap().unwrap().text;
+    assert!(prompt.contains("Title: Getting Started"), "{}", prompt);
+    assert!(prompt.contains("Tags: setup, cli"), "{}", prompt);
+    assert!(prompt.contains("# Install"), "{}", prompt);
+    assert!(!prompt.contains("layout: page"), "{}", prompt);
+}
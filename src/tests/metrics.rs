@@ -0,0 +1,26 @@
+use crate::metrics::{measure, FileMetrics};
+
+#[test]
+fn test_rust_line_and_block_comments_are_counted() {
+    let source = "\
+//! Config loading
+
+/* Reads the file
+   and merges defaults */
+fn load() -> Config {
+    let config = read(); // trailing comments are code
+
+    /* one-line block */
+    config
+}
+";
+    let metrics = measure(source, Some("rust"));
+    assert_eq!(metrics, FileMetrics { lines: 10, blank_lines: 2, comment_lines: Some(4) });
+    assert_eq!(metrics.loc(), 8);
+    assert_eq!(metrics.comment_percent(), Some(50));
+
+    // Languages without known comment syntax only get line counts
+    let metrics = measure("{\n\n\"a\": 1\n}\n", Some("json"));
+    assert_eq!(metrics, FileMetrics { lines: 4, blank_lines: 1, comment_lines: None });
+    assert_eq!(metrics.comment_percent(), None);
+}
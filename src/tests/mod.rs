@@ -1,156 +1,86 @@
-use std::fs;
-use tempfile::NamedTempFile;
-use std::io::Write;
-use std::path::PathBuf;
-use crate::utils::get_file_info;
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    fn create_temp_file(content: &[u8]) -> NamedTempFile {
-        let mut file = NamedTempFile::new().unwrap();
-        file.write_all(content).unwrap();
-        file.flush().unwrap();
-        file
-    }
-
-    fn create_temp_file_with_extension(content: &[u8], extension: &str) -> (NamedTempFile, PathBuf) {
-        let mut file = NamedTempFile::new().unwrap();
-        file.write_all(content).unwrap();
-        file.flush().unwrap();
-        let new_path = file.path().with_extension(extension);
-        fs::rename(file.path(), &new_path).unwrap();
-        (file, new_path)
-    }
-
-    #[test]
-    fn test_typescript_react_detection() {
-        let content = "import React from 'react';\nexport const App = () => <div>Hello</div>;";
-        let (_file, path) = create_temp_file_with_extension(content.as_bytes(), "tsx");
-        let info = get_file_info(&path).unwrap();
-        assert!(info.is_text);
-        assert_eq!(info.interpreter, Some("typescript-react".to_string()));
-    }
-
-    #[test]
-    fn test_shell_script_detection() {
-        let content = "#!/bin/bash\necho 'Hello'";
-        let file = create_temp_file(content.as_bytes());
-        let info = get_file_info(file.path()).unwrap();
-        assert!(info.is_text);
-        assert_eq!(info.interpreter, Some("bash".to_string()));
-    }
-
-    #[test]
-    fn test_env_shebang_detection() {
-        let content = "#!/usr/bin/env python3\nprint('Hello')";
-        let file = create_temp_file(content.as_bytes());
-        let info = get_file_info(file.path()).unwrap();
-        assert!(info.is_text);
-        assert_eq!(info.interpreter, Some("python3".to_string()));
-    }
-
-    #[test]
-    fn test_binary_file_detection() {
-        let content = vec![0x7F, 0x45, 0x4C, 0x46, 0x01, 0x01, 0x01, 0x00];
-        let file = create_temp_file(&content);
-        let info = get_file_info(file.path()).unwrap();
-        assert!(!info.is_text);
-        assert_eq!(info.interpreter, None);
-    }
-
-    #[test]
-    fn test_dotfile_detection() {
-        let content = "ignore_pattern = \"*.log\"";
-        let file = create_temp_file(content.as_bytes());
-        let path = file.path().with_file_name(".gitignore");
-        fs::rename(file.path(), &path).unwrap();
-        let info = get_file_info(&path).unwrap();
-        assert!(info.is_text);
-        assert_eq!(info.interpreter, Some("config".to_string()));
-    }
-
-    #[test]
-    fn test_common_programming_languages() {
-        let test_cases = vec![
-            ("fn main() {}", "rs", "rust"),
-            ("def hello(): pass", "py", "python3"),
-            ("package main", "go", "go"),
-            ("public class Test {}", "java", "java"),
-            ("console.log('test');", "js", "javascript"),
-        ];
-
-        for (content, ext, expected_interpreter) in test_cases {
-            let (_file, path) = create_temp_file_with_extension(content.as_bytes(), ext);
-            let info = get_file_info(&path).unwrap();
-            assert!(info.is_text, "File with extension {} should be text", ext);
-            assert_eq!(info.interpreter, Some(expected_interpreter.to_string()), 
-                      "File with extension {} should have interpreter {}", ext, expected_interpreter);
+mod archive;
+mod file_explorer;
+mod git_log;
+mod gpt_client;
+mod html;
+mod manifests;
+mod markdown;
+mod metrics;
+mod notebook;
+mod output;
+mod pricing;
+mod redact;
+mod utils;
+mod watch;
+
+// Serializes the tests that set the process-wide --interpreter-override
+// mapping, which would otherwise see each other's overrides
+static INTERPRETER_OVERRIDES_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Run `test` with `mapping` as the --interpreter-override mapping, which
+/// is cleared again afterwards, even if the test panics.
+pub(crate) fn with_interpreter_overrides<T>(mapping: &str, test: impl FnOnce() -> T) -> T {
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            crate::utils::set_interpreter_overrides("").unwrap();
         }
     }
 
-    #[test]
-    fn test_various_config_files() {
-        let test_cases = vec![
-            ("{\"key\": \"value\"}", "json", "json"),
-            ("key: value", "yaml", "yaml"),
-            ("key = \"value\"", "toml", "toml"),
-            ("[section]\nkey=value", "ini", "ini"),
-        ];
-
-        for (content, ext, expected_interpreter) in test_cases {
-            let (_file, path) = create_temp_file_with_extension(content.as_bytes(), ext);
-            let info = get_file_info(&path).unwrap();
-            assert!(info.is_text, "File with extension {} should be text", ext);
-            assert_eq!(info.interpreter, Some(expected_interpreter.to_string()));
-        }
-    }
+    let _lock = INTERPRETER_OVERRIDES_LOCK.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+    crate::utils::set_interpreter_overrides(mapping).unwrap();
+    let _reset = Reset;
+    test()
+}
 
-    #[test]
-    fn test_web_technology_files() {
-        let test_cases = vec![
-            ("<html><body></body></html>", "html", "html"),
-            (".class { color: red; }", "css", "css"),
-            ("$color: red;", "scss", "scss"),
-            ("<template><div></div></template>", "vue", "vue"),
-        ];
+/// Collects what a writer prints, readable while and after it runs.
+#[derive(Clone, Default)]
+pub(crate) struct SharedOutput(pub(crate) std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
 
-        for (content, ext, expected_interpreter) in test_cases {
-            let (_file, path) = create_temp_file_with_extension(content.as_bytes(), ext);
-            let info = get_file_info(&path).unwrap();
-            assert!(info.is_text, "File with extension {} should be text", ext);
-            assert_eq!(info.interpreter, Some(expected_interpreter.to_string()));
-        }
+impl std::io::Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
     }
+    fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+}
 
-    #[test]
-    fn test_empty_file() {
-        let file = create_temp_file(&[]);
-        let info = get_file_info(file.path()).unwrap();
-        assert!(info.is_text);
-        assert_eq!(info.size, 0);
-    }
+/// Serve `reply` as the completion for every chat request on a local port
+/// and return the base URL to point a client at.
+pub(crate) fn mock_openai(reply: &str) -> String {
+    let body = serde_json::json!({
+        "choices": [{ "message": { "role": "assistant", "content": reply } }]
+    })
+    .to_string();
+    mock_openai_with(move |_| (200, body.clone()))
+}
 
-    #[test]
-    fn test_text_with_binary_content() {
-        let mut content = Vec::with_capacity(512);
-        content.extend_from_slice(b"Hello World!\n");
-        // Fill the rest with non-printable characters
-        for i in content.len()..512 {
-            content.push((i % 256) as u8);
+/// Like `mock_openai`, answering each request body with the status and
+/// body that `respond` returns for it.
+pub(crate) fn mock_openai_with(respond: impl Fn(&str) -> (u16, String) + Send + 'static) -> String {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 2 {
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+                line.clear();
+            }
+            let mut request = vec![0; content_length];
+            let _ = reader.read_exact(&mut request);
+            let (status, body) = respond(&String::from_utf8_lossy(&request));
+            let _ = write!(stream, "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                status, body.len(), body);
         }
-        let file = create_temp_file(&content);
-        let info = get_file_info(file.path()).unwrap();
-        assert!(!info.is_text);
-    }
-
-    #[test]
-    fn test_extensionless_script_detection() {
-        let content = "#!/usr/bin/env node\nconsole.log('Hello');";
-        let file = create_temp_file(content.as_bytes());
-        let info = get_file_info(file.path()).unwrap();
-        assert!(info.is_text);
-        assert_eq!(info.interpreter, Some("node".to_string()));
-    }
+    });
+    base_url
 }
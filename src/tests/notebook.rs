@@ -0,0 +1,26 @@
+use crate::notebook::cells_text;
+
+#[test]
+fn test_notebook_cells_are_joined_without_outputs() {
+    let raw = r##"{
+        "cells": [
+            {"cell_type": "markdown", "metadata": {}, "source": ["# Loading data\n", "Reads the CSV."]},
+            {"cell_type": "code", "metadata": {}, "execution_count": 1,
+             "source": "import pandas as pd\ndf = pd.read_csv('data.csv')",
+             "outputs": [{"output_type": "stream", "name": "stdout", "text": ["SECRET OUTPUT"]}]}
+        ],
+        "metadata": {"language_info": {"name": "python"}},
+        "nbformat": 4,
+        "nbformat_minor": 5
+    }"##;
+
+    let text = cells_text(raw).unwrap();
+    assert_eq!(text, "# Loading data\nReads the CSV.\n\n```python\nimport pandas as pd\ndf = pd.read_csv('data.csv')\n```");
+    assert!(!text.contains("SECRET OUTPUT"));
+}
+
+#[test]
+fn test_malformed_notebook_is_not_parsed() {
+    assert_eq!(cells_text("{\"cells\": [unterminated"), None);
+    assert_eq!(cells_text("plain text"), None);
+}
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde_json::Value;
+use crate::output::{spawn_output, GroupedWriter, JsonWriter, MarkdownReport, OutputWriter, Stats, SummaryFiles, TextWriter};
+use crate::utils::FileInfo;
+use super::SharedOutput;
+
+fn rust_file_info() -> FileInfo {
+    FileInfo {
+        size: 42,
+        is_text: true,
+        interpreter: Some("rust".to_string()),
+    }
+}
+
+#[test]
+fn test_json_document_contains_files_and_stats() {
+    let mut buffer = Vec::new();
+    {
+        let mut writer = JsonWriter::new(&mut buffer, false);
+        writer.begin(Path::new("src")).unwrap();
+        writer.directory(Path::new("src"), 0).unwrap();
+        writer.file(Path::new("src/main.rs"), &rust_file_info(), 1).unwrap();
+        writer.summary("Entry point", 1).unwrap();
+        writer.file(Path::new("src/lib.rs"), &rust_file_info(), 1).unwrap();
+        writer.finish(&Stats { total_dirs: 1, total_files: 2, ..Default::default() }).unwrap();
+    }
+
+    let document: Value = serde_json::from_slice(&buffer).unwrap();
+    let files = document["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0]["path"], "src/main.rs");
+    assert_eq!(files[0]["size"], 42);
+    assert_eq!(files[0]["is_text"], true);
+    assert_eq!(files[0]["interpreter"], "rust");
+    assert_eq!(files[0]["summary"], "Entry point");
+    assert!(files[1]["summary"].is_null());
+    assert_eq!(document["stats"]["total_dirs"], 1);
+    assert_eq!(document["stats"]["total_files"], 2);
+}
+
+#[test]
+fn test_jsonl_emits_one_object_per_line() {
+    let mut buffer = Vec::new();
+    {
+        let mut writer = JsonWriter::new(&mut buffer, true);
+        writer.begin(Path::new("src")).unwrap();
+        writer.file(Path::new("src/a.rs"), &rust_file_info(), 1).unwrap();
+        writer.file(Path::new("src/b.rs"), &rust_file_info(), 1).unwrap();
+        let summaries = HashMap::from([
+            ("src/b.rs".to_string(), "B".to_string()),
+            ("src/a.rs".to_string(), "A".to_string()),
+        ]);
+        writer.batch_summaries(&summaries).unwrap();
+        writer.finish(&Stats { total_dirs: 0, total_files: 2, ..Default::default() }).unwrap();
+    }
+
+    let lines: Vec<Value> = String::from_utf8(buffer).unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0]["path"], "src/a.rs");
+    assert_eq!(lines[1]["path"], "src/b.rs");
+    assert_eq!(lines[2]["batch_summaries"][0]["path"], "src/a.rs");
+    assert_eq!(lines[3]["stats"]["total_files"], 2);
+}
+
+#[test]
+fn test_text_writer_matches_tree_layout() {
+    let mut buffer = Vec::new();
+    {
+        let mut writer = TextWriter::new(&mut buffer);
+        writer.directory(Path::new("project/src"), 1).unwrap();
+        writer.file(Path::new("project/src/main.rs"), &rust_file_info(), 2).unwrap();
+        writer.summary("Entry point", 2).unwrap();
+    }
+
+    let output = String::from_utf8(buffer).unwrap();
+    assert_eq!(output, "  📁 src/\n    📄 main.rs (42 B)\n       📝 Summary: Entry point\n");
+}
+
+#[test]
+fn test_markdown_report_sections() {
+    let dir = tempfile::tempdir().unwrap();
+    let report_path = dir.path().join("REPORT.md");
+    let mut report = MarkdownReport::new(report_path.clone());
+    report.begin(Path::new("src")).unwrap();
+    report.file(Path::new("src/main.rs"), &rust_file_info(), 1).unwrap();
+    report.summary("Entry point.\n\nParses arguments.", 1).unwrap();
+    assert!(!report_path.exists());
+
+    report.finish(&Stats { total_dirs: 1, total_files: 1, ..Default::default() }).unwrap();
+    let content = std::fs::read_to_string(&report_path).unwrap();
+    assert!(content.starts_with("# src\n\n## src/main.rs\n\n- Size: 42 B\n- Interpreter: rust\n\n"));
+    assert!(content.contains("> Entry point.\n>\n> Parses arguments.\n"));
+    // Only the report itself is left behind, no temp files
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_summary_files_mirror_the_explored_tree() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("out");
+    let mut files = SummaryFiles::new(out.clone());
+    files.begin(Path::new("project")).unwrap();
+    files.file(Path::new("project/src/foo.rs"), &rust_file_info(), 2).unwrap();
+    files.summary("Defines foo.", 2).unwrap();
+    files.file(Path::new("project/../escape.rs"), &rust_file_info(), 1).unwrap();
+    files.summary("Outside.", 1).unwrap();
+    // Another root with a file of the same relative path
+    files.begin(Path::new("other")).unwrap();
+    files.file(Path::new("other/src/foo.rs"), &rust_file_info(), 2).unwrap();
+    files.summary("Another foo.", 2).unwrap();
+
+    let content = std::fs::read_to_string(out.join("src/foo.rs.md")).unwrap();
+    assert_eq!(content, "# project/src/foo.rs\n\nDefines foo.\n");
+    assert!(std::fs::read_to_string(out.join("src/foo.rs.2.md")).unwrap().contains("Another foo."));
+    assert!(out.join("escape.rs.md").exists());
+    assert!(!dir.path().join("escape.rs.md").exists());
+}
+
+#[test]
+fn test_quiet_text_output_lists_only_summaries() {
+    let mut buffer = Vec::new();
+    {
+        let mut writer = TextWriter::quiet(&mut buffer);
+        writer.begin(Path::new("src")).unwrap();
+        writer.directory(Path::new("src"), 0).unwrap();
+        writer.file(Path::new("src/main.rs"), &rust_file_info(), 1).unwrap();
+        writer.summary("Entry point", 1).unwrap();
+        writer.file(Path::new("src/lib.rs"), &rust_file_info(), 1).unwrap();
+        writer.finish(&Stats { total_dirs: 1, total_files: 2, ..Default::default() }).unwrap();
+    }
+
+    let output = String::from_utf8(buffer).unwrap();
+    assert!(output.starts_with("src/main.rs: Entry point\n\nSummary:\n"), "{}", output);
+    assert!(!output.contains("📄") && !output.contains("📁"));
+}
+
+#[test]
+fn test_grouped_output_clusters_files_by_interpreter() {
+    let python = FileInfo { size: 10, is_text: true, interpreter: Some("python3".to_string()) };
+    let mut buffer = Vec::new();
+    {
+        let mut writer = GroupedWriter::new(TextWriter::new(&mut buffer));
+        writer.begin(Path::new(".")).unwrap();
+        writer.directory(Path::new("src"), 0).unwrap();
+        writer.file(Path::new("src/main.rs"), &rust_file_info(), 1).unwrap();
+        writer.summary("Entry point", 1).unwrap();
+        writer.file(Path::new("tools/gen.py"), &python, 1).unwrap();
+        writer.summary("Code generator", 1).unwrap();
+        writer.file(Path::new("src/lib.rs"), &rust_file_info(), 1).unwrap();
+        writer.finish(&Stats::default()).unwrap();
+    }
+
+    let output = String::from_utf8(buffer).unwrap();
+    let python_at = output.find("== python3 ==").unwrap();
+    let rust_at = output.find("== rust ==").unwrap();
+    assert!(python_at < rust_at, "{}", output);
+    assert!(output[python_at..rust_at].contains("📄 tools/gen.py (10 B)\n   📝 Summary: Code generator\n"), "{}", output);
+    let rust = &output[rust_at..];
+    assert!(rust.find("src/lib.rs").unwrap() < rust.find("src/main.rs").unwrap(), "{}", output);
+    assert!(rust.contains("📄 src/main.rs (42 B)\n   📝 Summary: Entry point\n"), "{}", output);
+    assert!(!output.contains("📁"), "{}", output);
+}
+
+#[test]
+fn test_grouped_output_places_batch_summaries() {
+    let mut buffer = Vec::new();
+    {
+        let mut writer = GroupedWriter::new(TextWriter::quiet(&mut buffer));
+        writer.file(Path::new("src/main.rs"), &rust_file_info(), 1).unwrap();
+        writer.batch_summaries(&HashMap::from([("src/main.rs".to_string(), "Entry point".to_string())])).unwrap();
+        writer.finish(&Stats::default()).unwrap();
+    }
+
+    let output = String::from_utf8(buffer).unwrap();
+    assert!(output.starts_with("\n== rust ==\nsrc/main.rs: Entry point\n"), "{}", output);
+}
+
+const STRUCTURED: &str = r#"{"purpose":"Entry point","key_components":["main"],"dependencies":[],"risks":["panics"]}"#;
+
+#[test]
+fn test_structured_summary_rendering() {
+    let mut buffer = Vec::new();
+    {
+        let mut writer = TextWriter::new(&mut buffer);
+        writer.file(Path::new("src/main.rs"), &rust_file_info(), 0).unwrap();
+        writer.summary(STRUCTURED, 0).unwrap();
+    }
+    let output = String::from_utf8(buffer).unwrap();
+    assert!(output.ends_with("   Purpose: Entry point\n   Key components: main\n   Risks: panics\n"), "{}", output);
+
+    let mut buffer = Vec::new();
+    {
+        let mut writer = JsonWriter::new(&mut buffer, true);
+        writer.file(Path::new("src/main.rs"), &rust_file_info(), 0).unwrap();
+        writer.summary(STRUCTURED, 0).unwrap();
+        writer.finish(&Stats::default()).unwrap();
+    }
+    let first: Value = serde_json::from_str(String::from_utf8(buffer).unwrap().lines().next().unwrap()).unwrap();
+    assert_eq!(first["summary"]["purpose"], "Entry point");
+    assert_eq!(first["summary"]["risks"][0], "panics");
+}
+
+#[test]
+fn test_output_lines_from_concurrent_senders_never_interleave() {
+    use std::io::Write;
+
+    let buffer = SharedOutput::default();
+    let mut output = spawn_output(buffer.clone());
+    let senders: Vec<_> = (0..8).map(|task| {
+        let mut sender = output.clone();
+        std::thread::spawn(move || {
+            for line in 0..200 {
+                // Each line is written in pieces, as `writeln!` does
+                write!(sender, "task {} ", task).unwrap();
+                std::thread::yield_now();
+                write!(sender, "line {}", line).unwrap();
+                writeln!(sender, " end").unwrap();
+            }
+        })
+    }).collect();
+    for sender in senders {
+        sender.join().unwrap();
+    }
+    output.flush().unwrap();
+
+    let written = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = written.lines().collect();
+    assert_eq!(lines.len(), 8 * 200);
+    let pattern = regex::Regex::new(r"^task \d+ line \d+ end$").unwrap();
+    assert!(lines.iter().all(|line| pattern.is_match(line)), "{}", written);
+    for task in 0..8 {
+        let own: Vec<_> = lines.iter().filter(|line| line.starts_with(&format!("task {} ", task))).collect();
+        assert_eq!(own.len(), 200);
+        assert!(own.iter().enumerate().all(|(i, line)| line.ends_with(&format!(" line {} end", i))));
+    }
+}
+
+/// Fails every write, like stdout piped into a reader that has exited.
+struct ClosedPipe;
+
+impl std::io::Write for ClosedPipe {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Broken pipe"))
+    }
+    fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+}
+
+#[test]
+fn test_output_write_errors_reach_the_writer() {
+    use std::io::Write;
+
+    let mut output = spawn_output(ClosedPipe);
+    let mut writer = TextWriter::new(output.clone());
+    writer.begin(Path::new("src")).unwrap();
+    // The write fails on the output task, so it surfaces at the next flush
+    let err = output.flush().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+    assert!(writer.summary("more", 0).is_err());
+}
@@ -0,0 +1,55 @@
+use crate::gpt_client::{ClientConfig, GPTClient};
+use crate::pricing::{estimate_cost, estimate_tokens, CostReport};
+
+#[test]
+fn test_estimate_tokens_uses_four_chars_per_token() {
+    assert_eq!(estimate_tokens(""), 0);
+    assert_eq!(estimate_tokens("abcd"), 1);
+    assert_eq!(estimate_tokens("abcde"), 2);
+}
+
+#[test]
+fn test_estimate_cost_for_known_model() {
+    let cost = estimate_cost("gpt-4o-mini", 10_000, 1_000).unwrap();
+    assert!((cost - 0.0021).abs() < 1e-9);
+    assert_eq!(estimate_cost("unknown-model", 1_000, 1_000), None);
+}
+
+#[test]
+fn test_cost_reports_add_up_requests_at_their_model_price() {
+    let mut report = CostReport::default();
+    report.add("gpt-4o-mini", 10_000, 1_000);
+    report.add("gpt-4o", 2_000, 500);
+    assert_eq!((report.requests, report.input_tokens, report.output_tokens), (2, 12_000, 1_500));
+    assert!((report.cost.unwrap() - (0.0021 + 0.01)).abs() < 1e-9);
+
+    // One unpriced model makes the total unknown
+    report.add("local", 100, 100);
+    assert_eq!(report.cost, None);
+    assert_eq!(report.requests, 3);
+}
+
+#[tokio::test]
+async fn test_requests_are_counted_in_the_cost_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("main.rs");
+    std::fs::write(&file, "fn main() {}").unwrap();
+    let client = GPTClient::new(ClientConfig {
+        cache_dir: Some(dir.path().join("cache")),
+        base_url: Some(crate::tests::mock_openai("Prints nothing")),
+        model: "gpt-4o-mini".to_string(),
+        max_retries: 0,
+        ..ClientConfig::default()
+    })
+    .unwrap();
+    assert_eq!(client.cost_report(), Some(CostReport::default()));
+
+    client.summarize_file(&file, None).await.unwrap();
+    // Cache hits cost nothing
+    client.summarize_file(&file, None).await.unwrap();
+    let report = client.cost_report().unwrap();
+    assert_eq!(report.requests, 1);
+    assert_eq!(report.output_tokens, estimate_tokens("Prints nothing"));
+    assert!(report.input_tokens > estimate_tokens("fn main() {}"));
+    assert_eq!(report.cost, estimate_cost("gpt-4o-mini", report.input_tokens, report.output_tokens));
+}
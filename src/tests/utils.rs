@@ -0,0 +1,326 @@
+use std::fs;
+use tempfile::NamedTempFile;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use crate::utils::{average_line_length, base64_encode, format_timestamp, get_file_info, is_lock_file, is_minified, load_file_types, parse_path_range, parse_size, read_head_middle_tail, read_text_lossy, set_interpreter_overrides, LineRange};
+use super::with_interpreter_overrides;
+
+fn create_temp_file(content: &[u8]) -> NamedTempFile {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(content).unwrap();
+    file.flush().unwrap();
+    file
+}
+
+fn create_temp_file_with_extension(content: &[u8], extension: &str) -> (NamedTempFile, PathBuf) {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(content).unwrap();
+    file.flush().unwrap();
+    let new_path = file.path().with_extension(extension);
+    fs::rename(file.path(), &new_path).unwrap();
+    (file, new_path)
+}
+
+#[test]
+fn test_typescript_react_detection() {
+    let content = "import React from 'react';\nexport const App = () => <div>Hello</div>;";
+    let (_file, path) = create_temp_file_with_extension(content.as_bytes(), "tsx");
+    let info = get_file_info(&path).unwrap();
+    assert!(info.is_text);
+    assert_eq!(info.interpreter, Some("typescript-react".to_string()));
+}
+
+#[test]
+fn test_interpreter_override() {
+    let (_file, path) = create_temp_file_with_extension(b"x = linspace(0, 1, 10);\nplot(x);", "m");
+    let info = with_interpreter_overrides(".M=matlab", || get_file_info(&path).unwrap());
+    assert!(info.is_text);
+    assert_eq!(info.interpreter, Some("matlab".to_string()));
+    assert!(set_interpreter_overrides("m").is_err());
+}
+
+#[test]
+fn test_user_file_types_extend_the_defaults() {
+    let config = load_file_types(Some("[mime_overrides]\nm = \"matlab\"\nts = \"typescript-node\"\n")).unwrap();
+    assert_eq!(config.interpreter_for_extension("m"), Some("matlab"));
+    assert_eq!(config.interpreter_for_extension("ts"), Some("typescript-node"));
+    // Entries the file doesn't mention keep their defaults
+    assert_eq!(config.interpreter_for_extension("tsx"), Some("typescript-react"));
+
+    assert!(load_file_types(Some("[mime_overrides]\nm = ")).is_err());
+    assert_eq!(load_file_types(None).unwrap().interpreter_for_extension("m"), None);
+}
+
+#[test]
+fn test_shell_script_detection() {
+    let content = "#!/bin/bash\necho 'Hello'";
+    let file = create_temp_file(content.as_bytes());
+    let info = get_file_info(file.path()).unwrap();
+    assert!(info.is_text);
+    assert_eq!(info.interpreter, Some("bash".to_string()));
+}
+
+#[test]
+fn test_env_shebang_detection() {
+    let content = "#!/usr/bin/env python3\nprint('Hello')";
+    let file = create_temp_file(content.as_bytes());
+    let info = get_file_info(file.path()).unwrap();
+    assert!(info.is_text);
+    assert_eq!(info.interpreter, Some("python3".to_string()));
+}
+
+#[test]
+fn test_binary_file_detection() {
+    let content = vec![0x7F, 0x45, 0x4C, 0x46, 0x01, 0x01, 0x01, 0x00];
+    let file = create_temp_file(&content);
+    let info = get_file_info(file.path()).unwrap();
+    assert!(!info.is_text);
+    assert_eq!(info.interpreter, None);
+}
+
+#[test]
+fn test_dotfile_detection() {
+    let content = "ignore_pattern = \"*.log\"";
+    let file = create_temp_file(content.as_bytes());
+    let path = file.path().with_file_name(".gitignore");
+    fs::rename(file.path(), &path).unwrap();
+    let info = get_file_info(&path).unwrap();
+    assert!(info.is_text);
+    assert_eq!(info.interpreter, Some("config".to_string()));
+}
+
+#[test]
+fn test_common_programming_languages() {
+    let test_cases = vec![
+        ("fn main() {}", "rs", "rust"),
+        ("def hello(): pass", "py", "python3"),
+        ("package main", "go", "go"),
+        ("public class Test {}", "java", "java"),
+        ("console.log('test');", "js", "javascript"),
+    ];
+
+    for (content, ext, expected_interpreter) in test_cases {
+        let (_file, path) = create_temp_file_with_extension(content.as_bytes(), ext);
+        let info = get_file_info(&path).unwrap();
+        assert!(info.is_text, "File with extension {} should be text", ext);
+        assert_eq!(info.interpreter, Some(expected_interpreter.to_string()), 
+                  "File with extension {} should have interpreter {}", ext, expected_interpreter);
+    }
+}
+
+#[test]
+fn test_various_config_files() {
+    let test_cases = vec![
+        ("{\"key\": \"value\"}", "json", "json"),
+        ("key: value", "yaml", "yaml"),
+        ("key = \"value\"", "toml", "toml"),
+        ("[section]\nkey=value", "ini", "ini"),
+    ];
+
+    for (content, ext, expected_interpreter) in test_cases {
+        let (_file, path) = create_temp_file_with_extension(content.as_bytes(), ext);
+        let info = get_file_info(&path).unwrap();
+        assert!(info.is_text, "File with extension {} should be text", ext);
+        assert_eq!(info.interpreter, Some(expected_interpreter.to_string()));
+    }
+}
+
+#[test]
+fn test_web_technology_files() {
+    let test_cases = vec![
+        ("<html><body></body></html>", "html", "html"),
+        (".class { color: red; }", "css", "css"),
+        ("$color: red;", "scss", "scss"),
+        ("<template><div></div></template>", "vue", "vue"),
+    ];
+
+    for (content, ext, expected_interpreter) in test_cases {
+        let (_file, path) = create_temp_file_with_extension(content.as_bytes(), ext);
+        let info = get_file_info(&path).unwrap();
+        assert!(info.is_text, "File with extension {} should be text", ext);
+        assert_eq!(info.interpreter, Some(expected_interpreter.to_string()));
+    }
+}
+
+#[test]
+fn test_empty_file() {
+    let file = create_temp_file(&[]);
+    let info = get_file_info(file.path()).unwrap();
+    assert!(info.is_text);
+    assert_eq!(info.size, 0);
+}
+
+#[test]
+fn test_text_with_binary_content() {
+    let mut content = Vec::with_capacity(512);
+    content.extend_from_slice(b"Hello World!\n");
+    // Fill the rest with non-printable characters
+    for i in content.len()..512 {
+        content.push((i % 256) as u8);
+    }
+    let file = create_temp_file(&content);
+    let info = get_file_info(file.path()).unwrap();
+    assert!(!info.is_text);
+}
+
+#[cfg(feature = "magic")]
+#[test]
+fn test_utf16_text_detected_with_magic() {
+    let mut content = vec![0xFF, 0xFE];
+    for unit in "Hello from a UTF-16 encoded text file\n".encode_utf16() {
+        content.extend_from_slice(&unit.to_le_bytes());
+    }
+    let file = create_temp_file(&content);
+    let info = get_file_info(file.path()).unwrap();
+    assert!(info.is_text);
+}
+
+#[cfg(feature = "magic")]
+#[test]
+fn test_extensionless_json_detected_with_magic() {
+    let file = create_temp_file(b"{\n  \"name\": \"nexplorer\",\n  \"tags\": [\"cli\", \"rust\"]\n}\n");
+    let info = get_file_info(file.path()).unwrap();
+    assert!(info.is_text);
+    assert_eq!(info.interpreter.as_deref(), Some("json"));
+}
+
+#[test]
+fn test_extensionless_script_detection() {
+    let content = "#!/usr/bin/env node\nconsole.log('Hello');";
+    let file = create_temp_file(content.as_bytes());
+    let info = get_file_info(file.path()).unwrap();
+    assert!(info.is_text);
+    assert_eq!(info.interpreter, Some("node".to_string()));
+}
+
+#[test]
+fn test_timestamps_format_as_utc_dates() {
+    assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+    assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00 UTC");
+    assert_eq!(format_timestamp(1_791_980_130), "2026-10-14 12:15:30 UTC");
+}
+
+#[test]
+fn test_base64_pads_partial_groups() {
+    assert_eq!(base64_encode(b""), "");
+    assert_eq!(base64_encode(b"f"), "Zg==");
+    assert_eq!(base64_encode(b"fo"), "Zm8=");
+    assert_eq!(base64_encode(b"foo"), "Zm9v");
+    assert_eq!(base64_encode(&[0xFB, 0xFF]), "+/8=");
+}
+
+/// Counts the bytes read through it.
+struct CountingReader<R> {
+    inner: R,
+    read: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: std::io::Seek> std::io::Seek for CountingReader<R> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[test]
+fn test_large_files_are_sampled_within_the_window() {
+    let lines: Vec<String> = (0..100_000).map(|i| format!("log line {:06}", i)).collect();
+    let file = create_temp_file(lines.join("\n").as_bytes());
+    let size = file.as_file().metadata().unwrap().len();
+
+    let mut reader = CountingReader { inner: fs::File::open(file.path()).unwrap(), read: 0 };
+    let sample = read_head_middle_tail(&mut reader, size, 4096).unwrap();
+    assert!(reader.read <= 3 * 4096, "read {} bytes", reader.read);
+
+    assert!(sample.starts_with("[Partial view: the start, middle and end of a 1.53 MiB file]\n\nlog line 000000\n"), "{}", &sample[..100]);
+    assert!(sample.contains("\nlog line 050000\n"));
+    assert!(sample.ends_with("\nlog line 099999"));
+    // Slices are cut to whole lines
+    assert!(sample.lines().skip(1).all(|line| line.is_empty() || line == "[...]" || line.len() == 15), "{}", sample);
+}
+
+#[test]
+fn test_parse_size_suffixes() {
+    assert_eq!(parse_size("1M").unwrap(), 1024 * 1024);
+    assert_eq!(parse_size("512K").unwrap(), 512 * 1024);
+    assert_eq!(parse_size("2mb").unwrap(), 2 * 1024 * 1024);
+    assert_eq!(parse_size("4096").unwrap(), 4096);
+    assert!(parse_size("lots").is_err());
+    assert!(parse_size("").is_err());
+}
+
+#[test]
+fn test_path_line_ranges_are_parsed() {
+    assert_eq!(
+        parse_path_range(std::path::Path::new("src/main.rs:40-120")).unwrap(),
+        (PathBuf::from("src/main.rs"), Some(LineRange { start: 40, end: 120 }))
+    );
+    assert_eq!(
+        parse_path_range(std::path::Path::new("notes.txt:7-7")).unwrap(),
+        (PathBuf::from("notes.txt"), Some(LineRange { start: 7, end: 7 }))
+    );
+    for plain in ["src/main.rs", "-", "C:\\src\\main.rs", "host:path/file.rs"] {
+        assert_eq!(parse_path_range(std::path::Path::new(plain)).unwrap(), (PathBuf::from(plain), None));
+    }
+
+    // An existing file whose name looks like a range is taken as it is
+    let dir = tempfile::tempdir().unwrap();
+    let odd = dir.path().join("log:1-2");
+    fs::write(&odd, "entry").unwrap();
+    assert_eq!(parse_path_range(&odd).unwrap(), (odd.clone(), None));
+}
+
+#[test]
+fn test_malformed_line_ranges_are_rejected() {
+    for (arg, reason) in [
+        ("src/main.rs:40", "expected START-END"),
+        ("src/main.rs:40-", "expected START-END"),
+        ("src/main.rs:-120", "expected START-END"),
+        ("src/main.rs:4-x", "expected START-END"),
+        ("src/main.rs:0-10", "counted from 1"),
+        ("src/main.rs:120-40", "starts after it ends"),
+    ] {
+        let err = parse_path_range(std::path::Path::new(arg)).unwrap_err().to_string();
+        assert!(err.contains(arg) && err.contains(reason), "{}: {}", arg, err);
+    }
+}
+
+#[test]
+fn test_line_range_reads_lines() {
+    let content = "one\r\ntwo\nthree\nfour\n".as_bytes();
+    assert_eq!(LineRange { start: 1, end: 2 }.read(content).unwrap(), ("one\ntwo".to_string(), 2));
+    assert_eq!(LineRange { start: 3, end: 10 }.read(content).unwrap(), ("three\nfour".to_string(), 4));
+    assert!(LineRange { start: 5, end: 6 }.read(content).is_err());
+
+    // Reading stops at the end of the range
+    let mut reader = std::io::Cursor::new("one\ntwo\nthree\n");
+    LineRange { start: 1, end: 2 }.read(&mut reader).unwrap();
+    assert_eq!(reader.position(), 8);
+}
+
+#[test]
+fn test_latin1_text_is_read_lossily() {
+    let file = create_temp_file(b"// caf\xe9 au lait\nfn main() {}\n");
+    let text = read_text_lossy(file.path()).unwrap();
+    assert_eq!(text, "// caf\u{FFFD} au lait\nfn main() {}\n");
+}
+
+#[test]
+fn test_minified_and_lock_file_detection() {
+    assert_eq!(average_line_length("abcd\n\nab\n"), 3);
+    assert_eq!(average_line_length(""), 0);
+    assert!(!is_minified("fn main() {\n    println!(\"hi\");\n}\n"));
+    assert!(is_minified(&format!("{}\n", "var a=1;".repeat(100))));
+
+    assert!(is_lock_file(std::path::Path::new("Cargo.lock")));
+    assert!(is_lock_file(std::path::Path::new("web/package-lock.json")));
+    assert!(!is_lock_file(std::path::Path::new("package.json")));
+    assert!(!is_lock_file(std::path::Path::new("lock.rs")));
+}
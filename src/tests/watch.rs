@@ -0,0 +1,46 @@
+use std::path::{Path, PathBuf};
+use crate::file_explorer::FileExplorer;
+use crate::watch::reported_path;
+
+fn roots() -> Vec<(PathBuf, PathBuf)> {
+    vec![
+        (PathBuf::from("project"), PathBuf::from("/home/user/project")),
+        (PathBuf::from("notes.md"), PathBuf::from("/home/user/notes.md")),
+    ]
+}
+
+#[test]
+fn test_changed_paths_are_reported_under_the_roots_as_given() {
+    let explorer = FileExplorer::new(3);
+    let report = |changed: &str| reported_path(&explorer, &roots(), None, Path::new(changed));
+
+    assert_eq!(
+        report("/home/user/project/src/main.rs"),
+        Some((PathBuf::from("project"), PathBuf::from("project/src/main.rs")))
+    );
+    // A watched file is reported as its own root
+    assert_eq!(report("/home/user/notes.md"), Some((PathBuf::from("notes.md"), PathBuf::from("notes.md"))));
+    assert_eq!(report("/home/user/other/main.rs"), None);
+}
+
+#[test]
+fn test_changes_in_excluded_dirs_are_ignored() {
+    let mut explorer = FileExplorer::new(3);
+    explorer.set_excluded_dirs(vec!["target".to_string()]);
+    let report = |changed: &str| reported_path(&explorer, &roots(), None, Path::new(changed)).map(|(_, path)| path);
+
+    assert_eq!(report("/home/user/project/target/debug/build.log"), None);
+    assert_eq!(report("/home/user/project/src/target/mod.rs"), None);
+    // Only directories are excluded, not files of the same name
+    assert_eq!(report("/home/user/project/target"), Some(PathBuf::from("project/target")));
+}
+
+#[test]
+fn test_changes_in_the_cache_dir_are_ignored() {
+    let explorer = FileExplorer::new(3);
+    let cache_dir = Path::new("/home/user/project/.cache");
+    let report = |changed: &str| reported_path(&explorer, &roots(), Some(cache_dir), Path::new(changed)).map(|(_, path)| path);
+
+    assert_eq!(report("/home/user/project/.cache/0123abcd.json"), None);
+    assert_eq!(report("/home/user/project/.cached.rs"), Some(PathBuf::from("project/.cached.rs")));
+}
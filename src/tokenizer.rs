@@ -0,0 +1,33 @@
+/// Rough number of English words per token, used to phrase token budgets
+/// as word counts in prompts.
+const WORDS_PER_TOKEN: f32 = 0.75;
+
+/// Number of tokens `text` encodes to for `model`. Models tiktoken doesn't
+/// know (Claude, local models) are counted with `cl100k_base`, which is
+/// close enough for budgeting.
+pub fn count_tokens(model: &str, text: &str) -> usize {
+    let bpe = tiktoken_rs::bpe_for_model(model)
+        .unwrap_or_else(|_| tiktoken_rs::cl100k_base_singleton());
+    bpe.encode_ordinary(text).len()
+}
+
+/// Number of words in `text`. Japanese doesn't separate words with
+/// spaces, so there every non-whitespace character counts as one.
+pub fn count_words(text: &str, language: &str) -> usize {
+    if language.eq_ignore_ascii_case("japanese") {
+        text.chars().filter(|c| !c.is_whitespace()).count()
+    } else {
+        text.split_whitespace().count()
+    }
+}
+
+/// Whether `words` is more than 50% away from `target`.
+pub fn is_far_from_target(words: usize, target: u32) -> bool {
+    let target = target as usize;
+    words * 2 < target || words * 2 > target * 3
+}
+
+/// The word count to ask for so a reply fits in `tokens`.
+pub fn tokens_to_words(tokens: u32) -> u32 {
+    (tokens as f32 * WORDS_PER_TOKEN).round() as u32
+}
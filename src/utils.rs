@@ -1,14 +1,15 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs;
 use anyhow::Result;
 use humansize::{format_size as humansize_format, BINARY};
 use std::collections::HashMap;
+use std::sync::RwLock;
 use once_cell::sync::Lazy;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
 use serde::Deserialize;
-use log::{debug, trace};
+use log::{debug, trace, warn};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FileInfo {
     pub size: u64,
     pub is_text: bool,
@@ -16,14 +17,22 @@ pub struct FileInfo {
 }
 
 #[derive(Debug, Deserialize)]
-struct FileTypeConfig {
-    file_type_multipliers: HashMap<String, f32>,
+pub(crate) struct FileTypeConfig {
     mime_overrides: HashMap<String, String>,
+    #[serde(default)]
+    mime_types: MimeTypes,
     known_dotfiles: KnownDotfiles,
     binary_signatures: HashMap<String, Vec<u8>>,
     text_detection: TextDetection,
 }
 
+#[derive(Debug, Default, Deserialize)]
+struct MimeTypes {
+    /// Non `text/*` MIME types that still hold text
+    #[serde(default)]
+    textual: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct KnownDotfiles {
     patterns: Vec<String>,
@@ -35,17 +44,231 @@ struct TextDetection {
     sample_size: usize,
 }
 
+impl FileTypeConfig {
+    pub(crate) fn interpreter_for_extension(&self, extension: &str) -> Option<&str> {
+        self.mime_overrides.get(extension).map(String::as_str)
+    }
+}
+
+const DEFAULT_FILE_TYPES: &str = include_str!("../config/filetypes.toml");
+
+// The built-in file types, extended by ~/.config/nexplorer/filetypes.toml
 static CONFIG: Lazy<FileTypeConfig> = Lazy::new(|| {
-    let config_path = Path::new("config/filetypes.toml");
-    let config_str = fs::read_to_string(config_path)
-        .unwrap_or_else(|_| include_str!("../config/filetypes.toml").to_string());
-    toml::from_str(&config_str).expect("Failed to parse file type configuration")
+    let user_path = config_dir().map(|dir| dir.join("filetypes.toml"));
+    let user = user_path.as_deref().and_then(|path| fs::read_to_string(path).ok());
+    load_file_types(user.as_deref()).unwrap_or_else(|e| {
+        warn!("Ignoring {}: {:#}", user_path.unwrap_or_default().display(), e);
+        load_file_types(None).expect("Failed to parse file type configuration")
+    })
 });
 
+/// The built-in file types with a user file's on top. Tables are merged
+/// key by key, so a file only needs the entries it adds or changes; lists
+/// of names such as `known_dotfiles.patterns` are extended and any other
+/// value is replaced.
+pub(crate) fn load_file_types(user: Option<&str>) -> Result<FileTypeConfig> {
+    let mut config: toml::Value = toml::from_str(DEFAULT_FILE_TYPES)?;
+    if let Some(user) = user {
+        merge_toml(&mut config, toml::from_str(user)?);
+    }
+    Ok(config.try_into()?)
+}
+
+fn merge_toml(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_toml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (toml::Value::Array(base), toml::Value::Array(overlay))
+            if overlay.iter().chain(base.iter()).all(toml::Value::is_str) =>
+        {
+            for value in overlay {
+                if !base.contains(&value) {
+                    base.push(value);
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// Extension -> interpreter pairs from --interpreter-override
+static INTERPRETER_OVERRIDES: Lazy<RwLock<HashMap<String, String>>> = Lazy::new(Default::default);
+
+/// User configuration directory, `~/.config/nexplorer`.
+pub fn config_dir() -> Option<PathBuf> {
+    directories::BaseDirs::new().map(|dirs| dirs.home_dir().join(".config").join("nexplorer"))
+}
+
+/// Parse a byte count such as `1048576`, `512K`, `2M` or `1G` (binary
+/// multiples, case-insensitive, optional trailing `B`).
+pub fn parse_size(value: &str) -> Result<u64> {
+    let trimmed = value.trim();
+    let upper = trimmed.to_ascii_uppercase();
+    let number = upper.strip_suffix('B').unwrap_or(&upper);
+    let (digits, multiplier) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 1024),
+        Some('M') => (&number[..number.len() - 1], 1024 * 1024),
+        Some('G') => (&number[..number.len() - 1], 1024 * 1024 * 1024),
+        _ => (number, 1),
+    };
+    let count: u64 = digits.trim().parse()
+        .map_err(|_| anyhow::anyhow!("Invalid size '{}': expected a byte count like 500K or 2M", trimmed))?;
+    count.checked_mul(multiplier)
+        .ok_or_else(|| anyhow::anyhow!("Size '{}' is too large", trimmed))
+}
+
+/// Lines `start` to `end` of a file, counted from 1 and inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LineRange {
+    /// The lines of `reader` in the range and the last of them, which is
+    /// before `end` when there are fewer lines. Nothing past `end` is read,
+    /// and invalid UTF-8 is replaced.
+    pub fn read<R: BufRead>(&self, mut reader: R) -> Result<(String, usize)> {
+        let mut lines = Vec::new();
+        let mut line = Vec::new();
+        let mut count = 0;
+        while count < self.end {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            count += 1;
+            if count >= self.start {
+                let text = line.strip_suffix(b"\n").unwrap_or(&line);
+                let text = text.strip_suffix(b"\r").unwrap_or(text);
+                lines.push(String::from_utf8_lossy(text).into_owned());
+            }
+        }
+        if self.start > count {
+            anyhow::bail!("Line range {}-{} starts past the end ({} lines)", self.start, self.end, count);
+        }
+        Ok((lines.join("\n"), count))
+    }
+}
+
+/// Split a path argument of the form `path:start-end`, such as
+/// `src/main.rs:40-120`, into the path and its line range. Arguments
+/// without a numeric suffix, and existing files whose name merely looks
+/// like one, are plain paths.
+pub fn parse_path_range(arg: &Path) -> Result<(PathBuf, Option<LineRange>)> {
+    let plain = || Ok((arg.to_path_buf(), None));
+    let Some((path, range)) = arg.to_str().and_then(|arg| arg.rsplit_once(':')) else {
+        return plain();
+    };
+    if arg.exists() || path.is_empty() || !range.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        return plain();
+    }
+
+    let invalid = |reason: &str| anyhow::anyhow!("Invalid line range '{}' in '{}': {}", range, arg.display(), reason);
+    let (start, end) = range.split_once('-').ok_or_else(|| invalid("expected START-END, e.g. 40-120"))?;
+    let parse = |number: &str| number.parse::<usize>().map_err(|_| invalid("expected START-END, e.g. 40-120"));
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start == 0 {
+        return Err(invalid("lines are counted from 1"));
+    }
+    if start > end {
+        return Err(invalid("the range starts after it ends"));
+    }
+    Ok((PathBuf::from(path), Some(LineRange { start, end })))
+}
+
 pub fn format_size(size: u64) -> String {
     humansize_format(size, BINARY)
 }
 
+/// Seconds since the epoch as a UTC date and time, `YYYY-MM-DD HH:MM:SS UTC`.
+pub fn format_timestamp(secs: u64) -> String {
+    let (days, time) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01, counting in 400-year eras
+    // of March-based years (Howard Hinnant's days_from_civil inverted)
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year, month, day, time / 3_600, time % 3_600 / 60, time % 60
+    )
+}
+
+/// Read a text file, replacing invalid UTF-8 (e.g. Latin-1 bytes) rather
+/// than failing.
+pub fn read_text_lossy(path: &Path) -> Result<String> {
+    let bytes = fs::read(path)?;
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(err) => {
+            warn!("{} is not valid UTF-8; invalid bytes were replaced", path.display());
+            Ok(String::from_utf8_lossy(err.as_bytes()).into_owned())
+        }
+    }
+}
+
+/// The first, middle and last `window` bytes of `size` bytes of text,
+/// reading nothing else. Each slice is cut to whole lines where it can be
+/// and the gaps are marked, so the model knows it sees part of the file.
+pub fn read_head_middle_tail<R: Read + Seek>(reader: &mut R, size: u64, window: u64) -> Result<String> {
+    let starts = [0, (size / 2).saturating_sub(window / 2), size.saturating_sub(window)];
+    let mut slices = Vec::new();
+    for (index, start) in starts.into_iter().enumerate() {
+        reader.seek(SeekFrom::Start(start))?;
+        let mut bytes = Vec::new();
+        reader.by_ref().take(window).read_to_end(&mut bytes)?;
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        // Slices after the head start mid-line, and those before the tail end mid-line
+        let text = match index {
+            0 => text.rsplit_once('\n').map_or(text.as_str(), |(lines, _)| lines),
+            1 => text.split_once('\n').map_or(text.as_str(), |(_, rest)| rest)
+                .rsplit_once('\n').map_or(text.as_str(), |(lines, _)| lines),
+            _ => text.split_once('\n').map_or(text.as_str(), |(_, rest)| rest),
+        };
+        slices.push(text.to_string());
+    }
+    Ok(format!(
+        "[Partial view: the start, middle and end of a {} file]\n\n{}\n\n[...]\n\n{}\n\n[...]\n\n{}",
+        format_size(size), slices[0], slices[1], slices[2]
+    ))
+}
+
+/// Average line length above which content is treated as minified
+const MINIFIED_LINE_LENGTH: usize = 500;
+
+/// Average number of characters per non-empty line.
+pub fn average_line_length(content: &str) -> usize {
+    let (lines, chars) = content.lines()
+        .filter(|line| !line.trim().is_empty())
+        .fold((0, 0), |(lines, chars), line| (lines + 1, chars + line.chars().count()));
+    chars.checked_div(lines).unwrap_or(0)
+}
+
+pub fn is_minified(content: &str) -> bool {
+    average_line_length(content) > MINIFIED_LINE_LENGTH
+}
+
+/// Dependency lock files such as `Cargo.lock` or `package-lock.json`.
+pub fn is_lock_file(path: &Path) -> bool {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    name.ends_with(".lock") || name == "package-lock.json"
+}
+
 fn normalize_shebang_line(line: &str) -> String {
     line.trim()
         .trim_start_matches("#!")
@@ -53,11 +276,106 @@ fn normalize_shebang_line(line: &str) -> String {
         .to_lowercase()
 }
 
+/// Parse `ext=value` pairs separated by commas, as taken by `flag`.
+/// Extensions are matched case-insensitively and may be written with a
+/// leading dot.
+pub(crate) fn parse_extension_map(mapping: &str, flag: &str) -> Result<HashMap<String, String>> {
+    let mut map = HashMap::new();
+    for pair in mapping.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let (ext, value) = pair.split_once('=')
+            .map(|(ext, value)| (ext.trim().trim_start_matches('.'), value.trim()))
+            .filter(|(ext, value)| !ext.is_empty() && !value.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Invalid {} entry '{}', expected ext=value", flag, pair))?;
+        map.insert(ext.to_lowercase(), value.to_string());
+    }
+    Ok(map)
+}
+
+/// Replace the interpreter overrides with the `ext=interpreter` pairs in
+/// `mapping`. They take precedence over the extensions in
+/// `config/filetypes.toml`.
+pub fn set_interpreter_overrides(mapping: &str) -> Result<()> {
+    let overrides = parse_extension_map(mapping, "--interpreter-override")?;
+    *INTERPRETER_OVERRIDES.write().unwrap() = overrides;
+    Ok(())
+}
+
+/// The interpreter forced for `extension` with --interpreter-override.
+pub(crate) fn interpreter_override(extension: &str) -> Option<String> {
+    INTERPRETER_OVERRIDES.read().unwrap().get(&extension.to_lowercase()).cloned()
+}
+
+/// The extensions the file type configuration maps to `interpreter`,
+/// sorted.
+pub(crate) fn extensions_for_interpreter(interpreter: &str) -> Vec<String> {
+    let mut extensions: Vec<String> = CONFIG.mime_overrides
+        .iter()
+        // Skipping the MIME types, which are keyed by their full name
+        .filter(|(extension, known)| known.as_str() == interpreter && !extension.contains('/'))
+        .map(|(extension, _)| extension.clone())
+        .collect();
+    extensions.sort();
+    extensions
+}
+
 fn get_interpreter_for_extension(extension: &str) -> Option<String> {
-    CONFIG.mime_overrides.get(extension)
+    CONFIG.interpreter_for_extension(extension)
+        .map(|s| s.to_string())
+}
+
+fn get_interpreter_for_mime(mime_type: &str) -> Option<String> {
+    CONFIG.mime_overrides.get(mime_type)
         .map(|s| s.to_string())
 }
 
+fn is_textual_mime(mime_type: &str) -> bool {
+    mime_type.starts_with("text/") || CONFIG.mime_types.textual.iter().any(|t| t == mime_type)
+}
+
+#[cfg(feature = "magic")]
+fn detect_mime_type(path: &Path) -> Option<String> {
+    use crate::magic::Magic;
+
+    // Loading the magic database is expensive, so keep one cookie per thread
+    thread_local! {
+        static MAGIC: Option<Magic> = Magic::new();
+    }
+    MAGIC.with(|magic| magic.as_ref().and_then(|magic| magic.get_mime_type(path)))
+}
+
+#[cfg(not(feature = "magic"))]
+fn detect_mime_type(_path: &Path) -> Option<String> {
+    None
+}
+
+/// Image types the vision APIs accept
+const IMAGE_MIME_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// The MIME type of an image a model could be shown, as magic detects it
+/// or else going by the extension.
+pub(crate) fn image_mime_type(path: &Path) -> Option<&'static str> {
+    let mime_type = detect_mime_type(path)
+        .or_else(|| mime_guess::from_path(path).first().map(|mime| mime.essence_str().to_string()))?;
+    IMAGE_MIME_TYPES.iter().copied().find(|image| *image == mime_type)
+}
+
+/// Standard base64 with padding.
+pub(crate) fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let triple = chunk.iter().enumerate().fold(0u32, |triple, (i, byte)| triple | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(triple >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
 fn has_shell_script_shebang(path: &Path) -> Option<String> {
     if let Ok(file) = fs::File::open(path) {
         if let Some(Ok(first_line)) = BufReader::new(file).lines().next() {
@@ -131,12 +449,30 @@ fn is_known_dotfile(path: &Path) -> bool {
     false
 }
 
+/// The interpreter, or the lowercase extension when none was detected.
+pub(crate) fn file_kind(path: &Path, info: &FileInfo) -> String {
+    info.interpreter.clone().unwrap_or_else(|| {
+        path.extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_else(|| "(none)".to_string())
+    })
+}
+
 pub fn get_file_info(path: &Path) -> Result<FileInfo> {
     debug!("Analyzing file: {}", path.display());
     let metadata = fs::metadata(path)?;
+    let mime_type = detect_mime_type(path);
+    if let Some(mime_type) = &mime_type {
+        debug!("MIME type: {}", mime_type);
+    }
     
-    // Perform binary detection first and cache the result
-    let is_binary = is_binary_file(path);
+    // Perform binary detection first and cache the result. A specific MIME
+    // type is trusted; unknown data falls back to content analysis.
+    let is_binary = match mime_type.as_deref() {
+        Some(mime_type) if is_textual_mime(mime_type) => false,
+        Some(mime_type) if mime_type != "application/octet-stream" => true,
+        _ => is_binary_file(path),
+    };
     if is_binary {
         debug!("File type: binary");
         return Ok(FileInfo {
@@ -171,7 +507,9 @@ pub fn get_file_info(path: &Path) -> Result<FileInfo> {
         .and_then(|ext| ext.to_str())
         .map(|s| s.to_lowercase())
     {
-        if let Some(interpreter) = get_interpreter_for_extension(&extension) {
+        if let Some(interpreter) = interpreter_override(&extension)
+            .or_else(|| get_interpreter_for_extension(&extension))
+        {
             debug!("File type: {}", interpreter);
             return Ok(FileInfo {
                 size: metadata.len(),
@@ -181,6 +519,15 @@ pub fn get_file_info(path: &Path) -> Result<FileInfo> {
         }
     }
     
+    if let Some(interpreter) = mime_type.as_deref().and_then(get_interpreter_for_mime) {
+        debug!("File type: {}", interpreter);
+        return Ok(FileInfo {
+            size: metadata.len(),
+            is_text: true,
+            interpreter: Some(interpreter),
+        });
+    }
+    
     debug!("File type: text");
     Ok(FileInfo {
         size: metadata.len(),
@@ -0,0 +1,107 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use notify::{EventKind, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use crate::file_explorer::FileExplorer;
+
+// Events for the same files closer together than this are handled once
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watch `roots` after the initial pass and summarize each file again
+/// when it changes, until interrupted. Summaries are cached by content, so
+/// a changed file misses the cache on its own.
+pub async fn watch(explorer: &mut FileExplorer, roots: &[PathBuf]) -> Result<()> {
+    let (sender, mut events) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        match event {
+            Ok(event) => {
+                let _ = sender.send(event);
+            }
+            Err(e) => warn!("Watch error: {}", e),
+        }
+    })?;
+    // Events carry canonical paths, which are mapped back onto the roots
+    // as given so that files are reported the way the initial pass did
+    let mut canonical_roots = Vec::new();
+    for root in roots {
+        watcher.watch(root, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to watch {}", root.display()))?;
+        canonical_roots.push((root.clone(), fs::canonicalize(root)?));
+    }
+
+    // Files reported so far, so only their removal is announced
+    let mut known: HashSet<PathBuf> = roots.iter()
+        .flat_map(|root| explorer.files_under(root))
+        .collect();
+    let cache_dir = explorer.summarizer().and_then(|summarizer| fs::canonicalize(summarizer.cache_dir()).ok());
+
+    eprintln!("\nWatching {} for changes (Ctrl-C to stop)...",
+        roots.iter().map(|root| root.display().to_string()).collect::<Vec<_>>().join(", "));
+
+    loop {
+        // Ctrl-C here has no summaries to wait for, so it ends the watch
+        let event = tokio::select! {
+            event = events.recv() => event,
+            _ = tokio::signal::ctrl_c() => None,
+        };
+        let Some(event) = event else {
+            break;
+        };
+        let mut changed = BTreeSet::new();
+        collect(&mut changed, event);
+        // Editors often write a file in several steps; wait for them to
+        // settle before summarizing
+        while let Ok(Some(event)) = tokio::time::timeout(DEBOUNCE, events.recv()).await {
+            collect(&mut changed, event);
+        }
+
+        for changed_path in changed {
+            let Some((root, path)) = reported_path(explorer, &canonical_roots, cache_dir.as_deref(), &changed_path) else {
+                continue;
+            };
+
+            if path.is_file() {
+                debug!("Changed: {}", path.display());
+                explorer.explore_under(&root, &path).await?;
+                known.insert(path);
+                if explorer.is_interrupted() {
+                    return Ok(());
+                }
+            } else if !path.exists() && known.remove(&path) {
+                eprintln!("Removed: {}", path.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The root a change to `changed`, a canonical path from an event, is in
+/// and the path it is reported under: mapped back onto that root, as
+/// `roots` pairs each root given with its canonical path. Changes outside
+/// the roots, in an excluded directory or in the cache dir are ignored.
+pub(crate) fn reported_path(
+    explorer: &FileExplorer,
+    roots: &[(PathBuf, PathBuf)],
+    cache_dir: Option<&Path>,
+    changed: &Path,
+) -> Option<(PathBuf, PathBuf)> {
+    if cache_dir.is_some_and(|dir| changed.starts_with(dir)) {
+        return None;
+    }
+    roots.iter().find_map(|(root, canonical)| {
+        let relative = changed.strip_prefix(canonical).ok()?;
+        // A watched file is its own root
+        let path = if relative.as_os_str().is_empty() { root.clone() } else { root.join(relative) };
+        (!explorer.is_in_excluded_dir(relative)).then(|| (root.clone(), path))
+    })
+}
+
+fn collect(changed: &mut BTreeSet<PathBuf>, event: notify::Event) {
+    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+        changed.extend(event.paths);
+    }
+}